@@ -0,0 +1,64 @@
+//! Matching cost of the mocks as the registry grows, run with `cargo bench`.
+//! The time per request should stay flat as the number of mocks grows since
+//! only the mocks indexed under the method and the path are evaluated.
+
+use service::utils::{APIs, MockRequest, ResponseBody, API};
+
+const ITERATIONS: u32 = 20_000;
+
+fn mocks(count: usize) -> APIs {
+    let apis = (0..count)
+        .map(|i| API {
+            method: ["GET", "POST", "PUT", "DELETE"][i % 4].to_string(),
+            path: match i % 3 {
+                0 => format!("/v1/resource{i}/items"),
+                1 => format!("/v1/resource{i}/{{id}}"),
+                _ => format!("/v1/resource{i}/{{id:[0-9]+}}/details"),
+            },
+            body: Some(ResponseBody::Json(serde_json::json!({"id": i}))),
+            ..Default::default()
+        })
+        .collect();
+    APIs::new(apis).expect("mocks compile")
+}
+
+fn request(method: &str, path: &str) -> MockRequest {
+    MockRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        ..Default::default()
+    }
+}
+
+fn bench(runtime: &tokio::runtime::Runtime, name: &str, apis: &APIs, request: &MockRequest) {
+    let started = std::time::Instant::now();
+    for _ in 0..ITERATIONS {
+        let response = runtime.block_on(apis.response(std::hint::black_box(request)));
+        std::hint::black_box(response.ok());
+    }
+    let per_request = started.elapsed() / ITERATIONS;
+    println!(
+        "{name:<8} {:>8} mocks {:>10?}/request",
+        apis.len(),
+        per_request
+    );
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("runtime builds");
+    for count in [10, 100, 1_000, 10_000] {
+        let apis = mocks(count);
+        // the mocks matched last in a linear scan
+        let last = count - 1;
+        let path = match last % 3 {
+            0 => format!("/v1/resource{last}/items"),
+            1 => format!("/v1/resource{last}/42"),
+            _ => format!("/v1/resource{last}/42/details"),
+        };
+        let method = ["GET", "POST", "PUT", "DELETE"][last % 4];
+        bench(&runtime, "match", &apis, &request(method, path.as_str()));
+        bench(&runtime, "miss", &apis, &request("GET", "/v2/unknown"));
+    }
+}
@@ -1,3 +1,4 @@
 // pub mod pg;
 // pub mod redis;
 // pub mod schema;
+pub mod sqlite;
@@ -0,0 +1,260 @@
+pub type Error = rusqlite::Error;
+
+static SQLITE_CONN: std::sync::OnceLock<std::sync::Mutex<rusqlite::Connection>> =
+    std::sync::OnceLock::new();
+
+const MIGRATIONS: &str = "
+CREATE TABLE IF NOT EXISTS mocks (
+    id TEXT PRIMARY KEY,
+    definition TEXT NOT NULL,
+    tags TEXT NOT NULL DEFAULT '[]',
+    position INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS mock_versions (
+    id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    definition TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (id, version)
+);
+";
+
+const NOW: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+/// Mock definition as stored, `definition` is the json of the mock
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Mock {
+    pub id: String,
+    pub definition: String,
+    pub tags: Vec<String>,
+    pub version: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A version of a mock, `definition` is `None` for the version deleting the mock
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MockVersion {
+    pub id: String,
+    pub version: i64,
+    pub definition: Option<String>,
+    pub created_at: String,
+}
+
+pub fn init_sqlite(path: &str) -> Result<(), rusqlite::Error> {
+    let conn = rusqlite::Connection::open(path)?;
+    conn.execute_batch(MIGRATIONS)?;
+    SQLITE_CONN
+        .set(std::sync::Mutex::new(conn))
+        .expect("Error in setting the SQLITE_CONN for sqlite");
+    Ok(())
+}
+
+pub fn with_conn<T>(
+    f: impl FnOnce(&mut rusqlite::Connection) -> Result<T, rusqlite::Error>,
+) -> Result<T, rusqlite::Error> {
+    let mut conn = SQLITE_CONN
+        .get()
+        .expect("sqlite is not initialized")
+        .lock()
+        .expect("sqlite lock poisoned");
+    f(&mut conn)
+}
+
+fn tags(tags: String) -> Vec<String> {
+    serde_json::from_str(tags.as_str()).unwrap_or_default()
+}
+
+pub fn mocks(conn: &rusqlite::Connection) -> Result<Vec<Mock>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, definition, tags, version, created_at, updated_at FROM mocks ORDER BY position",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Mock {
+            id: row.get(0)?,
+            definition: row.get(1)?,
+            tags: tags(row.get(2)?),
+            version: row.get(3)?,
+            created_at: row.get(4)?,
+            updated_at: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Inserts or updates the mock, a new version is recorded only if the
+/// definition has changed
+pub fn upsert(
+    conn: &rusqlite::Connection,
+    id: &str,
+    definition: &str,
+    tags: &[String],
+    position: usize,
+) -> Result<(), rusqlite::Error> {
+    use rusqlite::OptionalExtension;
+    let tags = serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string());
+    let current: Option<(String, i64)> = conn
+        .query_row(
+            "SELECT definition, version FROM mocks WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let version = match current {
+        Some((current, _)) if current == definition => {
+            conn.execute(
+                "UPDATE mocks SET position = ?2 WHERE id = ?1",
+                rusqlite::params![id, position],
+            )?;
+            return Ok(());
+        }
+        Some((_, version)) => {
+            let version = version + 1;
+            conn.execute(
+                format!(
+                    "UPDATE mocks SET definition = ?2, tags = ?3, position = ?4, version = ?5,
+                     updated_at = {NOW} WHERE id = ?1"
+                )
+                .as_str(),
+                rusqlite::params![id, definition, tags, position, version],
+            )?;
+            version
+        }
+        None => {
+            let version = last_version(conn, id)? + 1;
+            conn.execute(
+                format!(
+                    "INSERT INTO mocks (id, definition, tags, position, version, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, {NOW}, {NOW})"
+                )
+                .as_str(),
+                rusqlite::params![id, definition, tags, position, version],
+            )?;
+            version
+        }
+    };
+    conn.execute(
+        format!("INSERT INTO mock_versions (id, version, definition, created_at) VALUES (?1, ?2, ?3, {NOW})")
+            .as_str(),
+        rusqlite::params![id, version, definition],
+    )?;
+    Ok(())
+}
+
+fn last_version(conn: &rusqlite::Connection, id: &str) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM mock_versions WHERE id = ?1",
+        [id],
+        |row| row.get(0),
+    )
+}
+
+/// Deletes the mock, history is kept with a version marking the deletion
+pub fn delete(conn: &rusqlite::Connection, id: &str) -> Result<(), rusqlite::Error> {
+    if conn.execute("DELETE FROM mocks WHERE id = ?1", [id])? == 0 {
+        return Ok(());
+    }
+    let version = last_version(conn, id)? + 1;
+    conn.execute(
+        format!("INSERT INTO mock_versions (id, version, definition, created_at) VALUES (?1, ?2, NULL, {NOW})")
+            .as_str(),
+        rusqlite::params![id, version],
+    )?;
+    Ok(())
+}
+
+pub fn history(conn: &rusqlite::Connection, id: &str) -> Result<Vec<MockVersion>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, version, definition, created_at FROM mock_versions WHERE id = ?1 ORDER BY version",
+    )?;
+    let rows = stmt.query_map([id], |row| {
+        Ok(MockVersion {
+            id: row.get(0)?,
+            version: row.get(1)?,
+            definition: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> rusqlite::Connection {
+        let conn = rusqlite::Connection::open_in_memory().expect("in memory database");
+        conn.execute_batch(MIGRATIONS).expect("migrated");
+        conn
+    }
+
+    fn versions(conn: &rusqlite::Connection, id: &str) -> Vec<(i64, Option<String>)> {
+        history(conn, id)
+            .expect("history")
+            .into_iter()
+            .map(|v| (v.version, v.definition))
+            .collect()
+    }
+
+    #[test]
+    fn versions_are_recorded_only_for_changes() {
+        let conn = conn();
+        upsert(&conn, "a", "{\"v\":1}", &[], 0).expect("inserted");
+        upsert(&conn, "a", "{\"v\":1}", &[], 1).expect("unchanged");
+        upsert(&conn, "a", "{\"v\":2}", &["x".to_string()], 1).expect("updated");
+        let stored = mocks(&conn).expect("mocks");
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].version, 2);
+        assert_eq!(stored[0].definition, "{\"v\":2}");
+        assert_eq!(stored[0].tags, ["x"]);
+        assert_eq!(
+            versions(&conn, "a"),
+            [
+                (1, Some("{\"v\":1}".to_string())),
+                (2, Some("{\"v\":2}".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn deletions_are_kept_in_the_history() {
+        let conn = conn();
+        upsert(&conn, "a", "{\"v\":1}", &[], 0).expect("inserted");
+        delete(&conn, "a").expect("deleted");
+        // deleting a missing mock records nothing
+        delete(&conn, "a").expect("deleted");
+        assert!(mocks(&conn).expect("mocks").is_empty());
+        upsert(&conn, "a", "{\"v\":1}", &[], 0).expect("inserted again");
+        assert_eq!(mocks(&conn).expect("mocks")[0].version, 3);
+        assert_eq!(
+            versions(&conn, "a"),
+            [
+                (1, Some("{\"v\":1}".to_string())),
+                (2, None),
+                (3, Some("{\"v\":1}".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn mocks_are_listed_by_position() {
+        let conn = conn();
+        upsert(&conn, "a", "{}", &[], 1).expect("inserted");
+        upsert(&conn, "b", "{}", &[], 0).expect("inserted");
+        let ids: Vec<String> = mocks(&conn)
+            .expect("mocks")
+            .into_iter()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(ids, ["b", "a"]);
+        // moving a mock does not make a new version
+        upsert(&conn, "a", "{}", &[], 0).expect("moved");
+        upsert(&conn, "b", "{}", &[], 1).expect("moved");
+        let stored = mocks(&conn).expect("mocks");
+        assert_eq!(stored[0].id, "a");
+        assert_eq!(stored[0].version, 1);
+    }
+}
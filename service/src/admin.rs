@@ -0,0 +1,537 @@
+use crate::utils::API;
+
+pub const PREFIX: &str = "/__admin/";
+
+/// Ids the `mappings/{id}` routes could not be told apart from the other
+/// `mappings/` routes with
+const RESERVED_IDS: [&str; 4] = ["reset", "hits", "export", "import"];
+
+/// Whether the path is served by the admin apis, the bare `/__admin` included
+pub fn is_admin(path: &str) -> bool {
+    path.starts_with(PREFIX) || path == PREFIX.trim_end_matches('/')
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AdminError {
+    #[error("NotFound: {0}")]
+    NotFound(String),
+    #[error("Conflict: mock with id {0} already exists")]
+    Conflict(String),
+    #[error("BodyReadError: {0}")]
+    BodyRead(#[from] hyper::Error),
+    #[error("InvalidJson: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("CompileError: {0}")]
+    Compile(#[from] crate::utils::CompileError),
+    #[error("LoadError: {0}")]
+    Load(#[from] crate::registry::LoadError),
+    #[error("StoreError: {0}")]
+    Store(#[from] crate::store::StoreError),
+    #[error("CriteriaError: {0}")]
+    Criteria(#[from] crate::journal::CriteriaError),
+    #[error("OpenAPIError: {0}")]
+    OpenAPI(#[from] crate::openapi::OpenAPIError),
+    #[error("PluginError: {0}")]
+    Plugin(#[from] crate::plugin::PluginError),
+    #[error("StoreDisabled: mocks are not kept in the sqlite store")]
+    StoreDisabled,
+    #[error("ReservedId: {0} is the name of an admin route")]
+    ReservedId(String),
+}
+
+impl AdminError {
+    fn status(&self) -> hyper::StatusCode {
+        match self {
+            AdminError::NotFound(_) => hyper::StatusCode::NOT_FOUND,
+            AdminError::Conflict(_) => hyper::StatusCode::CONFLICT,
+            AdminError::BodyRead(_)
+            | AdminError::InvalidJson(_)
+            | AdminError::Compile(_)
+            | AdminError::Criteria(_)
+            | AdminError::OpenAPI(_)
+            | AdminError::Plugin(_) => hyper::StatusCode::BAD_REQUEST,
+            AdminError::StoreDisabled | AdminError::ReservedId(_) => hyper::StatusCode::BAD_REQUEST,
+            AdminError::Load(_) | AdminError::Store(_) => hyper::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+async fn from_body<T: serde::de::DeserializeOwned>(b: hyper::Body) -> Result<T, AdminError> {
+    let b = hyper::body::to_bytes(b).await?;
+    Ok(serde_json::from_slice(b.as_ref())?)
+}
+
+fn json<T: serde::Serialize>(
+    value: &T,
+    status: hyper::StatusCode,
+) -> Result<hyper::Response<hyper::Body>, AdminError> {
+    Ok(crate::router::response(
+        serde_json::to_string(value)?,
+        status,
+    ))
+}
+
+/// Gives the mock an id if it does not have one, refusing the reserved ones
+fn with_id(api: API) -> Result<API, AdminError> {
+    let api = api.with_id();
+    match api.id.as_deref() {
+        Some(id) if RESERVED_IDS.contains(&id) => Err(AdminError::ReservedId(id.to_string())),
+        _ => Ok(api),
+    }
+}
+
+fn find(apis: &[API], id: &str) -> Result<usize, AdminError> {
+    apis.iter()
+        .position(|api| api.id.as_deref() == Some(id))
+        .ok_or_else(|| AdminError::NotFound(id.to_string()))
+}
+
+/// Adds the imported mocks, replacing the ones with the same method, path and
+/// matchers while keeping their ids
+fn merge(imported: Vec<API>) -> Result<usize, AdminError> {
+    let count = imported.len();
+    crate::registry::update(|apis| {
+        for mut api in imported {
+            let key = crate::recorder::key(&api);
+            match apis.iter().position(|a| crate::recorder::key(a) == key) {
+                Some(index) => {
+                    api.id = apis[index].id.clone();
+                    apis[index] = api;
+                }
+                None => apis.push(api),
+            }
+        }
+        Ok::<_, AdminError>(())
+    })?;
+    Ok(count)
+}
+
+#[derive(serde::Deserialize)]
+struct ScenarioUpdate {
+    state: String,
+}
+
+fn find_scenario(name: &str) -> Result<(), AdminError> {
+    crate::scenario::list(&crate::registry::current().apis())
+        .iter()
+        .find(|scenario| scenario.name == name)
+        .map(|_| ())
+        .ok_or_else(|| AdminError::NotFound(format!("scenario {name}")))
+}
+
+fn set_enabled(id: &str, enabled: bool) -> Result<API, AdminError> {
+    crate::registry::update(|apis| {
+        let index = find(apis, id)?;
+        apis[index].enabled = enabled;
+        Ok(apis[index].clone())
+    })
+}
+
+/// Admin apis, served under `/__admin/`
+/// - `GET /__admin/mappings?tag=<tag>` lists the mocks, optionally by a tag
+/// - `POST /__admin/mappings` creates a mock
+/// - `PUT /__admin/mappings` replaces all of the mocks
+/// - `POST /__admin/mappings/reset` reloads the mocks from the files and resets
+///   the scenarios
+/// - `GET /__admin/mappings/hits` number of times each mock has responded
+/// - `POST /__admin/mappings/hits/reset` resets the hit counters
+/// - `GET /__admin/mappings/export` mocks in the `apis.json` format
+/// - `POST /__admin/mappings/import` adds the mocks, replacing the ones with same ids
+/// - `POST /__admin/mappings/openapi` adds the mocks generated from the OpenAPI
+///   spec in the body, yaml or json, replacing the ones with same ids
+/// - `POST /__admin/mappings/import/har?match_body=true` adds the mocks from the
+///   HAR in the body, see `crate::har::import`
+/// - `POST /__admin/mappings/import/postman?match_body=true` adds the mocks from
+///   the examples of the Postman collection in the body
+/// - `GET /__admin/store/mappings` stored mocks with version and timestamps
+/// - `GET /__admin/store/mappings/{id}/history` every stored version of a mock
+/// - `GET /__admin/requests` journal of the requests, `DELETE` clears it
+/// - `GET /__admin/requests/har` journal of the requests as a HAR
+/// - `POST /__admin/requests/find` journal entries matching the criteria
+/// - `POST /__admin/requests/count` number of journal entries matching the criteria
+/// - `POST /__admin/requests/verify` asserts the number of matching entries,
+///   responds with `417 Expectation Failed` if it does not hold
+/// - `GET /__admin/scenarios` scenarios of the mocks with their current states
+/// - `POST /__admin/scenarios/reset` moves every scenario back to `Started`
+/// - `PUT /__admin/scenarios/{name}/state` sets the state, `{"state": "<state>"}`
+/// - `POST /__admin/scenarios/{name}/reset` moves the scenario back to `Started`
+/// - `GET /__admin/scripts/state` key value state of the scripts, `DELETE` clears it
+/// - `GET /__admin/webhooks` every webhook delivery attempt, `DELETE` clears them
+/// - `GET /__admin/plugins` loaded WebAssembly plugins
+/// - `PUT /__admin/plugins/{name}` loads the plugin in the body, replacing the
+///   one with the same name, `DELETE` unloads it
+/// - `GET|PUT|DELETE /__admin/mappings/{id}` reads, replaces or deletes a mock
+/// - `POST /__admin/mappings/{id}/enable|disable` toggles a mock
+pub async fn handler(
+    req: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, http_service::errors::RouteError> {
+    match route(req).await {
+        Ok(r) => Ok(r),
+        Err(e) => {
+            tracing::error!(target = "AdminError", "Error: {}", e);
+            Ok(crate::router::response(
+                serde_json::to_string(&serde_json::json!({
+                    "success": false,
+                    "message": e.to_string()
+                }))?,
+                e.status(),
+            ))
+        }
+    }
+}
+
+async fn route(
+    req: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, AdminError> {
+    let (parts, body) = req.into_parts();
+    let path = parts
+        .uri
+        .path()
+        .strip_prefix(PREFIX.trim_end_matches('/'))
+        .unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (&parts.method, segments.as_slice()) {
+        (&hyper::Method::GET, ["mappings"]) => {
+            let tag = form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+                .find(|(k, _)| k == "tag")
+                .map(|(_, v)| v.to_string());
+            let apis: Vec<API> = crate::registry::current()
+                .apis()
+                .into_iter()
+                .filter(|api| tag.as_ref().map(|t| api.tags.contains(t)).unwrap_or(true))
+                .collect();
+            json(&apis, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["mappings"]) => {
+            let api = with_id(from_body::<API>(body).await?)?;
+            let created = crate::registry::update(|apis| {
+                let id = api.id.clone().unwrap_or_default();
+                if find(apis, id.as_str()).is_ok() {
+                    return Err(AdminError::Conflict(id));
+                }
+                apis.push(api.clone());
+                Ok(api)
+            })?;
+            json(&created, hyper::StatusCode::CREATED)
+        }
+        (&hyper::Method::PUT, ["mappings"]) => {
+            let replaced: Vec<API> = from_body::<Vec<API>>(body)
+                .await?
+                .into_iter()
+                .map(with_id)
+                .collect::<Result<_, _>>()?;
+            crate::registry::update(|apis| {
+                *apis = replaced.clone();
+                Ok::<_, AdminError>(())
+            })?;
+            json(&replaced, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["mappings", "reset"]) => {
+            let count = crate::registry::reset()?;
+            crate::scenario::reset(None);
+            json(
+                &serde_json::json!({"success": true, "count": count}),
+                hyper::StatusCode::OK,
+            )
+        }
+        (&hyper::Method::GET, ["mappings", "hits"]) => {
+            json(&crate::registry::current().hits(), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["mappings", "hits", "reset"]) => {
+            crate::registry::current().reset_hits();
+            json(&serde_json::json!({"success": true}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["mappings", "export"]) => {
+            json(&crate::registry::current().apis(), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["mappings", "import"]) => {
+            let imported: Vec<API> = from_body::<Vec<API>>(body)
+                .await?
+                .into_iter()
+                .map(with_id)
+                .collect::<Result<_, _>>()?;
+            let count = imported.len();
+            crate::registry::update(|apis| {
+                for api in imported {
+                    match find(apis, api.id.as_deref().unwrap_or_default()) {
+                        Ok(index) => apis[index] = api,
+                        Err(_) => apis.push(api),
+                    }
+                }
+                Ok::<_, AdminError>(())
+            })?;
+            json(
+                &serde_json::json!({"success": true, "count": count}),
+                hyper::StatusCode::OK,
+            )
+        }
+        (&hyper::Method::POST, ["mappings", "openapi"]) => {
+            let spec = hyper::body::to_bytes(body).await?;
+            let imported = crate::openapi::parse(
+                "request body",
+                String::from_utf8_lossy(spec.as_ref()).as_ref(),
+            )?;
+            let count = imported.len();
+            crate::registry::update(|apis| {
+                for api in imported {
+                    match find(apis, api.id.as_deref().unwrap_or_default()) {
+                        Ok(index) => apis[index] = api,
+                        Err(_) => apis.push(api),
+                    }
+                }
+                Ok::<_, AdminError>(())
+            })?;
+            json(
+                &serde_json::json!({"success": true, "count": count}),
+                hyper::StatusCode::OK,
+            )
+        }
+        (&hyper::Method::POST, ["mappings", "import", format @ ("har" | "postman")]) => {
+            let match_body =
+                form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+                    .any(|(k, v)| k == "match_body" && v == "true");
+            let imported = match *format {
+                "har" => crate::har::import(from_body(body).await?, match_body),
+                _ => crate::postman::import(&from_body(body).await?, match_body),
+            };
+            let count = merge(imported)?;
+            json(
+                &serde_json::json!({"success": true, "count": count}),
+                hyper::StatusCode::OK,
+            )
+        }
+        (&hyper::Method::GET, ["store", "mappings"]) => {
+            if !crate::store::is_sqlite() {
+                return Err(AdminError::StoreDisabled);
+            }
+            json(&crate::store::mocks()?, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["store", "mappings", id, "history"]) => {
+            if !crate::store::is_sqlite() {
+                return Err(AdminError::StoreDisabled);
+            }
+            json(&crate::store::history(id)?, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["requests"]) => {
+            json(&crate::journal::entries(), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["requests", "har"]) => json(
+            &crate::har::export(&crate::journal::entries()),
+            hyper::StatusCode::OK,
+        ),
+        (&hyper::Method::DELETE, ["requests"]) => {
+            crate::journal::clear();
+            json(&serde_json::json!({"success": true}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["requests", "find"]) => {
+            let criteria: crate::journal::Criteria = from_body(body).await?;
+            json(&crate::journal::find(&criteria)?, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["requests", "count"]) => {
+            let criteria: crate::journal::Criteria = from_body(body).await?;
+            let count = crate::journal::find(&criteria)?.len();
+            json(&serde_json::json!({"count": count}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["requests", "verify"]) => {
+            let verification: crate::journal::Verification = from_body(body).await?;
+            let count = crate::journal::find(&verification.criteria)?.len();
+            let success = verification.holds(count);
+            json(
+                &serde_json::json!({
+                    "success": success,
+                    "count": count,
+                    "expected": {
+                        "count": verification.count,
+                        "at_least": verification.at_least,
+                        "at_most": verification.at_most,
+                    }
+                }),
+                if success {
+                    hyper::StatusCode::OK
+                } else {
+                    hyper::StatusCode::EXPECTATION_FAILED
+                },
+            )
+        }
+        (&hyper::Method::GET, ["scenarios"]) => json(
+            &crate::scenario::list(&crate::registry::current().apis()),
+            hyper::StatusCode::OK,
+        ),
+        (&hyper::Method::POST, ["scenarios", "reset"]) => {
+            crate::scenario::reset(None);
+            json(&serde_json::json!({"success": true}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::PUT, ["scenarios", name, "state"]) => {
+            let update: ScenarioUpdate = from_body(body).await?;
+            find_scenario(name)?;
+            crate::scenario::set(name, update.state.as_str());
+            json(&serde_json::json!({"success": true}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["scenarios", name, "reset"]) => {
+            find_scenario(name)?;
+            crate::scenario::reset(Some(name));
+            json(&serde_json::json!({"success": true}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["scripts", "state"]) => {
+            json(&crate::script::state(), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::DELETE, ["scripts", "state"]) => {
+            crate::script::reset_state();
+            json(&serde_json::json!({"success": true}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["webhooks"]) => {
+            json(&crate::webhook::deliveries(), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::DELETE, ["webhooks"]) => {
+            crate::webhook::clear();
+            json(&serde_json::json!({"success": true}), hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["plugins"]) => json(&crate::plugin::list(), hyper::StatusCode::OK),
+        (&hyper::Method::PUT, ["plugins", name]) => {
+            let module = hyper::body::to_bytes(body).await?;
+            json(
+                &crate::plugin::load(name, module.as_ref())?,
+                hyper::StatusCode::OK,
+            )
+        }
+        (&hyper::Method::DELETE, ["plugins", name]) => {
+            let removed = crate::plugin::remove(name)
+                .ok_or_else(|| AdminError::NotFound(format!("plugin {name}")))?;
+            json(&removed, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::GET, ["mappings", id]) => {
+            let apis = crate::registry::current().apis();
+            let index = find(&apis, id)?;
+            json(&apis[index], hyper::StatusCode::OK)
+        }
+        (&hyper::Method::PUT, ["mappings", id]) => {
+            let mut api = from_body::<API>(body).await?;
+            api.id = Some(id.to_string());
+            let updated = crate::registry::update(|apis| {
+                let index = find(apis, id)?;
+                apis[index] = api.clone();
+                Ok::<_, AdminError>(api)
+            })?;
+            json(&updated, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::DELETE, ["mappings", id]) => {
+            let deleted = crate::registry::update(|apis| {
+                let index = find(apis, id)?;
+                Ok::<_, AdminError>(apis.remove(index))
+            })?;
+            json(&deleted, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["mappings", id, "enable"]) => {
+            json(&set_enabled(id, true)?, hyper::StatusCode::OK)
+        }
+        (&hyper::Method::POST, ["mappings", id, "disable"]) => {
+            json(&set_enabled(id, false)?, hyper::StatusCode::OK)
+        }
+        _ => Err(AdminError::NotFound(parts.uri.path().to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn call(
+        method: &str,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> (hyper::StatusCode, serde_json::Value) {
+        let request = hyper::Request::builder()
+            .method(method)
+            .uri(path)
+            .body(
+                body.map(|b| hyper::Body::from(b.to_string()))
+                    .unwrap_or_default(),
+            )
+            .expect("valid request");
+        let response = handler(request).await.expect("response");
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("body");
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn mocks_are_created_read_replaced_and_deleted() {
+        let _serial = crate::registry::tests::serial().await;
+        let mock = json!({"id": "a", "method": "GET", "path": "/a"});
+        let (status, _) = call("POST", "/__admin/mappings", Some(mock.clone())).await;
+        assert_eq!(status, hyper::StatusCode::CREATED);
+        let (status, _) = call("POST", "/__admin/mappings", Some(mock)).await;
+        assert_eq!(status, hyper::StatusCode::CONFLICT);
+
+        let replaced = json!({"method": "POST", "path": "/b"});
+        let (status, body) = call("PUT", "/__admin/mappings/a", Some(replaced)).await;
+        assert_eq!(status, hyper::StatusCode::OK);
+        assert_eq!(body["id"], "a");
+        let (_, body) = call("GET", "/__admin/mappings/a", None).await;
+        assert_eq!(body["path"], "/b");
+
+        let (_, body) = call("POST", "/__admin/mappings/a/disable", None).await;
+        assert_eq!(body["enabled"], false);
+
+        let (status, _) = call("DELETE", "/__admin/mappings/a", None).await;
+        assert_eq!(status, hyper::StatusCode::OK);
+        let (status, _) = call("GET", "/__admin/mappings/a", None).await;
+        assert_eq!(status, hyper::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn mocks_are_listed_by_tag_and_imported_by_id() {
+        let _serial = crate::registry::tests::serial().await;
+        let mocks = json!([
+            {"id": "a", "method": "GET", "path": "/a", "tags": ["x"]},
+            {"id": "b", "method": "GET", "path": "/b"}
+        ]);
+        let (status, _) = call("PUT", "/__admin/mappings", Some(mocks)).await;
+        assert_eq!(status, hyper::StatusCode::OK);
+        let (_, body) = call("GET", "/__admin/mappings?tag=x", None).await;
+        assert_eq!(body.as_array().map(Vec::len), Some(1));
+
+        let imported = json!([
+            {"id": "b", "method": "GET", "path": "/b", "status": 201},
+            {"id": "c", "method": "GET", "path": "/c"}
+        ]);
+        let (_, body) = call("POST", "/__admin/mappings/import", Some(imported)).await;
+        assert_eq!(body["count"], 2);
+        let (_, body) = call("GET", "/__admin/mappings/export", None).await;
+        assert_eq!(body.as_array().map(Vec::len), Some(3));
+        let (_, body) = call("GET", "/__admin/mappings/b", None).await;
+        assert_eq!(body["status"], 201);
+    }
+
+    #[tokio::test]
+    async fn invalid_and_reserved_mocks_are_refused() {
+        let _serial = crate::registry::tests::serial().await;
+        for id in RESERVED_IDS {
+            let mock = json!({"id": id, "method": "GET", "path": "/a"});
+            let (status, _) = call("POST", "/__admin/mappings", Some(mock.clone())).await;
+            assert_eq!(status, hyper::StatusCode::BAD_REQUEST, "{id}");
+            let (status, _) = call("POST", "/__admin/mappings/import", Some(json!([mock]))).await;
+            assert_eq!(status, hyper::StatusCode::BAD_REQUEST, "{id}");
+        }
+        let invalid = json!({"method": "GET", "path": "/a", "status": 1000});
+        let (status, _) = call("POST", "/__admin/mappings", Some(invalid)).await;
+        assert_eq!(status, hyper::StatusCode::BAD_REQUEST);
+        let (status, _) = call("POST", "/__admin/mappings", Some(json!("not a mock"))).await;
+        assert_eq!(status, hyper::StatusCode::BAD_REQUEST);
+        assert!(crate::registry::current().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unknown_routes_are_not_found() {
+        assert!(is_admin("/__admin"));
+        assert!(is_admin("/__admin/mappings"));
+        assert!(!is_admin("/__administrator"));
+        let (status, _) = call("GET", "/__admin", None).await;
+        assert_eq!(status, hyper::StatusCode::NOT_FOUND);
+        let (status, _) = call("PATCH", "/__admin/mappings", None).await;
+        assert_eq!(status, hyper::StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,362 @@
+/// Request body as json by its `Content-Type`, the same representation is seen
+/// by the matchers, the contracts, the templates and the journal
+/// - json as is
+/// - `application/x-www-form-urlencoded` as an object of the fields, repeated
+///   fields as arrays
+/// - `multipart/form-data` as an object of the fields, files as objects with
+///   `filename`, `content_type`, `size` and `content`, base64 encoded if binary
+/// - xml as an object of the elements, attributes as `@name` and text next to
+///   attributes or elements as `#text`, repeated elements as arrays
+/// - text and `application/graphql` as a string and anything else as a base64
+///   encoded string
+///
+/// An empty body is `null`, a body which fails to parse is kept as text so
+/// that it can still be matched and reported.
+pub fn parse(content_type: Option<&str>, bytes: &[u8]) -> serde_json::Value {
+    if bytes.is_empty() {
+        return serde_json::Value::Null;
+    }
+    // the boundary is case sensitive, only the media type is lowercased
+    let boundary = content_type.and_then(|c| parameter(c, "boundary"));
+    let content_type = content_type.unwrap_or_default().to_lowercase();
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let parsed = match mime.as_str() {
+        "application/x-www-form-urlencoded" => Some(form(bytes)),
+        "multipart/form-data" => boundary.and_then(|b| multipart(bytes, b.as_str())),
+        mime if mime.ends_with("xml") => xml(bytes),
+        mime if mime.starts_with("text/") || mime == "application/graphql" => return text(bytes),
+        // json is assumed when the content type is missing, as clients often
+        // leave it out
+        mime if mime.is_empty() || mime.contains("json") => serde_json::from_slice(bytes).ok(),
+        _ => return binary(bytes),
+    };
+    parsed.unwrap_or_else(|| {
+        tracing::warn!(
+            target = "BodyParseError",
+            content_type = content_type.as_str(),
+            "body is kept as text"
+        );
+        match std::str::from_utf8(bytes) {
+            Ok(_) => text(bytes),
+            Err(_) => binary(bytes),
+        }
+    })
+}
+
+fn text(bytes: &[u8]) -> serde_json::Value {
+    serde_json::Value::String(String::from_utf8_lossy(bytes).to_string())
+}
+
+fn binary(bytes: &[u8]) -> serde_json::Value {
+    use base64::Engine;
+    serde_json::Value::String(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Adds the value to the object, a repeated name turns into an array
+fn insert(
+    object: &mut serde_json::Map<String, serde_json::Value>,
+    name: String,
+    value: serde_json::Value,
+) {
+    match object.get_mut(name.as_str()) {
+        Some(serde_json::Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = serde_json::Value::Array(vec![first, value]);
+        }
+        None => {
+            object.insert(name, value);
+        }
+    }
+}
+
+fn form(bytes: &[u8]) -> serde_json::Value {
+    let mut object = serde_json::Map::new();
+    for (name, value) in form_urlencoded::parse(bytes) {
+        insert(
+            &mut object,
+            name.to_string(),
+            serde_json::Value::String(value.to_string()),
+        );
+    }
+    serde_json::Value::Object(object)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Value of a `name="value"` parameter of a header
+fn parameter(header: &str, name: &str) -> Option<String> {
+    header
+        .split(';')
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+}
+
+fn multipart(bytes: &[u8], boundary: &str) -> Option<serde_json::Value> {
+    let delimiter = format!("--{boundary}");
+    let mut object = serde_json::Map::new();
+    let mut rest = &bytes[find(bytes, delimiter.as_bytes())? + delimiter.len()..];
+    // every part starts after a line break following the delimiter, the
+    // delimiter followed by `--` closes the body
+    while !rest.starts_with(b"--") {
+        rest = rest.strip_prefix(b"\r\n")?;
+        let end = find(rest, format!("\r\n{delimiter}").as_bytes())?;
+        let part = &rest[..end];
+        rest = &rest[end + 2 + delimiter.len()..];
+
+        let header_end = find(part, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&part[..header_end]);
+        let content = &part[header_end + 4..];
+        let header = |name: &str| {
+            headers.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.trim()
+                    .eq_ignore_ascii_case(name)
+                    .then(|| value.trim().to_string())
+            })
+        };
+        let disposition = header("content-disposition")?;
+        let name = parameter(disposition.as_str(), "name")?;
+        let value = match parameter(disposition.as_str(), "filename") {
+            Some(filename) => {
+                let content_type = header("content-type")
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                let encoded = match std::str::from_utf8(content) {
+                    Ok(text) if !content_type.starts_with("application/octet-stream") => {
+                        serde_json::Value::String(text.to_string())
+                    }
+                    _ => binary(content),
+                };
+                serde_json::json!({
+                    "filename": filename,
+                    "content_type": content_type,
+                    "size": content.len(),
+                    "content": encoded,
+                })
+            }
+            None => text(content),
+        };
+        insert(&mut object, name, value);
+    }
+    Some(serde_json::Value::Object(object))
+}
+
+fn xml(bytes: &[u8]) -> Option<serde_json::Value> {
+    use quick_xml::events::{BytesStart, Event};
+    struct Element {
+        name: String,
+        object: serde_json::Map<String, serde_json::Value>,
+        text: String,
+    }
+    let start = |e: &BytesStart| -> Option<Element> {
+        let mut object = serde_json::Map::new();
+        for attribute in e.attributes() {
+            let attribute = attribute.ok()?;
+            object.insert(
+                format!("@{}", String::from_utf8_lossy(attribute.key.as_ref())),
+                serde_json::Value::String(attribute.unescape_value().ok()?.to_string()),
+            );
+        }
+        Some(Element {
+            name: String::from_utf8_lossy(e.name().as_ref()).to_string(),
+            object,
+            text: String::new(),
+        })
+    };
+    // an element with only text is the text itself
+    let value = |element: Element| -> serde_json::Value {
+        let text = element.text.trim().to_string();
+        if element.object.is_empty() {
+            return serde_json::Value::String(text);
+        }
+        let mut object = element.object;
+        if !text.is_empty() {
+            object.insert("#text".to_string(), serde_json::Value::String(text));
+        }
+        serde_json::Value::Object(object)
+    };
+
+    let mut reader = quick_xml::Reader::from_reader(bytes);
+    let mut stack: Vec<Element> = vec![];
+    let mut root = serde_json::Map::new();
+    loop {
+        match reader.read_event().ok()? {
+            Event::Start(e) => stack.push(start(&e)?),
+            Event::Empty(e) => {
+                let element = start(&e)?;
+                let name = element.name.to_string();
+                match stack.last_mut() {
+                    Some(parent) => insert(&mut parent.object, name, value(element)),
+                    None => insert(&mut root, name, value(element)),
+                }
+            }
+            Event::End(_) => {
+                let element = stack.pop()?;
+                let name = element.name.to_string();
+                match stack.last_mut() {
+                    Some(parent) => insert(&mut parent.object, name, value(element)),
+                    None => insert(&mut root, name, value(element)),
+                }
+            }
+            Event::Text(t) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(t.unescape().ok()?.as_ref());
+                }
+            }
+            Event::CData(c) => {
+                if let Some(element) = stack.last_mut() {
+                    element
+                        .text
+                        .push_str(String::from_utf8_lossy(c.as_ref()).as_ref());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if !stack.is_empty() || root.is_empty() {
+        return None;
+    }
+    Some(serde_json::Value::Object(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_is_parsed_with_or_without_a_content_type() {
+        assert_eq!(
+            parse(Some("application/json"), br#"{"a":1}"#),
+            json!({"a": 1})
+        );
+        assert_eq!(
+            parse(Some("application/vnd.api+json; charset=utf-8"), b"[1]"),
+            json!([1])
+        );
+        assert_eq!(parse(None, br#"{"a":1}"#), json!({"a": 1}));
+        assert_eq!(parse(Some("application/json"), b""), json!(null));
+    }
+
+    #[test]
+    fn invalid_bodies_are_kept_as_text() {
+        assert_eq!(parse(Some("application/json"), b"{oops"), json!("{oops"));
+        assert_eq!(parse(Some("application/xml"), b"<a>"), json!("<a>"));
+        assert_eq!(
+            parse(Some("application/json"), &[0xff, 0xfe]),
+            json!("//4=")
+        );
+    }
+
+    #[test]
+    fn text_and_binary_bodies() {
+        assert_eq!(parse(Some("text/plain"), b"hello"), json!("hello"));
+        assert_eq!(
+            parse(Some("application/graphql"), b"{ users { id } }"),
+            json!("{ users { id } }")
+        );
+        assert_eq!(
+            parse(Some("application/octet-stream"), b"hi"),
+            json!("aGk=")
+        );
+    }
+
+    #[test]
+    fn form_fields_repeated_as_arrays() {
+        assert_eq!(
+            parse(
+                Some("application/x-www-form-urlencoded"),
+                b"name=a+b&tag=x&tag=y&tag=z&empty="
+            ),
+            json!({"name": "a b", "tag": ["x", "y", "z"], "empty": ""})
+        );
+    }
+
+    #[test]
+    fn multipart_fields_and_files() {
+        let body = concat!(
+            "preamble\r\n",
+            "--XyZ\r\n",
+            "Content-Disposition: form-data; name=\"title\"\r\n",
+            "\r\n",
+            "hello\r\n",
+            "--XyZ\r\n",
+            "content-disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "line 1\r\nline 2\r\n",
+            "--XyZ\r\n",
+            "Content-Disposition: form-data; name=\"blob\"; filename=\"b.bin\"\r\n",
+            "\r\n",
+            "hi\r\n",
+            "--XyZ--\r\n",
+        );
+        assert_eq!(
+            parse(
+                Some("multipart/form-data; boundary=\"XyZ\""),
+                body.as_bytes()
+            ),
+            json!({
+                "title": "hello",
+                "file": {
+                    "filename": "a.txt",
+                    "content_type": "text/plain",
+                    "size": 14,
+                    "content": "line 1\r\nline 2",
+                },
+                "blob": {
+                    "filename": "b.bin",
+                    "content_type": "application/octet-stream",
+                    "size": 2,
+                    "content": "aGk=",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_multipart_is_kept_as_text() {
+        let body = b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nno end";
+        assert_eq!(
+            parse(Some("multipart/form-data; boundary=XyZ"), body),
+            json!(String::from_utf8_lossy(body))
+        );
+        assert_eq!(
+            parse(Some("multipart/form-data"), b"--XyZ--"),
+            json!("--XyZ--")
+        );
+    }
+
+    #[test]
+    fn xml_elements_attributes_and_text() {
+        let body = br#"<?xml version="1.0"?>
+            <order id="7">
+                <item sku="a">First &amp; best</item>
+                <item>plain</item>
+                <note><![CDATA[<raw>]]></note>
+                <empty/>
+            </order>"#;
+        assert_eq!(
+            parse(Some("application/xml"), body),
+            json!({"order": {
+                "@id": "7",
+                "item": [{"@sku": "a", "#text": "First & best"}, "plain"],
+                "note": "<raw>",
+                "empty": "",
+            }})
+        );
+        assert_eq!(
+            parse(Some("application/soap+xml"), b"<a><b>1</b></a>"),
+            json!({"a": {"b": "1"}})
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::schema::Violation;
+use crate::utils::MockRequest;
+use std::sync::Arc;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ContractError {
+    #[error("OpenAPIError: {0}")]
+    OpenAPI(#[from] crate::openapi::OpenAPIError),
+    #[error("OperationNotFound: {operation} in {spec}")]
+    OperationNotFound { spec: String, operation: String },
+    #[error("InvalidPattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+}
+
+/// Operation of an OpenAPI spec, by its `operationId` or by the method and the
+/// path as written in the spec like `POST /pets/{petId}`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct OperationRef {
+    pub spec: String,
+    pub operation: String,
+}
+
+/// Schemas the requests to a mock need to conform to, a request violating them
+/// gets a `400` listing every violation. JSON Schemas can be given for the
+/// body, query and headers or be taken from an OpenAPI operation, the ones
+/// given here take precedence over the ones from the operation.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Contract {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<serde_json::Value>,
+    /// Header names are matched case insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub openapi: Option<OperationRef>,
+}
+
+// Schema along with the document its `$ref`s are resolved against
+struct Schema {
+    root: Arc<serde_json::Value>,
+    patterns: crate::schema::Patterns,
+    schema: serde_json::Value,
+}
+
+impl Schema {
+    fn new(root: Arc<serde_json::Value>, schema: serde_json::Value) -> Result<Self, regex::Error> {
+        Ok(Schema {
+            patterns: crate::schema::Patterns::compile(&root, &schema)?,
+            root,
+            schema,
+        })
+    }
+
+    fn inline(schema: &serde_json::Value) -> Result<Self, regex::Error> {
+        Schema::new(Arc::new(schema.clone()), schema.clone())
+    }
+}
+
+#[derive(Default)]
+pub struct ContractValidator {
+    body: Option<Schema>,
+    // an absent body is only validated if the body is required
+    body_required: bool,
+    query: Option<Schema>,
+    headers: Option<Schema>,
+}
+
+/// Object schema of the parameters in the given location of an operation
+fn parameters_schema(
+    parameters: &[serde_json::Value],
+    location: &str,
+) -> Option<serde_json::Value> {
+    let parameters: Vec<&serde_json::Value> = parameters
+        .iter()
+        .filter(|p| p.get("in").and_then(|i| i.as_str()) == Some(location))
+        .collect();
+    if parameters.is_empty() {
+        return None;
+    }
+    let mut properties = serde_json::Map::new();
+    let mut required = vec![];
+    for parameter in parameters {
+        let Some(name) = parameter.get("name").and_then(|n| n.as_str()) else {
+            continue;
+        };
+        properties.insert(
+            name.to_string(),
+            parameter
+                .get("schema")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({})),
+        );
+        if parameter.get("required").and_then(|r| r.as_bool()) == Some(true) {
+            required.push(serde_json::Value::String(name.to_string()));
+        }
+    }
+    Some(serde_json::json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    }))
+}
+
+/// Lowercases the property names of the headers schema, the request headers
+/// are lowercase
+fn lowercase(mut schema: serde_json::Value) -> serde_json::Value {
+    if let Some(properties) = schema.get_mut("properties").and_then(|p| p.as_object_mut()) {
+        *properties = std::mem::take(properties)
+            .into_iter()
+            .map(|(name, property)| (name.to_lowercase(), property))
+            .collect();
+    }
+    if let Some(required) = schema.get_mut("required").and_then(|r| r.as_array_mut()) {
+        for name in required.iter_mut() {
+            if let Some(lowercase) = name.as_str().map(|n| n.to_lowercase()) {
+                *name = serde_json::Value::String(lowercase);
+            }
+        }
+    }
+    schema
+}
+
+impl ContractValidator {
+    pub fn new(contract: &Contract) -> Result<Self, ContractError> {
+        let mut validator = ContractValidator::default();
+        if let Some(reference) = &contract.openapi {
+            let spec = Arc::new(crate::openapi::load_spec(reference.spec.as_str())?);
+            let (operation, parameters) =
+                crate::openapi::operation(&spec, reference.operation.as_str()).ok_or_else(
+                    || ContractError::OperationNotFound {
+                        spec: reference.spec.to_string(),
+                        operation: reference.operation.to_string(),
+                    },
+                )?;
+            if let Some(request_body) = operation.get("requestBody") {
+                let request_body = crate::schema::resolve(&spec, request_body);
+                validator.body_required =
+                    request_body.get("required").and_then(|r| r.as_bool()) == Some(true);
+                let schema = request_body
+                    .get("content")
+                    .and_then(|c| c.as_object())
+                    .and_then(|c| {
+                        c.iter()
+                            .find(|(media_type, _)| media_type.contains("json"))
+                            .or_else(|| c.iter().next())
+                    })
+                    .and_then(|(_, media)| media.get("schema"));
+                validator.body = schema
+                    .map(|schema| Schema::new(spec.clone(), schema.clone()))
+                    .transpose()?;
+            }
+            validator.query = parameters_schema(&parameters, "query")
+                .map(|schema| Schema::new(spec.clone(), schema))
+                .transpose()?;
+            validator.headers = parameters_schema(&parameters, "header")
+                .map(|schema| Schema::new(spec.clone(), lowercase(schema)))
+                .transpose()?;
+        }
+        if let Some(body) = &contract.body {
+            validator.body = Some(Schema::inline(body)?);
+            validator.body_required = true;
+        }
+        if let Some(query) = &contract.query {
+            validator.query = Some(Schema::inline(query)?);
+        }
+        if let Some(headers) = &contract.headers {
+            validator.headers = Some(Schema::inline(&lowercase(headers.clone()))?);
+        }
+        Ok(validator)
+    }
+
+    /// Every violation of the contract by the request, empty if it conforms
+    pub fn validate(&self, request: &MockRequest) -> Vec<Violation> {
+        let mut violations = vec![];
+        if let Some(body) = &self.body {
+            if self.body_required || !request.body.is_null() {
+                violations.extend(crate::schema::validate(
+                    &body.root,
+                    &body.patterns,
+                    &body.schema,
+                    &request.body,
+                    "body",
+                ));
+            }
+        }
+        for (location, schema, values) in [
+            ("query", &self.query, &request.query),
+            ("headers", &self.headers, &request.headers),
+        ] {
+            if let Some(schema) = schema {
+                let values = crate::schema::coerce(&schema.root, &schema.schema, values);
+                violations.extend(crate::schema::validate(
+                    &schema.root,
+                    &schema.patterns,
+                    &schema.schema,
+                    &values,
+                    location,
+                ));
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn validator(contract: serde_json::Value) -> Result<ContractValidator, ContractError> {
+        ContractValidator::new(&serde_json::from_value(contract).expect("valid contract"))
+    }
+
+    #[test]
+    fn invalid_patterns_refuse_the_contract() {
+        let contract = json!({"body": {"properties": {"code": {"pattern": "(["}}}});
+        assert!(matches!(
+            validator(contract),
+            Err(ContractError::InvalidPattern(_))
+        ));
+        let contract = json!({"query": {"properties": {"q": {"pattern": "(["}}}});
+        assert!(validator(contract).is_err());
+    }
+
+    #[test]
+    fn requests_are_validated_against_the_patterns() {
+        let validator = validator(json!({
+            "body": {"properties": {"code": {"pattern": "^[A-Z]+$"}}},
+            "headers": {"properties": {"X-Tenant": {"pattern": "^t-"}}, "required": ["X-Tenant"]},
+        }))
+        .expect("valid contract");
+        let request = MockRequest {
+            headers: [("x-tenant".to_string(), "t-1".to_string())]
+                .into_iter()
+                .collect(),
+            body: json!({"code": "ABC"}),
+            ..Default::default()
+        };
+        assert!(validator.validate(&request).is_empty());
+        let request = MockRequest {
+            headers: [("x-tenant".to_string(), "other".to_string())]
+                .into_iter()
+                .collect(),
+            body: json!({"code": "abc"}),
+            ..Default::default()
+        };
+        let locations: Vec<_> = validator
+            .validate(&request)
+            .into_iter()
+            .map(|v| (v.location, v.path))
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                ("body".to_string(), "$.code".to_string()),
+                ("headers".to_string(), "$.x-tenant".to_string()),
+            ]
+        );
+    }
+}
@@ -4,4 +4,18 @@ pub enum RouteError {
     JsonSerializeError(#[from] serde_json::Error),
     #[error("GetProfileError: {0}")]
     GetProfileError(#[from] http_service::controller::GetProfileError),
+    #[error("HyperError: {0}")]
+    HyperError(#[from] hyper::Error),
+    #[error("TemplateError: {0}")]
+    TemplateError(#[from] http_service::template::TemplateError),
+    #[error("ResponseBodyError: {0}")]
+    ResponseBodyError(#[from] http_service::utils::ResponseBodyError),
+    #[error("FaultAbort: {0}")]
+    FaultAbort(#[from] http_service::fault::Abort),
+    #[error("InvalidStatusCode: {0}")]
+    InvalidStatusCode(#[from] hyper::http::status::InvalidStatusCode),
+    #[error("InvalidHeaderName: {0}")]
+    InvalidHeaderName(#[from] hyper::header::InvalidHeaderName),
+    #[error("InvalidHeaderValue: {0}")]
+    InvalidHeaderValue(#[from] hyper::header::InvalidHeaderValue),
 }
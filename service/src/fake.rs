@@ -0,0 +1,521 @@
+use rand::{Rng, SeedableRng};
+
+const FIRST_NAMES: [&str; 16] = [
+    "Aarav", "Abrar", "Amelia", "Chen", "Diego", "Fatima", "Hana", "Ivan", "Kofi", "Lena", "Mateo",
+    "Noor", "Olivia", "Priya", "Sven", "Yuki",
+];
+const LAST_NAMES: [&str; 16] = [
+    "Ahmed", "Bauer", "Costa", "Dubois", "Garcia", "Haddad", "Ito", "Kim", "Kowalski", "Mensah",
+    "Nair", "Novak", "Okafor", "Rossi", "Silva", "Wong",
+];
+const CITIES: [&str; 12] = [
+    "Bengaluru",
+    "Berlin",
+    "Cairo",
+    "Jakarta",
+    "Lagos",
+    "Lima",
+    "Mumbai",
+    "Nairobi",
+    "Osaka",
+    "Paris",
+    "Seoul",
+    "Toronto",
+];
+const COUNTRIES: [&str; 12] = [
+    "BR", "CA", "DE", "EG", "FR", "ID", "IN", "JP", "KE", "KR", "NG", "PE",
+];
+const DOMAINS: [&str; 4] = ["example.com", "example.org", "mail.test", "inbox.test"];
+const WORDS: [&str; 16] = [
+    "alpha", "bravo", "cedar", "delta", "ember", "fjord", "glade", "harbor", "indigo", "juniper",
+    "kelp", "lumen", "maple", "nova", "onyx", "prism",
+];
+
+// Timestamps fall in the two years from 2024-01-01 so a seed always
+// generates the same values
+const EPOCH: i64 = 1_704_067_200;
+const SPAN: i64 = 2 * 365 * 24 * 60 * 60;
+
+// Chains of $ref are followed up to this depth
+const MAX_DEPTH: usize = 16;
+
+// Arrays and strings are cut at these sizes whatever the schema allows, the
+// bodies are generated on every request
+const MAX_ITEMS: u64 = 100;
+const MAX_LENGTH: usize = 1024;
+
+/// Json response body generated from a JSON Schema on every request, the same
+/// `seed` always generates the same body. Values are picked from the `format`
+/// of the strings, like `email`, `uuid`, `date-time`, `did` or `hex`, or from
+/// the property names, like `name`, `email`, `amount` or `created_at`, and
+/// stay within `minimum`/`maximum`, `minLength`/`maxLength` and `enum`. Arrays
+/// have between `minItems` and `maxItems` items, `1` to `5` by default, and at
+/// most `100`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct FakeBody {
+    pub schema: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl FakeBody {
+    pub fn generate(&self) -> serde_json::Value {
+        let mut rng = match self.seed {
+            Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+            None => rand::rngs::StdRng::from_entropy(),
+        };
+        let mut faker = Faker {
+            root: &self.schema,
+            rng: &mut rng,
+            refs: vec![],
+        };
+        faker.value(&self.schema, None)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+struct Faker<'a> {
+    root: &'a serde_json::Value,
+    rng: &'a mut rand::rngs::StdRng,
+    refs: Vec<String>,
+}
+
+impl Faker<'_> {
+    fn pick<'b>(&mut self, items: &[&'b str]) -> &'b str {
+        items[self.rng.gen_range(0..items.len())]
+    }
+
+    fn hex(&mut self, bytes: usize) -> String {
+        to_hex(&(0..bytes).map(|_| self.rng.gen()).collect::<Vec<u8>>())
+    }
+
+    /// Value for the schema, `name` is the property the value is generated for
+    fn value(&mut self, schema: &serde_json::Value, name: Option<&str>) -> serde_json::Value {
+        let root = self.root;
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            if self.refs.len() > MAX_DEPTH || self.refs.iter().any(|r| r == reference) {
+                return serde_json::Value::Null;
+            }
+            self.refs.push(reference.to_string());
+            let value = self.value(crate::schema::resolve(root, schema), name);
+            self.refs.pop();
+            return value;
+        }
+        if let Some(constant) = schema.get("const") {
+            return constant.clone();
+        }
+        if let Some(values) = schema.get("enum").and_then(|e| e.as_array()) {
+            if !values.is_empty() {
+                return values[self.rng.gen_range(0..values.len())].clone();
+            }
+        }
+        if let Some(all_of) = schema.get("allOf").and_then(|a| a.as_array()) {
+            let mut merged = serde_json::Map::new();
+            for part in all_of {
+                if let serde_json::Value::Object(map) = self.value(part, name) {
+                    merged.extend(map);
+                }
+            }
+            return serde_json::Value::Object(merged);
+        }
+        if let Some(choices) = schema
+            .get("oneOf")
+            .or_else(|| schema.get("anyOf"))
+            .and_then(|a| a.as_array())
+        {
+            if !choices.is_empty() {
+                let choice = &choices[self.rng.gen_range(0..choices.len())];
+                return self.value(choice, name);
+            }
+        }
+
+        let name = name.unwrap_or_default().to_lowercase();
+        match crate::schema::schema_type(schema).as_deref() {
+            Some("object") => {
+                let mut object = serde_json::Map::new();
+                if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+                    for (property, property_schema) in properties {
+                        let value = self.value(property_schema, Some(property.as_str()));
+                        object.insert(property.to_string(), value);
+                    }
+                }
+                serde_json::Value::Object(object)
+            }
+            Some("array") => {
+                let min = schema
+                    .get("minItems")
+                    .and_then(|m| m.as_u64())
+                    .unwrap_or(1)
+                    .min(MAX_ITEMS);
+                let max = schema
+                    .get("maxItems")
+                    .and_then(|m| m.as_u64())
+                    .unwrap_or(min + 4)
+                    .clamp(min, MAX_ITEMS);
+                let count = self.rng.gen_range(min..=max);
+                let items = schema.get("items").cloned().unwrap_or_default();
+                serde_json::Value::Array(
+                    (0..count)
+                        .map(|_| self.value(&items, Some(name.as_str())))
+                        .collect(),
+                )
+            }
+            Some("integer") => serde_json::json!(self.integer(schema, name.as_str())),
+            Some("number") => serde_json::json!(self.number(schema, name.as_str())),
+            Some("boolean") => serde_json::Value::Bool(self.rng.gen()),
+            Some("string") => serde_json::Value::String(self.string(schema, name.as_str())),
+            _ => serde_json::Value::Null,
+        }
+    }
+
+    fn integer(&mut self, schema: &serde_json::Value, name: &str) -> i64 {
+        let timestamp = schema.get("format").and_then(|f| f.as_str()) == Some("timestamp")
+            || name.ends_with("_at")
+            || name.contains("timestamp");
+        let (default_min, default_max) = if timestamp {
+            (EPOCH, EPOCH + SPAN)
+        } else {
+            (0, 1000)
+        };
+        let min = schema
+            .get("minimum")
+            .and_then(|m| m.as_i64())
+            .unwrap_or(default_min);
+        let max = schema
+            .get("maximum")
+            .and_then(|m| m.as_i64())
+            .unwrap_or(default_max.max(min));
+        let max = max.max(min);
+        match schema.get("multipleOf").and_then(|m| m.as_i64()) {
+            // a multiple within the bounds, the lowest bound if there is none
+            Some(multiple) if multiple > 0 => {
+                let low = min.div_euclid(multiple) + i64::from(min.rem_euclid(multiple) != 0);
+                let high = max.div_euclid(multiple);
+                if low > high {
+                    return min;
+                }
+                self.rng.gen_range(low..=high) * multiple
+            }
+            _ => self.rng.gen_range(min..=max),
+        }
+    }
+
+    fn number(&mut self, schema: &serde_json::Value, name: &str) -> f64 {
+        let min = schema
+            .get("minimum")
+            .and_then(|m| m.as_f64())
+            .unwrap_or(0.0);
+        let max = schema
+            .get("maximum")
+            .and_then(|m| m.as_f64())
+            .unwrap_or(min + 1000.0);
+        match schema.get("multipleOf").and_then(|m| m.as_f64()) {
+            // a multiple within the bounds, the lowest bound if there is none
+            Some(multiple) if multiple > 0.0 => {
+                let low = (min / multiple).ceil();
+                let high = (max / multiple).floor();
+                if low > high || !low.is_finite() || !high.is_finite() {
+                    return min;
+                }
+                self.between(low, high).round().clamp(low, high) * multiple
+            }
+            // amounts are kept to the cents
+            _ if ["amount", "price", "balance", "fee"]
+                .iter()
+                .any(|n| name.contains(n)) =>
+            {
+                let value = self.between(min, max);
+                ((value * 100.0).floor() / 100.0).max(min)
+            }
+            _ => self.between(min, max),
+        }
+    }
+
+    /// Number between the bounds, interpolated so that a range wider than the
+    /// largest float does not overflow
+    fn between(&mut self, min: f64, max: f64) -> f64 {
+        if max <= min {
+            return min;
+        }
+        let t: f64 = self.rng.gen();
+        (min * (1.0 - t) + max * t).clamp(min, max)
+    }
+
+    fn string(&mut self, schema: &serde_json::Value, name: &str) -> String {
+        let format = schema
+            .get("format")
+            .and_then(|f| f.as_str())
+            .unwrap_or_default();
+        let value = match format {
+            "email" => self.email(),
+            "uuid" => self.uuid(),
+            "date-time" => self.timestamp().to_rfc3339(),
+            "date" => self.timestamp().format("%Y-%m-%d").to_string(),
+            "uri" | "url" => format!("https://{}/{}", self.pick(&DOMAINS), self.pick(&WORDS)),
+            "ipv4" => format!(
+                "10.{}.{}.{}",
+                self.rng.gen::<u8>(),
+                self.rng.gen::<u8>(),
+                self.rng.gen_range(1..255u8)
+            ),
+            "did" => self.did(),
+            "hex" => format!("0x{}", self.hex(32)),
+            _ if name.contains("email") => self.email(),
+            _ if name.ends_with("did") => self.did(),
+            _ if name.contains("hash") => format!("0x{}", self.hex(32)),
+            _ if name == "id" || name.ends_with("_id") => self.uuid(),
+            _ if name.ends_with("_at") || name.contains("date") || name.contains("time") => {
+                self.timestamp().to_rfc3339()
+            }
+            _ if name.contains("first_name") => self.pick(&FIRST_NAMES).to_string(),
+            _ if name.contains("last_name") => self.pick(&LAST_NAMES).to_string(),
+            _ if name.contains("name") => {
+                format!("{} {}", self.pick(&FIRST_NAMES), self.pick(&LAST_NAMES))
+            }
+            _ if name.contains("phone") => format!("+1555{:07}", self.rng.gen_range(0..10_000_000)),
+            _ if name.contains("city") => self.pick(&CITIES).to_string(),
+            _ if name.contains("country") => self.pick(&COUNTRIES).to_string(),
+            _ => format!("{}-{}", self.pick(&WORDS), self.pick(&WORDS)),
+        };
+        let min = schema
+            .get("minLength")
+            .and_then(|m| m.as_u64())
+            .unwrap_or(0)
+            .min(MAX_LENGTH as u64) as usize;
+        let max = schema
+            .get("maxLength")
+            .and_then(|m| m.as_u64())
+            .map(|m| m as usize);
+        let mut value: String = match max {
+            Some(max) => value.chars().take(max).collect(),
+            None => value,
+        };
+        let length = value.chars().count();
+        if length < min {
+            value.push_str("x".repeat(min - length).as_str());
+        }
+        value
+    }
+
+    fn email(&mut self) -> String {
+        format!(
+            "{}.{}{}@{}",
+            self.pick(&FIRST_NAMES).to_lowercase(),
+            self.pick(&LAST_NAMES).to_lowercase(),
+            self.rng.gen_range(1..100),
+            self.pick(&DOMAINS)
+        )
+    }
+
+    fn uuid(&mut self) -> String {
+        uuid::Builder::from_random_bytes(self.rng.gen())
+            .into_uuid()
+            .to_string()
+    }
+
+    fn timestamp(&mut self) -> chrono::DateTime<chrono::Utc> {
+        let seconds = EPOCH + self.rng.gen_range(0..SPAN);
+        chrono::DateTime::from_timestamp(seconds, 0).unwrap_or_default()
+    }
+
+    /// DID as the services encode it, hex of `did:nbg:<name>` padded to 32 bytes
+    fn did(&mut self) -> String {
+        let did = format!(
+            "did:nbg:{}{}",
+            self.pick(&FIRST_NAMES).to_lowercase(),
+            self.rng.gen_range(1000..10000)
+        );
+        let mut bytes = did.into_bytes();
+        bytes.resize(32, 0);
+        format!("0x{}", to_hex(&bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fake(schema: serde_json::Value, seed: u64) -> serde_json::Value {
+        FakeBody {
+            schema,
+            seed: Some(seed),
+        }
+        .generate()
+    }
+
+    fn user() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "string"},
+                "email": {"type": "string", "format": "email"},
+                "created_at": {"type": "string", "format": "date-time"},
+                "birthday": {"type": "string", "format": "date"},
+                "website": {"type": "string", "format": "uri"},
+                "ip": {"type": "string", "format": "ipv4"},
+                "code": {"type": "string", "minLength": 8, "maxLength": 10},
+                "age": {"type": "integer", "minimum": 18, "maximum": 99},
+                "score": {"type": "integer", "minimum": 0, "maximum": 100, "multipleOf": 5},
+                "amount": {"type": "number", "minimum": 1, "maximum": 2},
+                "role": {"enum": ["admin", "member"]},
+                "kind": {"const": "user"},
+                "active": {"type": "boolean"},
+                "tags": {
+                    "type": "array",
+                    "minItems": 2,
+                    "maxItems": 4,
+                    "items": {"type": "string"},
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_body() {
+        assert_eq!(fake(user(), 7), fake(user(), 7));
+        assert_ne!(fake(user(), 7), fake(user(), 8));
+    }
+
+    #[test]
+    fn generated_values_conform_to_the_schema() {
+        for seed in 0..64 {
+            let value = fake(user(), seed);
+            let patterns = crate::schema::Patterns::default();
+            let violations = crate::schema::validate(&user(), &patterns, &user(), &value, "body");
+            assert!(violations.is_empty(), "{value}: {violations:?}");
+            let id = value["id"].as_str().unwrap();
+            assert!(uuid::Uuid::parse_str(id).is_ok());
+            let amount = value["amount"].as_f64().unwrap();
+            assert_eq!((amount * 100.0).round() / 100.0, amount);
+        }
+    }
+
+    #[test]
+    fn values_follow_the_property_names() {
+        let schema = json!({"properties": {
+            "first_name": {"type": "string"},
+            "city": {"type": "string"},
+            "owner_did": {"type": "string"},
+            "tx_hash": {"type": "string"},
+            "updated_at": {"type": "integer"},
+        }});
+        let value = fake(schema, 1);
+        assert!(FIRST_NAMES.contains(&value["first_name"].as_str().unwrap()));
+        assert!(CITIES.contains(&value["city"].as_str().unwrap()));
+        let did = value["owner_did"].as_str().unwrap();
+        assert_eq!(did.len(), 2 + 64);
+        assert!(did.starts_with(format!("0x{}", to_hex(b"did:nbg:")).as_str()));
+        assert_eq!(value["tx_hash"].as_str().unwrap().len(), 2 + 64);
+        let updated_at = value["updated_at"].as_i64().unwrap();
+        assert!((EPOCH..=EPOCH + SPAN).contains(&updated_at));
+    }
+
+    #[test]
+    fn references_are_followed_and_cycles_cut() {
+        let schema = json!({
+            "$ref": "#/$defs/Node",
+            "$defs": {"Node": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "next": {"$ref": "#/$defs/Node"},
+                },
+            }},
+        });
+        let value = fake(schema, 3);
+        assert!(value["name"].is_string());
+        assert_eq!(value["next"], json!(null));
+    }
+
+    #[test]
+    fn multiples_stay_within_the_bounds() {
+        for seed in 0..32 {
+            let value = fake(
+                json!({"type": "integer", "minimum": 3, "maximum": 12, "multipleOf": 5}),
+                seed,
+            );
+            assert!(value == json!(5) || value == json!(10), "{value}");
+            let value = fake(
+                json!({"type": "integer", "minimum": -12, "maximum": -3, "multipleOf": 5}),
+                seed,
+            );
+            assert!(value == json!(-5) || value == json!(-10), "{value}");
+            let value = fake(
+                json!({"type": "number", "minimum": 0.3, "maximum": 1.2, "multipleOf": 0.5}),
+                seed,
+            )
+            .as_f64()
+            .unwrap();
+            assert!(value == 0.5 || value == 1.0, "{value}");
+        }
+    }
+
+    #[test]
+    fn range_without_a_multiple_falls_back_to_the_minimum() {
+        assert_eq!(
+            fake(
+                json!({"type": "integer", "minimum": 3, "maximum": 4, "multipleOf": 5}),
+                0
+            ),
+            json!(3)
+        );
+        assert_eq!(
+            fake(
+                json!({"type": "number", "minimum": 3, "maximum": 4, "multipleOf": 5}),
+                0
+            ),
+            json!(3.0)
+        );
+    }
+
+    #[test]
+    fn extreme_bounds_do_not_panic() {
+        for seed in 0..16 {
+            let value = fake(
+                json!({"type": "number", "minimum": -1e308, "maximum": 1e308}),
+                seed,
+            )
+            .as_f64()
+            .unwrap();
+            assert!(value.is_finite());
+            let value = fake(
+                json!({"type": "integer", "minimum": i64::MIN, "maximum": i64::MAX}),
+                seed,
+            );
+            assert!(value.is_i64());
+        }
+    }
+
+    #[test]
+    fn sizes_are_capped() {
+        let value = fake(
+            json!({"type": "array", "minItems": 4294967295u64, "maxItems": 4294967295u64, "items": {"type": "boolean"}}),
+            0,
+        );
+        assert_eq!(value.as_array().unwrap().len(), MAX_ITEMS as usize);
+        let value = fake(json!({"type": "string", "minLength": 4294967295u64}), 0);
+        assert_eq!(value.as_str().unwrap().len(), MAX_LENGTH);
+    }
+
+    #[test]
+    fn combinators_pick_a_schema() {
+        let all_of = json!({"allOf": [
+            {"properties": {"a": {"const": 1}}},
+            {"properties": {"b": {"const": 2}}},
+        ]});
+        assert_eq!(fake(all_of, 0), json!({"a": 1, "b": 2}));
+        for seed in 0..16 {
+            let one_of = fake(json!({"oneOf": [{"const": 1}, {"const": "a"}]}), seed);
+            assert!(one_of == json!(1) || one_of == json!("a"));
+        }
+        assert_eq!(fake(json!({}), 0), json!(null));
+    }
+}
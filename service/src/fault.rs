@@ -0,0 +1,199 @@
+/// Transport and protocol level failures a mock can respond with
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Fault {
+    /// Connection is reset with a TCP RST before any response is written
+    ConnectionReset,
+    /// Connection is closed without writing any response
+    EmptyResponse,
+    /// Headers and half of the body are written, then the connection is closed
+    CloseMidBody,
+    /// Only the first half of the body is sent, with a matching content length
+    TruncatedBody,
+    /// Body is corrupted so that it can not be parsed as json
+    MalformedJson,
+    /// Content length is announced larger than the body actually sent
+    WrongContentLength,
+}
+
+/// Responds with the given status instead of the mock response for a random
+/// fraction of the requests
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RandomError {
+    /// Fraction of the requests to fail, between `0.0` and `1.0`
+    pub rate: f64,
+    #[serde(default = "default_error_status")]
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<crate::utils::ResponseBody>,
+}
+
+fn default_error_status() -> u16 {
+    500
+}
+
+impl RandomError {
+    pub fn triggered(&self) -> bool {
+        rand::random::<f64>() < self.rate
+    }
+
+    pub fn body(&self) -> crate::utils::ResponseBody {
+        self.body.clone().unwrap_or_else(|| {
+            crate::utils::ResponseBody::Json(serde_json::json!({
+                "success": false,
+                "message": "INJECTED_ERROR"
+            }))
+        })
+    }
+}
+
+/// Faults which can not be expressed as a response, the connection serving
+/// the request has to be dropped by the caller.
+#[derive(thiserror::Error, Debug)]
+pub enum Abort {
+    #[error("ConnectionReset")]
+    ConnectionReset,
+    #[error("ConnectionClosed")]
+    ConnectionClosed,
+}
+
+/// Announces `length` as the content length but sends only `body` and then
+/// closes the connection. The body is streamed so that the partial body is
+/// flushed to the client before hyper gives up on the connection.
+fn send_then_close(response: &mut hyper::Response<hyper::Body>, body: Vec<u8>, length: usize) {
+    response.headers_mut().insert(
+        hyper::header::CONTENT_LENGTH,
+        hyper::http::HeaderValue::from(length),
+    );
+    let (mut sender, streamed) = hyper::Body::channel();
+    tokio::spawn(async move {
+        if sender.send_data(body.into()).await.is_ok() {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        sender.abort();
+    });
+    *response.body_mut() = streamed;
+}
+
+/// Applies the fault on the response which would otherwise have been sent
+pub fn apply(
+    fault: &Fault,
+    mut response: hyper::Response<hyper::Body>,
+    body: Vec<u8>,
+) -> Result<hyper::Response<hyper::Body>, Abort> {
+    tracing::info!(target = "fault", fault = format!("{:?}", fault));
+    match fault {
+        Fault::ConnectionReset => return Err(Abort::ConnectionReset),
+        Fault::EmptyResponse => return Err(Abort::ConnectionClosed),
+        Fault::CloseMidBody => {
+            let length = body.len();
+            send_then_close(&mut response, body[..length / 2].to_vec(), length);
+        }
+        Fault::TruncatedBody => {
+            *response.body_mut() = hyper::Body::from(body[..body.len() / 2].to_vec());
+        }
+        Fault::MalformedJson => {
+            let mut malformed = body;
+            malformed.insert(malformed.len().min(1), b',');
+            if malformed.len() > 2 {
+                malformed.pop();
+            }
+            *response.body_mut() = hyper::Body::from(malformed);
+        }
+        Fault::WrongContentLength => {
+            let length = body.len() + 16;
+            send_then_close(&mut response, body, length);
+        }
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::HttpBody;
+
+    fn applied(fault: Fault, body: &[u8]) -> Result<hyper::Response<hyper::Body>, Abort> {
+        apply(
+            &fault,
+            hyper::Response::new(hyper::Body::empty()),
+            body.to_vec(),
+        )
+    }
+
+    /// Data sent before the body ends, and whether it ended in an error
+    async fn sent(response: hyper::Response<hyper::Body>) -> (Vec<u8>, bool) {
+        let mut body = response.into_body();
+        let mut data = vec![];
+        while let Some(chunk) = body.data().await {
+            match chunk {
+                Ok(chunk) => data.extend_from_slice(&chunk),
+                Err(_) => return (data, true),
+            }
+        }
+        (data, false)
+    }
+
+    fn content_length(response: &hyper::Response<hyper::Body>) -> Option<&str> {
+        response
+            .headers()
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    #[test]
+    fn connection_faults_abort_the_response() {
+        assert!(matches!(
+            applied(Fault::ConnectionReset, b"{}"),
+            Err(Abort::ConnectionReset)
+        ));
+        assert!(matches!(
+            applied(Fault::EmptyResponse, b"{}"),
+            Err(Abort::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn truncated_bodies_send_the_first_half() {
+        let response = applied(Fault::TruncatedBody, b"abcdefgh").expect("response");
+        assert_eq!(sent(response).await, (b"abcd".to_vec(), false));
+    }
+
+    #[tokio::test]
+    async fn malformed_json_does_not_parse() {
+        for body in [&b"{\"a\":1}"[..], b"[]", b""] {
+            let response = applied(Fault::MalformedJson, body).expect("response");
+            let (data, _) = sent(response).await;
+            assert!(serde_json::from_slice::<serde_json::Value>(&data).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn closed_mid_body_announces_the_whole_body() {
+        let response = applied(Fault::CloseMidBody, b"abcdefgh").expect("response");
+        assert_eq!(content_length(&response), Some("8"));
+        assert_eq!(sent(response).await, (b"abcd".to_vec(), true));
+    }
+
+    #[tokio::test]
+    async fn wrong_content_length_announces_more_than_sent() {
+        let response = applied(Fault::WrongContentLength, b"abcdefgh").expect("response");
+        assert_eq!(content_length(&response), Some("24"));
+        assert_eq!(sent(response).await, (b"abcdefgh".to_vec(), true));
+    }
+
+    #[test]
+    fn random_errors_follow_the_rate() {
+        let error = |rate| RandomError {
+            rate,
+            status: default_error_status(),
+            body: None,
+        };
+        assert!((0..100).all(|_| error(1.0).triggered()));
+        assert!((0..100).all(|_| !error(0.0).triggered()));
+        assert!(matches!(
+            error(1.0).body(),
+            crate::utils::ResponseBody::Json(body) if body["message"] == "INJECTED_ERROR"
+        ));
+    }
+}
@@ -0,0 +1,923 @@
+use crate::matcher::{CompiledRule, Mismatch, ValueMatch};
+use crate::utils::MockRequest;
+use graphql_parser::query::{
+    Definition, FragmentDefinition, OperationDefinition, Selection, SelectionSet, Type,
+    TypeCondition,
+};
+use std::collections::{HashMap, HashSet};
+
+#[derive(thiserror::Error, Debug)]
+pub enum GraphQLError {
+    #[error("IOError: {path}: {source}")]
+    IO {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("QueryError: {0}")]
+    Query(String),
+    #[error("SchemaError: {0}")]
+    Schema(#[from] graphql_parser::schema::ParseError),
+    #[error("MatcherError: {0}")]
+    Matcher(#[from] crate::matcher::MatcherError),
+}
+
+/// GraphQL operation a mock responds to. GraphQL apis serve every operation on
+/// a single path, so the mocks on that path are told apart by
+/// - `operation_name`, the `operationName` of the request, or the name of the
+///   only operation in the query if the request does not give one
+/// - `query`, compared with the query of the request regardless of the
+///   whitespace and the comments
+/// - `variables`, conditions on the variables by their name, nested values by
+///   a dotted path like `input.amount`
+///
+/// and respond with `data` and `errors` in the `{"data": .., "errors": [..]}`
+/// envelope unless the mock has a `body` or a `response`. Requests are read
+/// from a json `POST` body, an `application/graphql` body or the query of a
+/// `GET`.
+///
+/// With a `schema`, a path to an SDL file, the matched requests are validated
+/// against the schema and the invalid ones get a `400` with the `errors`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct GraphQL {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, ValueMatch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+}
+
+impl GraphQL {
+    /// Response body in the `{"data": .., "errors": [..]}` envelope, `None` if
+    /// the mock gives neither
+    pub fn envelope(&self) -> Option<serde_json::Value> {
+        if self.data.is_none() && self.errors.is_none() {
+            return None;
+        }
+        let mut envelope = serde_json::Map::new();
+        if let Some(data) = &self.data {
+            envelope.insert("data".to_string(), data.clone());
+        }
+        if let Some(errors) = &self.errors {
+            envelope.insert("errors".to_string(), errors.clone());
+        }
+        Some(serde_json::Value::Object(envelope))
+    }
+}
+
+/// Operation requested by a GraphQL request
+#[derive(Clone, Debug, Default)]
+pub struct Operation {
+    pub name: Option<String>,
+    pub query: Option<String>,
+    /// Query without the whitespace and the comments, `None` if it does not parse
+    pub normalized: Option<String>,
+    pub variables: serde_json::Value,
+}
+
+impl Operation {
+    /// Operation of the request, `None` if the request is not a GraphQL request
+    pub fn new(request: &MockRequest) -> Option<Self> {
+        let text = |value: Option<&serde_json::Value>| {
+            value.and_then(|v| v.as_str()).map(|v| v.to_string())
+        };
+        let (query, name, variables) = match &request.body {
+            serde_json::Value::Object(body)
+                if body.contains_key("query") || body.contains_key("operationName") =>
+            {
+                (
+                    text(body.get("query")),
+                    text(body.get("operationName")),
+                    body.get("variables").cloned().unwrap_or_default(),
+                )
+            }
+            serde_json::Value::String(query)
+                if request
+                    .headers
+                    .get("content-type")
+                    .map(|c| c.contains("application/graphql"))
+                    .unwrap_or(false) =>
+            {
+                (Some(query.to_string()), None, serde_json::Value::Null)
+            }
+            _ if request.method == "GET" && request.query.contains_key("query") => (
+                request.query.get("query").cloned(),
+                request.query.get("operationName").cloned(),
+                request
+                    .query
+                    .get("variables")
+                    .map(|v| serde_json::Value::String(v.to_string()))
+                    .unwrap_or_default(),
+            ),
+            _ => return None,
+        };
+        // some clients send the variables as a json string
+        let variables = match variables {
+            serde_json::Value::String(v) => serde_json::from_str(v.as_str()).unwrap_or_default(),
+            variables => variables,
+        };
+        let name = name.filter(|n| !n.is_empty()).or_else(|| {
+            let document = graphql_parser::parse_query::<String>(query.as_deref()?).ok()?;
+            let mut operations = document.definitions.iter().filter_map(|d| match d {
+                Definition::Operation(operation) => Some(operation),
+                Definition::Fragment(_) => None,
+            });
+            match (operations.next(), operations.next()) {
+                (Some(operation), None) => operation_name(operation).map(|n| n.to_string()),
+                _ => None,
+            }
+        });
+        Some(Operation {
+            normalized: query
+                .as_ref()
+                .and_then(|q| graphql_parser::minify_query(q.to_string()).ok()),
+            name,
+            query,
+            variables,
+        })
+    }
+}
+
+fn operation_name<'a>(operation: &'a OperationDefinition<'a, String>) -> Option<&'a str> {
+    match operation {
+        OperationDefinition::SelectionSet(_) => None,
+        OperationDefinition::Query(q) => q.name.as_deref(),
+        OperationDefinition::Mutation(m) => m.name.as_deref(),
+        OperationDefinition::Subscription(s) => s.name.as_deref(),
+    }
+}
+
+/// Error in the format of the GraphQL responses
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ValidationError {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<Location>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ValidationError {
+    fn new(message: String, position: Option<graphql_parser::Pos>) -> Self {
+        ValidationError {
+            message,
+            locations: position
+                .map(|p| {
+                    vec![Location {
+                        line: p.line,
+                        column: p.column,
+                    }]
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn named_type<'a>(ty: &'a Type<'_, String>) -> &'a str {
+    match ty {
+        Type::NamedType(name) => name.as_str(),
+        Type::ListType(ty) | Type::NonNullType(ty) => named_type(ty),
+    }
+}
+
+fn type_name(ty: &Type<'_, String>) -> String {
+    match ty {
+        Type::NamedType(name) => name.to_string(),
+        Type::ListType(ty) => format!("[{}]", type_name(ty)),
+        Type::NonNullType(ty) => format!("{}!", type_name(ty)),
+    }
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Object,
+    Union,
+    // scalars and enums, which have no fields to select
+    Leaf,
+    Input,
+}
+
+struct Argument {
+    name: String,
+    ty: String,
+    required: bool,
+}
+
+struct FieldDef {
+    arguments: Vec<Argument>,
+    ty: String,
+}
+
+struct TypeDef {
+    kind: Kind,
+    fields: HashMap<String, FieldDef>,
+}
+
+/// Types of an SDL schema, only what the queries are validated against
+pub struct Schema {
+    types: HashMap<String, TypeDef>,
+    query: String,
+    mutation: String,
+    subscription: String,
+}
+
+impl Schema {
+    pub fn parse(sdl: &str) -> Result<Self, GraphQLError> {
+        use graphql_parser::schema::{self, TypeDefinition, TypeExtension};
+        let fields = |fields: &[schema::Field<'_, String>]| {
+            fields
+                .iter()
+                .map(|f| {
+                    let field = FieldDef {
+                        arguments: f
+                            .arguments
+                            .iter()
+                            .map(|a| Argument {
+                                name: a.name.to_string(),
+                                ty: type_name(&a.value_type),
+                                required: matches!(a.value_type, Type::NonNullType(_))
+                                    && a.default_value.is_none(),
+                            })
+                            .collect(),
+                        ty: named_type(&f.field_type).to_string(),
+                    };
+                    (f.name.to_string(), field)
+                })
+                .collect::<Vec<_>>()
+        };
+        let document = graphql_parser::parse_schema::<String>(sdl)?;
+        let mut types: HashMap<String, TypeDef> = ["Int", "Float", "String", "Boolean", "ID"]
+            .iter()
+            .map(|name| {
+                let leaf = TypeDef {
+                    kind: Kind::Leaf,
+                    fields: Default::default(),
+                };
+                (name.to_string(), leaf)
+            })
+            .collect();
+        let mut roots = [
+            "Query".to_string(),
+            "Mutation".to_string(),
+            "Subscription".to_string(),
+        ];
+        for definition in &document.definitions {
+            let (name, kind, defined) = match definition {
+                schema::Definition::SchemaDefinition(s) => {
+                    for (root, name) in
+                        roots
+                            .iter_mut()
+                            .zip([&s.query, &s.mutation, &s.subscription])
+                    {
+                        if let Some(name) = name {
+                            *root = name.to_string();
+                        }
+                    }
+                    continue;
+                }
+                schema::Definition::TypeDefinition(t) => match t {
+                    TypeDefinition::Object(o) => (&o.name, Kind::Object, fields(&o.fields)),
+                    TypeDefinition::Interface(i) => (&i.name, Kind::Object, fields(&i.fields)),
+                    TypeDefinition::Union(u) => (&u.name, Kind::Union, vec![]),
+                    TypeDefinition::Scalar(s) => (&s.name, Kind::Leaf, vec![]),
+                    TypeDefinition::Enum(e) => (&e.name, Kind::Leaf, vec![]),
+                    TypeDefinition::InputObject(i) => (&i.name, Kind::Input, vec![]),
+                },
+                schema::Definition::TypeExtension(t) => match t {
+                    TypeExtension::Object(o) => (&o.name, Kind::Object, fields(&o.fields)),
+                    TypeExtension::Interface(i) => (&i.name, Kind::Object, fields(&i.fields)),
+                    _ => continue,
+                },
+                schema::Definition::DirectiveDefinition(_) => continue,
+            };
+            types
+                .entry(name.to_string())
+                .or_insert(TypeDef {
+                    kind,
+                    fields: Default::default(),
+                })
+                .fields
+                .extend(defined);
+        }
+        let [query, mutation, subscription] = roots;
+        Ok(Schema {
+            types,
+            query,
+            mutation,
+            subscription,
+        })
+    }
+
+    /// Every error of the operation against the schema, empty if it is valid
+    pub fn validate(&self, operation: &Operation) -> Vec<ValidationError> {
+        let Some(query) = &operation.query else {
+            return vec![ValidationError::new(
+                "Must provide query string.".to_string(),
+                None,
+            )];
+        };
+        let document = match graphql_parser::parse_query::<String>(query.as_str()) {
+            Ok(document) => document,
+            Err(e) => return vec![ValidationError::new(e.to_string(), None)],
+        };
+        let mut fragments = HashMap::new();
+        let mut operations = vec![];
+        for definition in &document.definitions {
+            match definition {
+                Definition::Operation(o) => operations.push(o),
+                Definition::Fragment(f) => {
+                    fragments.insert(f.name.as_str(), f);
+                }
+            }
+        }
+        let selected = match &operation.name {
+            Some(name) => operations
+                .iter()
+                .find(|o| operation_name(o) == Some(name.as_str())),
+            None if operations.len() == 1 => operations.first(),
+            None => {
+                return vec![ValidationError::new(
+                    "Must provide operation name if query contains multiple operations."
+                        .to_string(),
+                    None,
+                )]
+            }
+        };
+        let Some(selected) = selected else {
+            return vec![ValidationError::new(
+                format!(
+                    "Unknown operation named \"{}\".",
+                    operation.name.as_deref().unwrap_or_default()
+                ),
+                None,
+            )];
+        };
+        let (root, variables, selection_set) = match selected {
+            OperationDefinition::SelectionSet(s) => (&self.query, &[][..], s),
+            OperationDefinition::Query(q) => {
+                (&self.query, &q.variable_definitions[..], &q.selection_set)
+            }
+            OperationDefinition::Mutation(m) => (
+                &self.mutation,
+                &m.variable_definitions[..],
+                &m.selection_set,
+            ),
+            OperationDefinition::Subscription(s) => (
+                &self.subscription,
+                &s.variable_definitions[..],
+                &s.selection_set,
+            ),
+        };
+        let mut errors = vec![];
+        if !self.types.contains_key(root) {
+            errors.push(ValidationError::new(
+                format!("Schema does not define the root type \"{root}\"."),
+                Some(selection_set.span.0),
+            ));
+            return errors;
+        }
+        for variable in variables {
+            let given = operation
+                .variables
+                .get(variable.name.as_str())
+                .filter(|v| !v.is_null());
+            if matches!(variable.var_type, Type::NonNullType(_))
+                && variable.default_value.is_none()
+                && given.is_none()
+            {
+                errors.push(ValidationError::new(
+                    format!(
+                        "Variable \"${}\" of required type \"{}\" was not provided.",
+                        variable.name,
+                        type_name(&variable.var_type)
+                    ),
+                    Some(variable.position),
+                ));
+            }
+        }
+        let mut visited = HashSet::new();
+        self.selections(root, selection_set, &fragments, &mut visited, &mut errors);
+        errors
+    }
+
+    fn selections<'a>(
+        &self,
+        ty: &str,
+        selection_set: &'a SelectionSet<'a, String>,
+        fragments: &HashMap<&'a str, &'a FragmentDefinition<'a, String>>,
+        visited: &mut HashSet<&'a str>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let Some(definition) = self.types.get(ty) else {
+            return;
+        };
+        for selection in &selection_set.items {
+            match selection {
+                // introspection is left to the clients
+                Selection::Field(field) if field.name.starts_with("__") => {}
+                Selection::Field(field) => {
+                    let Some(field_def) = definition.fields.get(field.name.as_str()) else {
+                        errors.push(ValidationError::new(
+                            format!("Cannot query field \"{}\" on type \"{ty}\".", field.name),
+                            Some(field.position),
+                        ));
+                        continue;
+                    };
+                    for (name, _) in &field.arguments {
+                        if !field_def.arguments.iter().any(|a| a.name.eq(name)) {
+                            errors.push(ValidationError::new(
+                                format!(
+                                    "Unknown argument \"{name}\" on field \"{ty}.{}\".",
+                                    field.name
+                                ),
+                                Some(field.position),
+                            ));
+                        }
+                    }
+                    for argument in field_def.arguments.iter().filter(|a| a.required) {
+                        if !field
+                            .arguments
+                            .iter()
+                            .any(|(name, _)| argument.name.eq(name))
+                        {
+                            errors.push(ValidationError::new(
+                                format!(
+                                    "Field \"{}\" argument \"{}\" of type \"{}\" is required, but it was not provided.",
+                                    field.name, argument.name, argument.ty
+                                ),
+                                Some(field.position),
+                            ));
+                        }
+                    }
+                    let leaf = self
+                        .types
+                        .get(field_def.ty.as_str())
+                        .map(|t| t.kind == Kind::Leaf || t.kind == Kind::Input)
+                        .unwrap_or(true);
+                    match (leaf, field.selection_set.items.is_empty()) {
+                        (true, false) => errors.push(ValidationError::new(
+                            format!(
+                                "Field \"{}\" must not have a selection since type \"{}\" has no subfields.",
+                                field.name, field_def.ty
+                            ),
+                            Some(field.position),
+                        )),
+                        (false, true) => errors.push(ValidationError::new(
+                            format!(
+                                "Field \"{}\" of type \"{}\" must have a selection of subfields.",
+                                field.name, field_def.ty
+                            ),
+                            Some(field.position),
+                        )),
+                        (false, false) => self.selections(
+                            field_def.ty.as_str(),
+                            &field.selection_set,
+                            fragments,
+                            visited,
+                            errors,
+                        ),
+                        (true, true) => {}
+                    }
+                }
+                Selection::FragmentSpread(spread) => {
+                    let Some(fragment) = fragments.get(spread.fragment_name.as_str()) else {
+                        errors.push(ValidationError::new(
+                            format!("Unknown fragment \"{}\".", spread.fragment_name),
+                            Some(spread.position),
+                        ));
+                        continue;
+                    };
+                    // every fragment is checked once, which also stops the cycles
+                    if !visited.insert(fragment.name.as_str()) {
+                        continue;
+                    }
+                    let TypeCondition::On(on) = &fragment.type_condition;
+                    if self.condition(on, fragment.position, errors) {
+                        self.selections(on, &fragment.selection_set, fragments, visited, errors);
+                    }
+                }
+                Selection::InlineFragment(inline) => {
+                    let on = match &inline.type_condition {
+                        Some(TypeCondition::On(on)) => on.as_str(),
+                        None => ty,
+                    };
+                    if self.condition(on, inline.position, errors) {
+                        self.selections(on, &inline.selection_set, fragments, visited, errors);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the type of a fragment is one fields can be selected on
+    fn condition(
+        &self,
+        on: &str,
+        position: graphql_parser::Pos,
+        errors: &mut Vec<ValidationError>,
+    ) -> bool {
+        match self.types.get(on).map(|t| &t.kind) {
+            Some(Kind::Object) | Some(Kind::Union) => true,
+            Some(_) => {
+                errors.push(ValidationError::new(
+                    format!("Fragment cannot condition on non composite type \"{on}\"."),
+                    Some(position),
+                ));
+                false
+            }
+            None => {
+                errors.push(ValidationError::new(
+                    format!("Unknown type \"{on}\"."),
+                    Some(position),
+                ));
+                false
+            }
+        }
+    }
+}
+
+/// `GraphQL` with the query normalized, the variable rules compiled and the
+/// schema parsed at load time
+pub struct GraphQLMatcher {
+    operation_name: Option<String>,
+    query: Option<String>,
+    variables: Vec<(String, CompiledRule)>,
+    schema: Option<Schema>,
+}
+
+impl GraphQLMatcher {
+    pub fn new(graphql: &GraphQL) -> Result<Self, GraphQLError> {
+        let schema = match &graphql.schema {
+            Some(path) => {
+                let sdl = std::fs::read_to_string(path).map_err(|source| GraphQLError::IO {
+                    path: path.to_string(),
+                    source,
+                })?;
+                Some(Schema::parse(sdl.as_str())?)
+            }
+            None => None,
+        };
+        Ok(GraphQLMatcher {
+            operation_name: graphql.operation_name.clone(),
+            query: graphql
+                .query
+                .as_ref()
+                .map(|q| graphql_parser::minify_query(q.to_string()))
+                .transpose()
+                .map_err(|e| GraphQLError::Query(e.to_string()))?,
+            variables: graphql
+                .variables
+                .iter()
+                .map(|(name, rule)| Ok((name.to_string(), CompiledRule::new(rule)?)))
+                .collect::<Result<Vec<_>, GraphQLError>>()?,
+            schema,
+        })
+    }
+
+    pub fn matches(&self, operation: Option<&Operation>) -> bool {
+        self.mismatches(operation).is_empty()
+    }
+
+    /// Number of the conditions, for how close a request came to matching
+    pub fn len(&self) -> usize {
+        self.operation_name.iter().len() + self.query.iter().len() + self.variables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every condition the operation fails, empty if it matches
+    pub fn mismatches(&self, operation: Option<&Operation>) -> Vec<Mismatch> {
+        let string = |s: Option<&String>| {
+            s.map(|s| serde_json::Value::String(s.to_string()))
+                .unwrap_or_default()
+        };
+        let Some(operation) = operation else {
+            let mut mismatch = Mismatch::new(
+                "graphql".to_string(),
+                serde_json::Value::String("GraphQL request".to_string()),
+                serde_json::Value::Null,
+            );
+            mismatch.hint = Some("no query or operationName in the request".to_string());
+            return vec![mismatch];
+        };
+        let mut mismatches = vec![];
+        if let Some(name) = &self.operation_name {
+            if operation.name.as_ref() != Some(name) {
+                mismatches.push(Mismatch::new(
+                    "graphql.operation_name".to_string(),
+                    string(Some(name)),
+                    string(operation.name.as_ref()),
+                ));
+            }
+        }
+        if let Some(query) = &self.query {
+            if operation.normalized.as_ref() != Some(query) {
+                let mut mismatch = Mismatch::new(
+                    "graphql.query".to_string(),
+                    string(Some(query)),
+                    string(operation.normalized.as_ref().or(operation.query.as_ref())),
+                );
+                if operation.query.is_some() && operation.normalized.is_none() {
+                    mismatch.hint = Some("query of the request does not parse".to_string());
+                }
+                mismatches.push(mismatch);
+            }
+        }
+        for (name, rule) in &self.variables {
+            let value = crate::template::lookup(&operation.variables, name.as_str());
+            if !rule.matches(value) {
+                mismatches.push(Mismatch::new(
+                    format!("graphql.variables.{name}"),
+                    rule.rule.clone(),
+                    value.cloned().unwrap_or_default(),
+                ));
+            }
+        }
+        mismatches
+    }
+
+    /// Errors of the operation against the schema of the mock, empty if the
+    /// mock has no schema
+    pub fn validate(&self, operation: Option<&Operation>) -> Vec<ValidationError> {
+        match (&self.schema, operation) {
+            (Some(schema), Some(operation)) => schema.validate(operation),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SDL: &str = r#"
+        type Query {
+            user(id: ID!): User
+            users(first: Int = 10, role: Role): [User!]!
+            search(text: String!): [Result]
+        }
+        type User {
+            id: ID!
+            name: String
+            friends: [User]
+        }
+        type Bot {
+            id: ID!
+        }
+        union Result = User | Bot
+        enum Role { ADMIN MEMBER }
+    "#;
+
+    fn post(body: serde_json::Value) -> MockRequest {
+        MockRequest {
+            method: "POST".to_string(),
+            path: "/graphql".to_string(),
+            body,
+            ..Default::default()
+        }
+    }
+
+    fn operation(query: &str, name: Option<&str>, variables: serde_json::Value) -> Operation {
+        Operation::new(&post(json!({
+            "query": query,
+            "operationName": name,
+            "variables": variables,
+        })))
+        .expect("GraphQL request")
+    }
+
+    fn errors(query: &str, name: Option<&str>, variables: serde_json::Value) -> Vec<String> {
+        Schema::parse(SDL)
+            .expect("valid schema")
+            .validate(&operation(query, name, variables))
+            .into_iter()
+            .map(|e| e.message)
+            .collect()
+    }
+
+    #[test]
+    fn operations_are_read_from_the_requests() {
+        let operation = operation("query Users { users { id } }", None, json!({"first": 1}));
+        assert_eq!(operation.name.as_deref(), Some("Users"));
+        assert_eq!(
+            operation.normalized.as_deref(),
+            Some("query Users{users{id}}")
+        );
+        assert_eq!(operation.variables, json!({"first": 1}));
+
+        let request = MockRequest {
+            headers: [(
+                "content-type".to_string(),
+                "application/graphql".to_string(),
+            )]
+            .into_iter()
+            .collect(),
+            ..post(json!("{ users { id } }"))
+        };
+        let operation = Operation::new(&request).expect("GraphQL request");
+        assert_eq!(operation.query.as_deref(), Some("{ users { id } }"));
+        assert_eq!(operation.name, None);
+
+        let request = MockRequest {
+            method: "GET".to_string(),
+            query: [
+                ("query", "query A { users { id } }"),
+                ("variables", r#"{"first": 2}"#),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            ..Default::default()
+        };
+        let operation = Operation::new(&request).expect("GraphQL request");
+        assert_eq!(operation.name.as_deref(), Some("A"));
+        assert_eq!(operation.variables, json!({"first": 2}));
+
+        assert!(Operation::new(&post(json!({"id": 1}))).is_none());
+        assert!(Operation::new(&post(json!("{ users { id } }"))).is_none());
+    }
+
+    #[test]
+    fn operation_name_is_only_inferred_for_a_single_operation() {
+        let query = "query A { users { id } } query B { users { name } }";
+        assert_eq!(operation(query, None, json!(null)).name, None);
+        assert_eq!(
+            operation(query, Some("B"), json!(null)).name.as_deref(),
+            Some("B")
+        );
+        let query = "query A { users { ...F } } fragment F on User { id }";
+        assert_eq!(
+            operation(query, None, json!(null)).name.as_deref(),
+            Some("A")
+        );
+        assert_eq!(
+            operation("{ users { id } }", Some(""), json!(null)).name,
+            None
+        );
+    }
+
+    #[test]
+    fn queries_match_regardless_of_whitespace_and_comments() {
+        let matcher = GraphQLMatcher::new(&GraphQL {
+            query: Some("query Users {\n  users {\n    id # the id\n  }\n}".to_string()),
+            ..Default::default()
+        })
+        .expect("valid matcher");
+        let same = operation("query Users{users{id}}", None, json!(null));
+        assert!(matcher.matches(Some(&same)));
+        let other = operation("query Users { users { name } }", None, json!(null));
+        assert!(!matcher.matches(Some(&other)));
+        let broken = operation("query Users { users(name: \"a) }", None, json!(null));
+        let mismatches = matcher.mismatches(Some(&broken));
+        assert_eq!(
+            mismatches[0].hint.as_deref(),
+            Some("query of the request does not parse")
+        );
+        assert!(GraphQLMatcher::new(&GraphQL {
+            query: Some("query { \"".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn operation_name_and_variables_are_matched() {
+        let graphql: GraphQL = serde_json::from_value(json!({
+            "operation_name": "Transfer",
+            "variables": {"input.amount": {"equals": 10}, "dry_run": {"present": false}},
+        }))
+        .expect("valid mock");
+        let matcher = GraphQLMatcher::new(&graphql).expect("valid matcher");
+        let query = "mutation Transfer($input: Input) { transfer(input: $input) }";
+        let matching = operation(query, None, json!({"input": {"amount": 10}}));
+        assert!(matcher.matches(Some(&matching)));
+        let failing = operation(
+            "mutation Other { other }",
+            None,
+            json!({"input": {"amount": 5}, "dry_run": true}),
+        );
+        let mut criteria: Vec<_> = matcher
+            .mismatches(Some(&failing))
+            .into_iter()
+            .map(|m| m.criterion)
+            .collect();
+        criteria.sort();
+        assert_eq!(
+            criteria,
+            vec![
+                "graphql.operation_name",
+                "graphql.variables.dry_run",
+                "graphql.variables.input.amount",
+            ]
+        );
+        assert_eq!(matcher.mismatches(None)[0].criterion, "graphql");
+    }
+
+    #[test]
+    fn valid_operations_have_no_errors() {
+        let query = r#"
+            query Search($text: String!) {
+                __typename
+                user(id: "1") { ...Names friends { id } }
+                search(text: $text) {
+                    ... on User { name }
+                    ... on Bot { id }
+                }
+            }
+            fragment Names on User { name ...Names }
+        "#;
+        assert!(errors(query, None, json!({"text": "a"})).is_empty());
+        assert!(errors("{ users(role: ADMIN) { id } }", None, json!(null)).is_empty());
+    }
+
+    #[test]
+    fn operation_selection_errors() {
+        let query = "query A { users { id } } query B { users { id } }";
+        assert_eq!(
+            errors(query, None, json!(null)),
+            vec!["Must provide operation name if query contains multiple operations."]
+        );
+        assert_eq!(
+            errors(query, Some("C"), json!(null)),
+            vec!["Unknown operation named \"C\"."]
+        );
+        assert_eq!(
+            errors("mutation { users { id } }", None, json!(null)),
+            vec!["Schema does not define the root type \"Mutation\"."]
+        );
+        assert_eq!(
+            Schema::parse(SDL)
+                .expect("valid schema")
+                .validate(&Operation::default())[0]
+                .message,
+            "Must provide query string."
+        );
+    }
+
+    #[test]
+    fn field_and_argument_errors() {
+        let errors = errors(
+            r#"query Q($text: String!) { user { nope } users(after: 1) search(text: "a") { id } }"#,
+            None,
+            json!({"text": null}),
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Variable \"$text\" of required type \"String!\" was not provided.",
+                "Field \"user\" argument \"id\" of type \"ID!\" is required, but it was not provided.",
+                "Cannot query field \"nope\" on type \"User\".",
+                "Unknown argument \"after\" on field \"Query.users\".",
+                "Field \"users\" of type \"User\" must have a selection of subfields.",
+                "Cannot query field \"id\" on type \"Result\".",
+            ]
+        );
+    }
+
+    #[test]
+    fn fragment_errors() {
+        let errors = errors(
+            r#"{ user(id: "1") { id { x } ...Missing ... on Role { id } ... on Nope { id } } }"#,
+            None,
+            json!(null),
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Field \"id\" must not have a selection since type \"ID\" has no subfields.",
+                "Unknown fragment \"Missing\".",
+                "Fragment cannot condition on non composite type \"Role\".",
+                "Unknown type \"Nope\".",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_have_the_locations() {
+        let schema = Schema::parse(SDL).expect("valid schema");
+        let found = schema.validate(&operation("{\n  nope\n}", None, json!(null)));
+        assert_eq!(
+            serde_json::to_value(&found).expect("serializable"),
+            json!([{
+                "message": "Cannot query field \"nope\" on type \"Query\".",
+                "locations": [{"line": 2, "column": 3}],
+            }])
+        );
+        let found = schema.validate(&operation("{ users {", None, json!(null)));
+        assert!(found[0].locations.is_empty());
+        assert!(Schema::parse("type {").is_err());
+    }
+}
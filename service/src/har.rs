@@ -0,0 +1,421 @@
+use crate::utils::API;
+
+/// HTTP Archive 1.2, only the parts used by the import and the export
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Har {
+    pub log: Log,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Log {
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub creator: Creator,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Creator {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    #[serde(default)]
+    pub started_date_time: String,
+    #[serde(default)]
+    pub time: f64,
+    pub request: Request,
+    pub response: Response,
+    #[serde(default)]
+    pub cache: serde_json::Value,
+    #[serde(default)]
+    pub timings: serde_json::Value,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct NameValue {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub http_version: String,
+    #[serde(default)]
+    pub headers: Vec<NameValue>,
+    #[serde(default)]
+    pub query_string: Vec<NameValue>,
+    #[serde(default)]
+    pub cookies: Vec<NameValue>,
+    #[serde(default = "unknown_size")]
+    pub headers_size: i64,
+    #[serde(default = "unknown_size")]
+    pub body_size: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<PostData>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostData {
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    pub status: u16,
+    #[serde(default)]
+    pub status_text: String,
+    #[serde(default)]
+    pub http_version: String,
+    #[serde(default)]
+    pub headers: Vec<NameValue>,
+    #[serde(default)]
+    pub cookies: Vec<NameValue>,
+    #[serde(default)]
+    pub content: Content,
+    #[serde(default, rename = "redirectURL")]
+    pub redirect_url: String,
+    #[serde(default = "unknown_size")]
+    pub headers_size: i64,
+    #[serde(default = "unknown_size")]
+    pub body_size: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    #[serde(default)]
+    pub size: i64,
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// `base64` for the binary content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+}
+
+fn unknown_size() -> i64 {
+    -1
+}
+
+/// Mocks from the HAR entries, the last entry wins among the ones with the
+/// same method and path, or the same body as well with `match_body`. Entries
+/// which never got a response, or with a content which fails to decode, are
+/// skipped.
+pub fn import(har: Har, match_body: bool) -> Vec<API> {
+    use base64::Engine;
+    let apis = har
+        .log
+        .entries
+        .into_iter()
+        .filter(|entry| entry.response.status > 0)
+        .filter_map(|entry| {
+            let content = entry.response.content;
+            let text = content.text.unwrap_or_default();
+            let body = match content.encoding.as_deref() {
+                Some("base64") => {
+                    match base64::engine::general_purpose::STANDARD.decode(text.as_bytes()) {
+                        Ok(body) => body,
+                        Err(e) => {
+                            tracing::warn!(
+                                target = "HarImportError",
+                                "Skipped: {} {}: {}",
+                                entry.request.method,
+                                entry.request.url,
+                                e
+                            );
+                            return None;
+                        }
+                    }
+                }
+                _ => text.into_bytes(),
+            };
+            let mut headers: Vec<(String, String)> = entry
+                .response
+                .headers
+                .into_iter()
+                .map(|h| (h.name, h.value))
+                .collect();
+            if !content.mime_type.is_empty()
+                && !headers
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            {
+                headers.push(("content-type".to_string(), content.mime_type));
+            }
+            Some(
+                crate::recorder::Exchange {
+                    method: entry.request.method,
+                    url: entry.request.url,
+                    request_body: entry.request.post_data.and_then(|p| p.text),
+                    status: entry.response.status,
+                    headers,
+                    body,
+                }
+                .into_api("har", match_body),
+            )
+        })
+        .collect();
+    crate::recorder::dedupe(apis)
+}
+
+fn name_values(map: &std::collections::HashMap<String, String>) -> Vec<NameValue> {
+    let mut values: Vec<NameValue> = map
+        .iter()
+        .map(|(name, value)| NameValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+        .collect();
+    values.sort_by(|a, b| a.name.cmp(&b.name));
+    values
+}
+
+/// HAR of the journal entries, urls are made absolute with the `host` header
+pub fn export(entries: &[crate::journal::Entry]) -> Har {
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            let request = &entry.request;
+            let host = request
+                .headers
+                .get("host")
+                .map(|h| h.as_str())
+                .unwrap_or("localhost");
+            let query: String = form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(request.query.iter())
+                .finish();
+            let url = match query.is_empty() {
+                true => format!("http://{}{}", host, request.path),
+                false => format!("http://{}{}?{}", host, request.path, query),
+            };
+            let mime_type = |default: &str| {
+                request
+                    .headers
+                    .get("content-type")
+                    .cloned()
+                    .unwrap_or_else(|| default.to_string())
+            };
+            let post_data = match &request.body {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(text) => Some(PostData {
+                    mime_type: mime_type("text/plain"),
+                    text: Some(text.to_string()),
+                }),
+                body => Some(PostData {
+                    mime_type: mime_type("application/json"),
+                    text: Some(body.to_string()),
+                }),
+            };
+            let response = entry.response.clone().unwrap_or_default();
+            let (text, encoding) = match (response.body, response.body_base64) {
+                (Some(text), _) => (Some(text), None),
+                (None, Some(encoded)) => (Some(encoded), Some("base64".to_string())),
+                (None, None) => (None, None),
+            };
+            let mime_type = response
+                .headers
+                .get("content-type")
+                .cloned()
+                .unwrap_or_default();
+            Entry {
+                started_date_time: entry.logged_at.to_string(),
+                time: entry.duration_ms as f64,
+                request: Request {
+                    method: request.method.to_string(),
+                    url,
+                    http_version: "HTTP/1.1".to_string(),
+                    headers: name_values(&request.headers),
+                    query_string: name_values(&request.query),
+                    cookies: vec![],
+                    headers_size: -1,
+                    body_size: post_data
+                        .as_ref()
+                        .and_then(|p| p.text.as_ref())
+                        .map(|t| t.len() as i64)
+                        .unwrap_or(0),
+                    post_data,
+                },
+                response: Response {
+                    status: response.status,
+                    status_text: hyper::StatusCode::from_u16(response.status)
+                        .ok()
+                        .and_then(|s| s.canonical_reason())
+                        .unwrap_or_default()
+                        .to_string(),
+                    http_version: "HTTP/1.1".to_string(),
+                    headers: name_values(&response.headers),
+                    cookies: vec![],
+                    content: Content {
+                        size: text.as_ref().map(|t| t.len() as i64).unwrap_or(0),
+                        mime_type,
+                        text,
+                        encoding,
+                    },
+                    redirect_url: String::new(),
+                    headers_size: -1,
+                    body_size: -1,
+                },
+                cache: serde_json::json!({}),
+                timings: serde_json::json!({
+                    "send": 0,
+                    "wait": entry.duration_ms,
+                    "receive": 0,
+                }),
+            }
+        })
+        .collect();
+    Har {
+        log: Log {
+            version: "1.2".to_string(),
+            creator: Creator {
+                name: "webgenix".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            entries,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn har(entries: serde_json::Value) -> Har {
+        serde_json::from_value(json!({"log": {"entries": entries}})).expect("valid har")
+    }
+
+    fn entry(
+        method: &str,
+        url: &str,
+        status: u16,
+        content: serde_json::Value,
+    ) -> serde_json::Value {
+        json!({
+            "request": {"method": method, "url": url, "postData": {"mimeType": "application/json", "text": "{\"a\":1}"}},
+            "response": {"status": status, "headers": [{"name": "Set-Cookie", "value": "a=b"}], "content": content}
+        })
+    }
+
+    #[test]
+    fn entries_become_mocks() {
+        let apis = import(
+            har(json!([entry(
+                "get",
+                "https://api.example.com/users/1?expand=true",
+                200,
+                json!({"mimeType": "application/json", "text": "{\"id\":1}"})
+            )])),
+            false,
+        );
+        assert_eq!(apis.len(), 1);
+        let api = &apis[0];
+        assert_eq!(
+            (api.method.as_str(), api.path.as_str()),
+            ("GET", "/users/1")
+        );
+        assert_eq!(api.status, Some(200));
+        assert_eq!(api.tags, ["har"]);
+        assert_eq!(api.headers["content-type"], "application/json");
+        assert!(!api.headers.contains_key("set-cookie"));
+        assert!(api.request.is_none());
+        assert!(matches!(
+            &api.body,
+            Some(crate::utils::ResponseBody::Json(body)) if *body == json!({"id": 1})
+        ));
+    }
+
+    #[test]
+    fn binary_content_is_decoded_and_broken_entries_are_skipped() {
+        let apis = import(
+            har(json!([
+                entry(
+                    "GET",
+                    "/image",
+                    200,
+                    json!({"mimeType": "image/png", "text": "iVBORw==", "encoding": "base64"})
+                ),
+                entry(
+                    "GET",
+                    "/broken",
+                    200,
+                    json!({"text": "not base64!", "encoding": "base64"})
+                ),
+                entry("GET", "/aborted", 0, json!({}))
+            ])),
+            false,
+        );
+        let paths: Vec<&str> = apis.iter().map(|api| api.path.as_str()).collect();
+        assert_eq!(paths, ["/image"]);
+        assert!(matches!(
+            &apis[0].body,
+            Some(crate::utils::ResponseBody::Base64(encoded)) if encoded == "iVBORw=="
+        ));
+    }
+
+    #[test]
+    fn last_entry_wins_among_the_same_requests() {
+        let entries = har(json!([
+            entry("POST", "/orders", 500, json!({"text": "first"})),
+            entry("POST", "/orders", 201, json!({"text": "second"}))
+        ]));
+        let apis = import(entries.clone(), false);
+        assert_eq!(apis.len(), 1);
+        assert_eq!(apis[0].status, Some(201));
+        let apis = import(entries, true);
+        assert_eq!(apis.len(), 1);
+        assert_eq!(
+            apis[0].request.as_ref().and_then(|r| r.body.clone()),
+            Some(json!({"a": 1}))
+        );
+    }
+
+    #[test]
+    fn journal_is_exported_with_absolute_urls() {
+        let entry = crate::journal::Entry {
+            request: crate::utils::MockRequest {
+                method: "POST".to_string(),
+                path: "/orders".to_string(),
+                query: [("page".to_string(), "2".to_string())].into(),
+                headers: [("host".to_string(), "mock:8000".to_string())].into(),
+                body: json!({"a": 1}),
+            },
+            response: Some(crate::journal::LoggedResponse {
+                status: 201,
+                body: Some("{}".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let exported = export(&[entry]);
+        let exported = &exported.log.entries[0];
+        assert_eq!(exported.request.url, "http://mock:8000/orders?page=2");
+        assert_eq!(
+            exported
+                .request
+                .post_data
+                .as_ref()
+                .and_then(|p| p.text.as_deref()),
+            Some("{\"a\":1}")
+        );
+        assert_eq!(exported.response.status, 201);
+        assert_eq!(exported.response.status_text, "Created");
+        assert_eq!(exported.response.content.text.as_deref(), Some("{}"));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::utils::MockRequest;
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct LoggedResponse {
+    pub status: u16,
+    pub headers: std::collections::HashMap<String, String>,
+    /// Body as text, binary bodies are kept in `body_base64` instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_base64: Option<String>,
+}
+
+impl LoggedResponse {
+    pub fn new(response: &hyper::Response<hyper::Body>, body: &[u8]) -> Self {
+        use base64::Engine;
+        let (body, body_base64) = match std::str::from_utf8(body) {
+            Ok(text) => (Some(text.to_string()), None),
+            Err(_) => (
+                None,
+                Some(base64::engine::general_purpose::STANDARD.encode(body)),
+            ),
+        };
+        LoggedResponse {
+            status: response.status().as_u16(),
+            headers: response
+                .headers()
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        k.as_str().to_string(),
+                        String::from_utf8_lossy(v.as_bytes()).to_string(),
+                    )
+                })
+                .collect(),
+            body,
+            body_base64,
+        }
+    }
+}
+
+/// A request served by the mocks along with the response sent
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Entry {
+    pub id: String,
+    pub logged_at: String,
+    pub request: MockRequest,
+    /// Id of the mock that matched, `None` for the unmatched requests
+    pub matched: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<LoggedResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<crate::fault::Fault>,
+    pub duration_ms: u64,
+}
+
+impl Entry {
+    pub fn new(request: &MockRequest) -> Self {
+        Entry {
+            id: uuid::Uuid::new_v4().to_string(),
+            logged_at: chrono::Utc::now().to_rfc3339(),
+            request: request.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// `JOURNAL_LIMIT`, read once, see `init`
+static LIMIT: LazyLock<usize> = LazyLock::new(crate::utils::read_journal_limit);
+
+/// Reads the `JOURNAL_LIMIT` at the startup, so that a bad value stops the
+/// service right away instead of failing the requests
+pub fn init() {
+    LazyLock::force(&LIMIT);
+}
+
+pub fn limit() -> usize {
+    *LIMIT
+}
+
+static JOURNAL: LazyLock<Mutex<VecDeque<Entry>>> = LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+// Entries are appended to the `JOURNAL_FILE` by a background task so the
+// requests never wait on the disk
+static WRITER: LazyLock<Option<tokio::sync::mpsc::UnboundedSender<String>>> = LazyLock::new(|| {
+    let path = crate::utils::read_journal_file()?;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        use tokio::io::AsyncWriteExt;
+        let mut file = match tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_str())
+            .await
+        {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::error!(target = "JournalFileError", "Error: {}: {}", path, e);
+                return;
+            }
+        };
+        while let Some(line) = receiver.recv().await {
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                tracing::error!(target = "JournalFileError", "Error: {}: {}", path, e);
+            }
+        }
+    });
+    Some(sender)
+});
+
+/// Records the entry, oldest entries are dropped beyond the `JOURNAL_LIMIT`
+pub fn record(entry: Entry) {
+    if let Some(writer) = WRITER.as_ref() {
+        if let Ok(line) = serde_json::to_string(&entry) {
+            let _ = writer.send(line + "\n");
+        }
+    }
+    let limit = limit();
+    let mut journal = JOURNAL.lock().expect("journal lock poisoned");
+    journal.push_back(entry);
+    while limit > 0 && journal.len() > limit {
+        journal.pop_front();
+    }
+}
+
+pub fn entries() -> Vec<Entry> {
+    JOURNAL
+        .lock()
+        .expect("journal lock poisoned")
+        .iter()
+        .cloned()
+        .collect()
+}
+
+pub fn clear() {
+    JOURNAL.lock().expect("journal lock poisoned").clear();
+}
+
+/// Criteria to find the journal entries, same conditions as the mocks along
+/// with an optional method and path template
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Criteria {
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Only the matched, `true`, or only the unmatched, `false`, requests
+    #[serde(default)]
+    pub matched: Option<bool>,
+    #[serde(flatten)]
+    pub request: crate::matcher::RequestMatch,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CriteriaError {
+    #[error("PathError: {0}")]
+    Path(#[from] crate::path::PathError),
+    #[error("MatcherError: {0}")]
+    Matcher(#[from] crate::matcher::MatcherError),
+}
+
+pub fn find(criteria: &Criteria) -> Result<Vec<Entry>, CriteriaError> {
+    let path = criteria
+        .path
+        .as_ref()
+        .map(|p| crate::path::PathPattern::parse(p.as_str()))
+        .transpose()?;
+    let matcher = crate::matcher::RequestMatcher::new(&criteria.request)?;
+    Ok(JOURNAL
+        .lock()
+        .expect("journal lock poisoned")
+        .iter()
+        .filter(|entry| {
+            criteria
+                .method
+                .as_ref()
+                .map(|m| m.eq_ignore_ascii_case(entry.request.method.as_str()))
+                .unwrap_or(true)
+                && path
+                    .as_ref()
+                    .map(|p| p.matches(entry.request.path.as_str()).is_some())
+                    .unwrap_or(true)
+                && criteria
+                    .matched
+                    .map(|m| m == entry.matched.is_some())
+                    .unwrap_or(true)
+                && matcher.matches(&entry.request)
+        })
+        .cloned()
+        .collect())
+}
+
+/// Expected number of requests, `count` is exact, `at_least` and `at_most`
+/// are the bounds
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Verification {
+    #[serde(flatten)]
+    pub criteria: Criteria,
+    #[serde(default)]
+    pub count: Option<usize>,
+    #[serde(default)]
+    pub at_least: Option<usize>,
+    #[serde(default)]
+    pub at_most: Option<usize>,
+}
+
+impl Verification {
+    pub fn holds(&self, actual: usize) -> bool {
+        self.count.map(|c| c == actual).unwrap_or(true)
+            && self.at_least.map(|c| actual >= c).unwrap_or(true)
+            && self.at_most.map(|c| actual <= c).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // every test records under its own path, the journal is shared
+    fn record_request(method: &str, path: &str, header: &str, matched: Option<&str>) {
+        let request = MockRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: [("x-tenant".to_string(), header.to_string())].into(),
+            ..Default::default()
+        };
+        record(Entry {
+            matched: matched.map(str::to_string),
+            ..Entry::new(&request)
+        });
+    }
+
+    fn criteria(criteria: serde_json::Value) -> Criteria {
+        serde_json::from_value(criteria).expect("valid criteria")
+    }
+
+    #[test]
+    fn entries_are_found_by_method_path_and_matchers() {
+        record_request("GET", "/find/users/1", "a", Some("users"));
+        record_request("POST", "/find/users/2", "b", Some("users"));
+        record_request("GET", "/find/other", "a", None);
+        let found = |c| find(&criteria(c)).expect("valid criteria").len();
+        assert_eq!(found(json!({"path": "/find/users/{id}"})), 2);
+        assert_eq!(
+            found(json!({"method": "get", "path": "/find/users/{id}"})),
+            1
+        );
+        assert_eq!(found(json!({"path": "/find/*", "matched": false})), 1);
+        assert_eq!(
+            found(json!({"path": "/find/*", "headers": {"x-tenant": "a"}})),
+            2
+        );
+    }
+
+    #[test]
+    fn invalid_criteria_are_refused() {
+        assert!(matches!(
+            find(&criteria(json!({"path": "/*/a"}))),
+            Err(CriteriaError::Path(_))
+        ));
+        assert!(matches!(
+            find(&criteria(json!({"headers": {"a": {"regex": "("}}}))),
+            Err(CriteriaError::Matcher(_))
+        ));
+    }
+
+    #[test]
+    fn verifications_hold_within_the_bounds() {
+        record_request("GET", "/verify", "a", Some("verify"));
+        record_request("GET", "/verify", "a", Some("verify"));
+        let verification = |v| -> Verification { serde_json::from_value(v).expect("valid") };
+        let holds = |v: serde_json::Value| {
+            let v = verification(v);
+            v.holds(find(&v.criteria).expect("valid criteria").len())
+        };
+        assert!(holds(json!({"path": "/verify", "count": 2})));
+        assert!(!holds(json!({"path": "/verify", "count": 1})));
+        assert!(holds(
+            json!({"path": "/verify", "at_least": 1, "at_most": 2})
+        ));
+        assert!(!holds(json!({"path": "/verify", "at_most": 1})));
+        assert!(!holds(json!({"path": "/verify", "at_least": 3})));
+        // no expectation at all holds for any number
+        assert!(holds(json!({"path": "/verify"})));
+    }
+}
@@ -0,0 +1,83 @@
+use rand_distr::Distribution;
+use std::sync::LazyLock;
+
+// `LATENCY_MULTIPLIER`, read once, see `init`
+static MULTIPLIER: LazyLock<f64> = LazyLock::new(crate::utils::read_latency_multiplier);
+
+/// Reads the `LATENCY_MULTIPLIER` at the startup, so that a bad value stops the
+/// service right away instead of failing the delayed requests
+pub fn init() {
+    LazyLock::force(&MULTIPLIER);
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LatencyError {
+    #[error("InvalidUniform: min {min} is greater than max {max}")]
+    InvalidUniform { min: u64, max: u64 },
+    #[error("InvalidNormal: {0}")]
+    InvalidNormal(#[from] rand_distr::NormalError),
+}
+
+/// Delay applied to a mock response, sampled for every request, all the values
+/// are in milliseconds.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Latency {
+    Fixed {
+        millis: u64,
+    },
+    Uniform {
+        min: u64,
+        max: u64,
+    },
+    Normal {
+        mean: f64,
+        std_dev: f64,
+    },
+    /// `median` of the distribution and `sigma` of the underlying normal
+    /// distribution, gives the long tail seen in real backends
+    LogNormal {
+        median: f64,
+        sigma: f64,
+    },
+}
+
+impl Latency {
+    pub fn validate(&self) -> Result<(), LatencyError> {
+        match self {
+            Latency::Fixed { .. } => {}
+            Latency::Uniform { min, max } => {
+                if min > max {
+                    return Err(LatencyError::InvalidUniform {
+                        min: *min,
+                        max: *max,
+                    });
+                }
+            }
+            Latency::Normal { mean, std_dev } => {
+                rand_distr::Normal::new(*mean, *std_dev)?;
+            }
+            Latency::LogNormal { median, sigma } => {
+                rand_distr::LogNormal::new(median.ln(), *sigma)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Samples the delay and scales it by the global `LATENCY_MULTIPLIER`
+    pub fn sample(&self) -> std::time::Duration {
+        let mut rng = rand::thread_rng();
+        let millis = match self {
+            Latency::Fixed { millis } => *millis as f64,
+            Latency::Uniform { min, max } => rand::Rng::gen_range(&mut rng, *min..=*max) as f64,
+            Latency::Normal { mean, std_dev } => rand_distr::Normal::new(*mean, *std_dev)
+                .map(|d| d.sample(&mut rng))
+                .unwrap_or(*mean),
+            Latency::LogNormal { median, sigma } => rand_distr::LogNormal::new(median.ln(), *sigma)
+                .map(|d| d.sample(&mut rng))
+                .unwrap_or(*median),
+        };
+        let millis = millis * *MULTIPLIER;
+        std::time::Duration::try_from_secs_f64(millis.max(0.0) / 1000.0).unwrap_or_default()
+    }
+}
@@ -1,8 +1,32 @@
 extern crate self as http_service;
 
+pub mod admin;
+pub mod body;
+pub mod contract;
 pub mod controller;
 pub mod errors;
+pub mod fake;
+pub mod fault;
+pub mod graphql;
+pub mod har;
+pub mod journal;
+pub mod latency;
+pub mod openapi;
+pub mod path;
+pub mod plugin;
+pub mod postman;
+pub mod recorder;
+pub mod registry;
 pub mod router;
+pub mod scenario;
+pub mod schema;
+pub mod script;
+pub mod store;
+pub mod stream;
+pub mod template;
+pub mod webhook;
 #[macro_use]
 pub mod macros;
+pub mod matcher;
+pub mod nearmiss;
 pub mod utils;
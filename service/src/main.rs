@@ -1,8 +1,11 @@
-pub struct HttpService;
+pub struct HttpService {
+    // kept to reset the connection on the `connection_reset` fault
+    stream: std::sync::Arc<std::net::TcpStream>,
+}
 
 impl hyper::service::Service<hyper::Request<hyper::Body>> for HttpService {
     type Response = hyper::Response<hyper::Body>;
-    type Error = hyper::Error;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
     type Future = std::pin::Pin<
         Box<dyn futures::Future<Output = Result<Self::Response, Self::Error>> + Send>,
     >;
@@ -15,9 +18,19 @@ impl hyper::service::Service<hyper::Request<hyper::Body>> for HttpService {
     }
 
     fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
-        Box::pin(async {
+        let stream = self.stream.clone();
+        Box::pin(async move {
             match service::router::handler(req).await {
                 Ok(r) => Ok(r),
+                Err(service::errors::RouteError::FaultAbort(abort)) => {
+                    // dropping the connection with zero linger sends a RST
+                    // instead of the graceful FIN
+                    if let service::fault::Abort::ConnectionReset = abort {
+                        socket2::SockRef::from(stream.as_ref())
+                            .set_linger(Some(std::time::Duration::ZERO))?;
+                    }
+                    Err(abort.into())
+                }
                 Err(e) => {
                     tracing::error!(target = "ServerHandlerError", "Error: {}", e);
                     Ok(service::router::response(
@@ -39,6 +52,13 @@ async fn http_main() -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
     let env_path = format!("{}.env", service::utils::read_env());
     dotenv::from_path(env_path.as_str()).ok();
     tracing::info!("Environment set: {}", env_path);
+    service::latency::init();
+    service::journal::init();
+
+    // Loading the mocks and watching the files for changes
+    service::plugin::init();
+    service::registry::init();
+    tokio::spawn(service::registry::watch());
 
     // Initializing the database pool
     // db::pg::init_db_pool();
@@ -65,12 +85,15 @@ async fn http_main() -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
 
     loop {
         let (tcp_stream, _) = listener.accept().await?;
+        let std_stream = tcp_stream.into_std()?;
+        let stream = std::sync::Arc::new(std_stream.try_clone()?);
+        let tcp_stream = tokio::net::TcpStream::from_std(std_stream)?;
         tokio::task::spawn(async move {
             if let Err(http_err) = hyper::server::conn::Http::new()
                 .http1_only(true)
                 .http2_max_header_list_size(16 * 10 * 1024)
                 .http1_keep_alive(true)
-                .serve_connection(tcp_stream, HttpService {})
+                .serve_connection(tcp_stream, HttpService { stream })
                 .with_upgrades()
                 .await
             {
@@ -0,0 +1,621 @@
+use crate::utils::MockRequest;
+use std::collections::HashMap;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MatcherError {
+    #[error("InvalidRegex: {0}")]
+    InvalidRegex(#[from] regex::Error),
+    #[error("InvalidJsonPath: {0}")]
+    InvalidJsonPath(String),
+}
+
+/// Condition on a single value of the request, a plain string is an exact
+/// match, otherwise every given rule needs to hold
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ValueMatch {
+    Equals(String),
+    Rule(MatchRule),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct MatchRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// `true` if the value must be present, `false` if it must be absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub present: Option<bool>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct JsonPathMatch {
+    /// JSONPath into the request body, like `$.transactions[0].did` or `$.items[*].id`
+    pub path: String,
+    #[serde(flatten)]
+    pub rule: MatchRule,
+}
+
+/// Conditions on the request besides the method and path, all of them need to
+/// hold for the mock to match
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct RequestMatch {
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub query: HashMap<String, ValueMatch>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, ValueMatch>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub cookies: HashMap<String, ValueMatch>,
+    /// Partial json, every key given here must be equal in the request body,
+    /// keys not given here are ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub json_path: Vec<JsonPathMatch>,
+    /// Regex on the request body, json bodies are matched in their serialized form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_regex: Option<String>,
+}
+
+/// Criterion of a mock the request failed, see `crate::nearmiss`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Mismatch {
+    /// `method`, `path`, `query.<name>`, `headers.<name>`, `cookies.<name>`,
+    /// `body`, `json_path <path>`, `body_regex` or `graphql.<criterion>`
+    pub criterion: String,
+    pub expected: serde_json::Value,
+    /// `null` if the request does not have the value at all
+    pub actual: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Mismatch {
+    pub fn new(criterion: String, expected: serde_json::Value, actual: serde_json::Value) -> Self {
+        Mismatch {
+            criterion,
+            expected,
+            actual,
+            hint: None,
+        }
+    }
+}
+
+/// `ValueMatch` with the regex compiled at load time
+pub struct CompiledRule {
+    /// Definition of the rule, reported in the mismatches
+    pub rule: serde_json::Value,
+    equals: Option<serde_json::Value>,
+    contains: Option<String>,
+    regex: Option<regex::Regex>,
+    present: Option<bool>,
+}
+
+impl CompiledRule {
+    pub fn new(m: &ValueMatch) -> Result<Self, MatcherError> {
+        match m {
+            ValueMatch::Equals(value) => Ok(CompiledRule {
+                rule: serde_json::Value::String(value.to_string()),
+                equals: Some(serde_json::Value::String(value.to_string())),
+                contains: None,
+                regex: None,
+                present: None,
+            }),
+            ValueMatch::Rule(rule) => Self::from_rule(rule),
+        }
+    }
+
+    fn from_rule(rule: &MatchRule) -> Result<Self, MatcherError> {
+        Ok(CompiledRule {
+            rule: serde_json::to_value(rule).unwrap_or_default(),
+            equals: rule.equals.clone(),
+            contains: rule.contains.clone(),
+            regex: rule
+                .regex
+                .as_ref()
+                .map(|r| regex::Regex::new(r.as_str()))
+                .transpose()?,
+            present: rule.present,
+        })
+    }
+
+    pub fn matches(&self, value: Option<&serde_json::Value>) -> bool {
+        let Some(value) = value else {
+            return self.present == Some(false);
+        };
+        if self.present == Some(false) {
+            return false;
+        }
+        let text = crate::template::to_text(value);
+        if let Some(equals) = &self.equals {
+            let equal = match (equals, value) {
+                (serde_json::Value::String(e), _) => e.eq(&text),
+                (e, v) => e.eq(v),
+            };
+            if !equal {
+                return false;
+            }
+        }
+        if let Some(contains) = &self.contains {
+            if !text.contains(contains.as_str()) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(text.as_str()) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether any of the values selected by a json path matches, a path
+    /// selecting nothing is an absent value
+    fn matches_any(&self, selected: &[&serde_json::Value]) -> bool {
+        if selected.is_empty() {
+            self.matches(None)
+        } else {
+            selected.iter().any(|v| self.matches(Some(v)))
+        }
+    }
+
+    fn matches_str(&self, value: Option<&String>) -> bool {
+        self.matches(
+            value
+                .map(|v| serde_json::Value::String(v.to_string()))
+                .as_ref(),
+        )
+    }
+}
+
+#[derive(Debug)]
+enum JsonPathStep {
+    Key(String),
+    Index(usize),
+    Any,
+}
+
+/// Subset of JSONPath: `$`, `.key`, `['key']`, `[0]` and `[*]`
+struct JsonPath(Vec<JsonPathStep>);
+
+impl JsonPath {
+    fn parse(path: &str) -> Result<Self, MatcherError> {
+        let invalid = || MatcherError::InvalidJsonPath(path.to_string());
+        let rest = path.strip_prefix('$').ok_or_else(invalid)?;
+        let mut steps = vec![];
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let mut key = String::new();
+                    while let Some(c) = chars.peek() {
+                        if *c == '.' || *c == '[' {
+                            break;
+                        }
+                        key.push(*c);
+                        chars.next();
+                    }
+                    if key.is_empty() {
+                        return Err(invalid());
+                    }
+                    steps.push(if key == "*" {
+                        JsonPathStep::Any
+                    } else {
+                        JsonPathStep::Key(key)
+                    });
+                }
+                '[' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(c) => inner.push(c),
+                            None => return Err(invalid()),
+                        }
+                    }
+                    let inner = inner.trim();
+                    steps.push(if inner == "*" {
+                        JsonPathStep::Any
+                    } else if let Ok(index) = inner.parse::<usize>() {
+                        JsonPathStep::Index(index)
+                    } else if inner.len() >= 2
+                        && (inner.starts_with('\'') && inner.ends_with('\'')
+                            || inner.starts_with('"') && inner.ends_with('"'))
+                    {
+                        JsonPathStep::Key(inner[1..inner.len() - 1].to_string())
+                    } else {
+                        return Err(invalid());
+                    });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(JsonPath(steps))
+    }
+
+    fn select<'a>(&self, value: &'a serde_json::Value) -> Vec<&'a serde_json::Value> {
+        let mut current = vec![value];
+        for step in self.0.iter() {
+            current = current
+                .into_iter()
+                .flat_map(|value| -> Vec<&serde_json::Value> {
+                    match (step, value) {
+                        (JsonPathStep::Key(key), serde_json::Value::Object(map)) => {
+                            map.get(key).into_iter().collect()
+                        }
+                        (JsonPathStep::Index(index), serde_json::Value::Array(items)) => {
+                            items.get(*index).into_iter().collect()
+                        }
+                        (JsonPathStep::Any, serde_json::Value::Array(items)) => {
+                            items.iter().collect()
+                        }
+                        (JsonPathStep::Any, serde_json::Value::Object(map)) => {
+                            map.values().collect()
+                        }
+                        _ => vec![],
+                    }
+                })
+                .collect();
+        }
+        current
+    }
+}
+
+/// `expected` is a subset of `actual`, objects are compared key by key for
+/// the keys present in `expected`, arrays element by element
+pub fn partial_eq(expected: &serde_json::Value, actual: &serde_json::Value) -> bool {
+    match (expected, actual) {
+        (serde_json::Value::Object(expected), serde_json::Value::Object(actual)) => expected
+            .iter()
+            .all(|(k, v)| actual.get(k).map(|a| partial_eq(v, a)).unwrap_or(false)),
+        (serde_json::Value::Array(expected), serde_json::Value::Array(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .zip(actual.iter())
+                    .all(|(e, a)| partial_eq(e, a))
+        }
+        (expected, actual) => expected.eq(actual),
+    }
+}
+
+fn cookies(request: &MockRequest) -> HashMap<String, String> {
+    request
+        .headers
+        .get("cookie")
+        .map(|cookie| {
+            cookie
+                .split(';')
+                .filter_map(|pair| pair.trim().split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `RequestMatch` with the regexes and json paths compiled at load time
+#[derive(Default)]
+pub struct RequestMatcher {
+    query: Vec<(String, CompiledRule)>,
+    headers: Vec<(String, CompiledRule)>,
+    cookies: Vec<(String, CompiledRule)>,
+    body: Option<serde_json::Value>,
+    // paths are kept as written to be reported in the mismatches
+    json_path: Vec<(String, JsonPath, CompiledRule)>,
+    body_regex: Option<regex::Regex>,
+}
+
+impl RequestMatcher {
+    pub fn new(m: &RequestMatch) -> Result<Self, MatcherError> {
+        let compile = |rules: &HashMap<String, ValueMatch>, lowercase: bool| {
+            rules
+                .iter()
+                .map(|(name, rule)| {
+                    let name = if lowercase {
+                        name.to_lowercase()
+                    } else {
+                        name.to_string()
+                    };
+                    Ok((name, CompiledRule::new(rule)?))
+                })
+                .collect::<Result<Vec<_>, MatcherError>>()
+        };
+        Ok(RequestMatcher {
+            query: compile(&m.query, false)?,
+            headers: compile(&m.headers, true)?,
+            cookies: compile(&m.cookies, false)?,
+            body: m.body.clone(),
+            json_path: m
+                .json_path
+                .iter()
+                .map(|j| {
+                    Ok((
+                        j.path.to_string(),
+                        JsonPath::parse(j.path.as_str())?,
+                        CompiledRule::from_rule(&j.rule)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, MatcherError>>()?,
+            body_regex: m
+                .body_regex
+                .as_ref()
+                .map(|r| regex::Regex::new(r.as_str()))
+                .transpose()?,
+        })
+    }
+
+    /// Whether the request holds every condition, see `mismatches` for the
+    /// ones failed
+    pub fn matches(&self, request: &MockRequest) -> bool {
+        self.mismatches(request).is_empty()
+    }
+
+    /// Number of the conditions, for how close a request came to matching
+    pub fn len(&self) -> usize {
+        self.query.len()
+            + self.headers.len()
+            + self.cookies.len()
+            + self.body.iter().len()
+            + self.json_path.len()
+            + self.body_regex.iter().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every condition the request fails, empty if the request matches
+    pub fn mismatches(&self, request: &MockRequest) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        let text = |value: Option<&String>| {
+            value
+                .map(|v| serde_json::Value::String(v.to_string()))
+                .unwrap_or_default()
+        };
+        let cookies = if self.cookies.is_empty() {
+            HashMap::new()
+        } else {
+            cookies(request)
+        };
+        for (location, rules, values) in [
+            ("query", &self.query, &request.query),
+            ("headers", &self.headers, &request.headers),
+            ("cookies", &self.cookies, &cookies),
+        ] {
+            for (name, rule) in rules.iter() {
+                if !rule.matches_str(values.get(name)) {
+                    mismatches.push(Mismatch::new(
+                        format!("{location}.{name}"),
+                        rule.rule.clone(),
+                        text(values.get(name)),
+                    ));
+                }
+            }
+        }
+        if let Some(body) = &self.body {
+            if !partial_eq(body, &request.body) {
+                mismatches.push(Mismatch::new(
+                    "body".to_string(),
+                    body.clone(),
+                    request.body.clone(),
+                ));
+            }
+        }
+        for (definition, path, rule) in self.json_path.iter() {
+            let selected = path.select(&request.body);
+            if !rule.matches_any(&selected) {
+                mismatches.push(Mismatch::new(
+                    format!("json_path {definition}"),
+                    rule.rule.clone(),
+                    match selected.as_slice() {
+                        [] => serde_json::Value::Null,
+                        [value] => (*value).clone(),
+                        values => values.iter().map(|v| (*v).clone()).collect(),
+                    },
+                ));
+            }
+        }
+        if let Some(regex) = &self.body_regex {
+            let body = crate::template::to_text(&request.body);
+            if !regex.is_match(body.as_str()) {
+                mismatches.push(Mismatch::new(
+                    "body_regex".to_string(),
+                    serde_json::Value::String(regex.as_str().to_string()),
+                    serde_json::Value::String(body),
+                ));
+            }
+        }
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn matcher(m: serde_json::Value) -> RequestMatcher {
+        let m: RequestMatch = serde_json::from_value(m).expect("valid request match");
+        RequestMatcher::new(&m).expect("valid matcher")
+    }
+
+    fn request(uri: &str, headers: &[(&str, &str)], body: serde_json::Value) -> MockRequest {
+        let mut builder = hyper::Request::builder().method("POST").uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let (parts, _) = builder.body(()).expect("valid request").into_parts();
+        MockRequest::new(&parts, body)
+    }
+
+    /// Checks `matches` against `mismatches`, which the near misses rely on
+    fn check(matcher: &RequestMatcher, request: &MockRequest) -> bool {
+        let matches = matcher.matches(request);
+        assert_eq!(matches, matcher.mismatches(request).is_empty());
+        matches
+    }
+
+    fn criteria(matcher: &RequestMatcher, request: &MockRequest) -> Vec<String> {
+        let mut criteria: Vec<_> = matcher
+            .mismatches(request)
+            .into_iter()
+            .map(|m| m.criterion)
+            .collect();
+        criteria.sort();
+        criteria
+    }
+
+    #[test]
+    fn rules_on_a_single_value() {
+        let rule = |m: serde_json::Value| {
+            CompiledRule::new(&serde_json::from_value(m).expect("valid rule")).expect("valid")
+        };
+        let value = json!("user-42");
+        assert!(rule(json!("user-42")).matches(Some(&value)));
+        assert!(!rule(json!("user-4")).matches(Some(&value)));
+        assert!(rule(json!({"contains": "42"})).matches(Some(&value)));
+        assert!(rule(json!({"regex": "^user-[0-9]+$"})).matches(Some(&value)));
+        assert!(!rule(json!({"regex": "^[0-9]+$"})).matches(Some(&value)));
+        assert!(!rule(json!({"contains": "user", "regex": "^[0-9]+$"})).matches(Some(&value)));
+        assert!(rule(json!({"equals": 42})).matches(Some(&json!(42))));
+        assert!(rule(json!({"equals": "42"})).matches(Some(&json!(42))));
+        assert!(!rule(json!({"equals": 42})).matches(Some(&json!("42"))));
+    }
+
+    #[test]
+    fn presence_rules() {
+        let present = CompiledRule::from_rule(&MatchRule {
+            present: Some(true),
+            ..Default::default()
+        })
+        .expect("valid");
+        let absent = CompiledRule::from_rule(&MatchRule {
+            present: Some(false),
+            ..Default::default()
+        })
+        .expect("valid");
+        assert!(present.matches(Some(&json!(""))));
+        assert!(!present.matches(None));
+        assert!(absent.matches(None));
+        assert!(!absent.matches(Some(&json!(""))));
+        // a rule without `present` still needs the value
+        assert!(!CompiledRule::new(&ValueMatch::Equals("".to_string()))
+            .expect("valid")
+            .matches(None));
+    }
+
+    #[test]
+    fn query_headers_and_cookies() {
+        let m = matcher(json!({
+            "query": {"page": "2"},
+            "headers": {"X-Tenant": {"regex": "^t-"}},
+            "cookies": {"session": {"present": true}},
+        }));
+        let matching = request(
+            "/users?page=2",
+            &[("x-tenant", "t-1"), ("cookie", "theme=dark; session=abc")],
+            json!(null),
+        );
+        assert!(check(&m, &matching));
+        let failing = request("/users?page=3", &[("x-tenant", "other")], json!(null));
+        assert!(!check(&m, &failing));
+        assert_eq!(
+            criteria(&m, &failing),
+            vec!["cookies.session", "headers.x-tenant", "query.page"]
+        );
+    }
+
+    #[test]
+    fn repeated_headers_are_joined() {
+        let m = matcher(json!({
+            "headers": {"accept": "text/html, application/json"},
+            "cookies": {"a": "1", "b": "2"},
+        }));
+        let request = request(
+            "/",
+            &[
+                ("accept", "text/html"),
+                ("accept", "application/json"),
+                ("cookie", "a=1"),
+                ("cookie", "b=2"),
+            ],
+            json!(null),
+        );
+        assert_eq!(request.headers["cookie"], "a=1; b=2");
+        assert!(check(&m, &request));
+    }
+
+    #[test]
+    fn body_is_a_partial_match() {
+        let m = matcher(json!({"body": {"user": {"id": 1}, "tags": ["a", "b"]}}));
+        let body = json!({"user": {"id": 1, "name": "x"}, "tags": ["a", "b"], "extra": true});
+        assert!(check(&m, &request("/", &[], body)));
+        let body = json!({"user": {"id": 1}, "tags": ["a", "b", "c"]});
+        assert!(!check(&m, &request("/", &[], body)));
+        assert!(!check(&m, &request("/", &[], json!({"user": {"id": 2}}))));
+    }
+
+    #[test]
+    fn json_path_selects_into_the_body() {
+        let body = json!({
+            "transactions": [{"did": "d-1"}, {"did": "d-2"}],
+            "meta": {"the key": "v"},
+        });
+        for (path, rule, expected) in [
+            ("$.transactions[0].did", json!({"equals": "d-1"}), true),
+            ("$.transactions[1].did", json!({"equals": "d-1"}), false),
+            ("$.transactions[*].did", json!({"equals": "d-2"}), true),
+            ("$.meta['the key']", json!({"equals": "v"}), true),
+            ("$.meta.*", json!({"contains": "v"}), true),
+            ("$.missing", json!({"present": false}), true),
+            ("$.transactions[5]", json!({"present": true}), false),
+        ] {
+            let mut definition = rule;
+            definition["path"] = json!(path);
+            let m = RequestMatcher::new(&RequestMatch {
+                json_path: vec![serde_json::from_value(definition).expect("valid")],
+                ..Default::default()
+            })
+            .expect("valid matcher");
+            assert_eq!(
+                check(&m, &request("/", &[], body.clone())),
+                expected,
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_json_paths_are_refused() {
+        for path in ["transactions", "$.", "$..did", "$[0", "$[abc]", "$x"] {
+            assert!(
+                matches!(JsonPath::parse(path), Err(MatcherError::InvalidJsonPath(_))),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn body_regex_matches_the_serialized_body() {
+        let m = matcher(json!({"body_regex": "\"did\":\"d-[0-9]+\""}));
+        assert!(check(&m, &request("/", &[], json!({"did": "d-1"}))));
+        assert!(!check(&m, &request("/", &[], json!({"did": "x"}))));
+        assert!(check(
+            &matcher(json!({"body_regex": "^plain"})),
+            &request("/", &[], json!("plain text"))
+        ));
+    }
+
+    #[test]
+    fn invalid_regexes_are_refused() {
+        let m: RequestMatch =
+            serde_json::from_value(json!({"headers": {"a": {"regex": "("}}})).expect("valid");
+        assert!(matches!(
+            RequestMatcher::new(&m),
+            Err(MatcherError::InvalidRegex(_))
+        ));
+    }
+}
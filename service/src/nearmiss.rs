@@ -0,0 +1,331 @@
+use crate::matcher::Mismatch;
+use crate::utils::{MockRequest, API};
+
+/// Mocks returned along with a `404`, at most this many
+pub const LIMIT: usize = 3;
+
+/// Mock which came close to matching an unmatched request
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct NearMiss {
+    pub id: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// Similarity from `0` to `1`, `1` being a match
+    pub score: f64,
+    pub mismatches: Vec<Mismatch>,
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+fn similarity(a: &str, b: &str) -> f64 {
+    let len = a.chars().count().max(b.chars().count());
+    if len == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f64 / len as f64
+}
+
+/// Segment by segment similarity of the path to the template, parameters
+/// match any segment and a wildcard matches the rest of the path
+fn path_similarity(template: &str, path: &str) -> f64 {
+    let template: Vec<&str> = template.split('/').filter(|s| !s.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut total = 0.0;
+    for (index, segment) in template.iter().enumerate() {
+        if *segment == "*" {
+            return (total + (template.len() - index) as f64) / template.len() as f64;
+        }
+        total += match path.get(index) {
+            Some(_) if segment.starts_with('{') => 1.0,
+            Some(actual) => similarity(segment, actual),
+            None => 0.0,
+        };
+    }
+    let len = template.len().max(path.len());
+    if len == 0 {
+        return 1.0;
+    }
+    total / len as f64
+}
+
+/// Why the request failed to match the mock and how close it came
+pub fn compare(
+    api: &API,
+    pattern: &crate::path::PathPattern,
+    matcher: &crate::matcher::RequestMatcher,
+    graphql: Option<&crate::graphql::GraphQLMatcher>,
+    states: &crate::scenario::States,
+    hits: &crate::utils::Hits,
+    request: &MockRequest,
+) -> NearMiss {
+    let mut mismatches = vec![];
+    let string = |s: &str| serde_json::Value::String(s.to_string());
+    if !api.enabled {
+        mismatches.push(Mismatch::new(
+            "enabled".to_string(),
+            serde_json::Value::Bool(true),
+            serde_json::Value::Bool(false),
+        ));
+    }
+    let now = chrono::Utc::now();
+    if !api.is_active(now) {
+        let mut mismatch = Mismatch::new(
+            "active".to_string(),
+            serde_json::json!({"from": api.active_from, "until": api.expires_at}),
+            serde_json::Value::String(now.to_rfc3339()),
+        );
+        mismatch.hint = Some("outside of the time window of the mock".to_string());
+        mismatches.push(mismatch);
+    }
+    if hits.remaining == Some(0) {
+        let mut mismatch = Mismatch::new(
+            "times".to_string(),
+            serde_json::json!(api.times),
+            serde_json::json!(hits.hits),
+        );
+        mismatch.hint = Some("mock is used up".to_string());
+        mismatches.push(mismatch);
+    }
+
+    let method = if api.method.eq(request.method.as_str()) {
+        1.0
+    } else {
+        let mut mismatch = Mismatch::new(
+            "method".to_string(),
+            string(api.method.as_str()),
+            string(request.method.as_str()),
+        );
+        if api.method.eq_ignore_ascii_case(request.method.as_str()) {
+            mismatch.hint = Some("methods are case sensitive".to_string());
+        }
+        mismatches.push(mismatch);
+        0.0
+    };
+
+    let path = if pattern.matches(request.path.as_str()).is_some() {
+        1.0
+    } else {
+        let toggled = match request.path.strip_suffix('/') {
+            Some(path) => path.to_string(),
+            None => format!("{}/", request.path),
+        };
+        let hint = if pattern.matches(toggled.as_str()).is_some() {
+            Some("differs only by the trailing slash")
+        } else if pattern
+            .matches(request.path.to_lowercase().as_str())
+            .is_some()
+            || api.path.eq_ignore_ascii_case(request.path.as_str())
+        {
+            Some("differs only in case")
+        } else {
+            None
+        };
+        mismatches.push(Mismatch {
+            criterion: "path".to_string(),
+            expected: string(api.path.as_str()),
+            actual: string(request.path.as_str()),
+            hint: hint.map(|h| h.to_string()),
+        });
+        match hint {
+            // a slash or a case away is almost a match
+            Some(_) => 0.95,
+            // parameters count as equal, a path failing on a parameter regex
+            // is still not a match
+            None => path_similarity(api.path.as_str(), request.path.as_str()).min(0.9),
+        }
+    };
+
+    let mut failed = matcher.mismatches(request);
+    let mut total = matcher.len();
+    if let Some(graphql) = graphql {
+        let operation = crate::graphql::Operation::new(request);
+        // a request which is not GraphQL at all still fails one condition
+        total += graphql.len().max(1);
+        failed.extend(graphql.mismatches(operation.as_ref()));
+    }
+    if let Some(scenario) = &api.scenario {
+        total += 1;
+        if !states.matches(scenario) {
+            let mut mismatch = Mismatch::new(
+                "scenario".to_string(),
+                string(scenario.required_state.as_deref().unwrap_or_default()),
+                string(states.state(scenario.name.as_str())),
+            );
+            mismatch.hint = Some(format!("state of the scenario {}", scenario.name));
+            failed.push(mismatch);
+        }
+    }
+    let conditions = if total == 0 {
+        1.0
+    } else {
+        1.0 - failed.len() as f64 / total as f64
+    };
+    mismatches.extend(failed);
+
+    // disabled, inactive and used up mocks are as good as disabled
+    let enabled = if api.enabled && api.is_active(now) && hits.remaining != Some(0) {
+        1.0
+    } else {
+        0.5
+    };
+    NearMiss {
+        id: api.id.clone(),
+        method: api.method.to_string(),
+        path: api.path.to_string(),
+        score: ((method + 2.0 * path + conditions) / 4.0 * enabled * 100.0).round() / 100.0,
+        mismatches,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn api(definition: serde_json::Value) -> API {
+        serde_json::from_value(definition).expect("valid mock")
+    }
+
+    fn request(method: &str, path: &str, body: serde_json::Value) -> MockRequest {
+        MockRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            body,
+            ..Default::default()
+        }
+    }
+
+    fn compared(api: &API, hits: u64, request: &MockRequest) -> NearMiss {
+        let pattern = crate::path::PathPattern::parse(api.path.as_str()).expect("valid path");
+        let matcher = crate::matcher::RequestMatcher::new(&api.request.clone().unwrap_or_default())
+            .expect("valid matcher");
+        let hits = crate::utils::Hits {
+            id: api.id.clone(),
+            hits,
+            remaining: api.times.map(|times| times.saturating_sub(hits)),
+        };
+        compare(
+            api,
+            &pattern,
+            &matcher,
+            None,
+            &crate::scenario::lock(),
+            &hits,
+            request,
+        )
+    }
+
+    fn compared_at(api: &API, path: &str) -> NearMiss {
+        compared(api, 0, &request("GET", path, json!(null)))
+    }
+
+    fn criteria(near_miss: &NearMiss) -> Vec<(&str, Option<&str>)> {
+        near_miss
+            .mismatches
+            .iter()
+            .map(|m| (m.criterion.as_str(), m.hint.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn levenshtein_distance() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("users", "users"), 0);
+        assert_eq!(levenshtein("users", "user"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(similarity("abcd", "abce"), 0.75);
+    }
+
+    #[test]
+    fn path_similarity_by_segments() {
+        assert_eq!(path_similarity("/v1/users/{id}", "/v1/users/42"), 1.0);
+        assert_eq!(path_similarity("/v1/ab", "/v1/xy/1"), 1.0 / 3.0);
+        assert_eq!(path_similarity("/static/*", "/static/a/b"), 1.0);
+        assert_eq!(path_similarity("/", "/"), 1.0);
+        assert!(path_similarity("/v1/users", "/v1/user") > path_similarity("/v1/users", "/v1/x"));
+    }
+
+    #[test]
+    fn matching_request_scores_one() {
+        let api = api(json!({"method": "GET", "path": "/users/{id}"}));
+        let near_miss = compared(&api, 0, &request("GET", "/users/1", json!(null)));
+        assert_eq!(near_miss.score, 1.0);
+        assert!(near_miss.mismatches.is_empty());
+    }
+
+    #[test]
+    fn method_and_path_hints() {
+        let api = api(json!({"method": "GET", "path": "/users"}));
+        let near_miss = compared(&api, 0, &request("get", "/users/", json!(null)));
+        assert_eq!(
+            criteria(&near_miss),
+            vec![
+                ("method", Some("methods are case sensitive")),
+                ("path", Some("differs only by the trailing slash")),
+            ]
+        );
+        assert_eq!(near_miss.score, 0.73);
+        let near_miss = compared_at(&api, "/Users");
+        assert_eq!(
+            criteria(&near_miss),
+            vec![("path", Some("differs only in case"))]
+        );
+        assert_eq!(near_miss.score, 0.98);
+    }
+
+    #[test]
+    fn unrelated_paths_score_lower() {
+        let api = api(json!({"method": "GET", "path": "/v1/users/{id:[0-9]+}"}));
+        let close = compared_at(&api, "/v1/users/abc");
+        let far = compared_at(&api, "/v2/orders/abc");
+        assert_eq!(criteria(&close), vec![("path", None)]);
+        // failing the parameter regex is close but still not a match
+        assert_eq!(close.score, 0.95);
+        assert!(far.score < close.score);
+    }
+
+    #[test]
+    fn failed_conditions_lower_the_score() {
+        let api = api(json!({
+            "method": "POST",
+            "path": "/sync",
+            "match": {"body": {"did": "a"}, "body_regex": "did"},
+        }));
+        let near_miss = compared(&api, 0, &request("POST", "/sync", json!({"did": "b"})));
+        assert_eq!(criteria(&near_miss), vec![("body", None)]);
+        assert_eq!(near_miss.score, 0.88);
+    }
+
+    #[test]
+    fn disabled_and_used_up_mocks_are_reported() {
+        let disabled = api(json!({"method": "GET", "path": "/a", "enabled": false}));
+        let near_miss = compared_at(&disabled, "/a");
+        assert_eq!(criteria(&near_miss), vec![("enabled", None)]);
+        assert_eq!(near_miss.score, 0.5);
+        let used_up = api(json!({"method": "GET", "path": "/a", "times": 2}));
+        let near_miss = compared(&used_up, 2, &request("GET", "/a", json!(null)));
+        assert_eq!(
+            criteria(&near_miss),
+            vec![("times", Some("mock is used up"))]
+        );
+        assert_eq!(near_miss.score, 0.5);
+    }
+}
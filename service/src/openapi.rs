@@ -0,0 +1,434 @@
+use crate::utils::{ResponseBody, API};
+use std::collections::HashMap;
+
+#[derive(thiserror::Error, Debug)]
+pub enum OpenAPIError {
+    #[error("IOError: {path}: {source}")]
+    IO {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("ParseError: {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+    #[error("InvalidSpec: {path}: {message}")]
+    InvalidSpec { path: String, message: String },
+}
+
+/// Mocks generated from the specs rank below the hand written ones, so a mock
+/// in the mock files overrides the operation with the same method and path
+pub const SPEC_PRIORITY: i64 = -1;
+
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Spec files from `OPENAPI_PATH`, comma separated
+pub fn paths() -> Vec<String> {
+    crate::utils::read_openapi_path()
+        .unwrap_or_default()
+        .split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Reads every spec from `OPENAPI_PATH` into mocks
+pub fn read_all() -> Result<Vec<API>, OpenAPIError> {
+    let mut apis = vec![];
+    for path in paths() {
+        apis.extend(read(path.as_str())?);
+    }
+    Ok(apis)
+}
+
+/// Reads an OpenAPI 3 spec, yaml or json, into mocks
+pub fn read(path: &str) -> Result<Vec<API>, OpenAPIError> {
+    mocks(&load_spec(path)?).map_err(|message| OpenAPIError::InvalidSpec {
+        path: path.to_string(),
+        message,
+    })
+}
+
+/// Parses the spec content into mocks, `path` is only used in the errors
+pub fn parse(path: &str, content: &str) -> Result<Vec<API>, OpenAPIError> {
+    mocks(&parse_spec(path, content)?).map_err(|message| OpenAPIError::InvalidSpec {
+        path: path.to_string(),
+        message,
+    })
+}
+
+fn parse_spec(path: &str, content: &str) -> Result<serde_json::Value, OpenAPIError> {
+    // yaml is a superset of json so both are parsed the same way
+    serde_yaml::from_str(content).map_err(|source| OpenAPIError::Parse {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Reads the spec file as json
+pub fn load_spec(path: &str) -> Result<serde_json::Value, OpenAPIError> {
+    let content = std::fs::read_to_string(path).map_err(|source| OpenAPIError::IO {
+        path: path.to_string(),
+        source,
+    })?;
+    parse_spec(path, content.as_str())
+}
+
+/// Finds the operation by its `operationId` or by the method and the path as
+/// written in the spec, like `POST /pets/{petId}`. The parameters of the path
+/// item are merged into the ones of the operation.
+pub fn operation(
+    spec: &serde_json::Value,
+    name: &str,
+) -> Option<(serde_json::Value, Vec<serde_json::Value>)> {
+    let (method, path) = name.split_once(' ').unwrap_or_default();
+    let paths = spec.get("paths")?.as_object()?;
+    for (item_path, item) in paths {
+        let item = crate::schema::resolve(spec, item);
+        for item_method in METHODS {
+            let Some(operation) = item.get(item_method) else {
+                continue;
+            };
+            let by_id = operation.get("operationId").and_then(|o| o.as_str()) == Some(name);
+            let by_route = method.eq_ignore_ascii_case(item_method) && path == item_path;
+            if !by_id && !by_route {
+                continue;
+            }
+            let parameters = |v: &serde_json::Value| -> Vec<serde_json::Value> {
+                v.get("parameters")
+                    .and_then(|p| p.as_array())
+                    .map(|p| {
+                        p.iter()
+                            .map(|p| crate::schema::resolve(spec, p).clone())
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            let mut merged = parameters(operation);
+            for parameter in parameters(item) {
+                let overridden = merged.iter().any(|p| {
+                    p.get("name") == parameter.get("name") && p.get("in") == parameter.get("in")
+                });
+                if !overridden {
+                    merged.push(parameter);
+                }
+            }
+            return Some((operation.clone(), merged));
+        }
+    }
+    None
+}
+
+/// Mocks for every operation of the spec, one per documented status. The
+/// lowest 2xx status, or the first status if there is none, is served by
+/// default and the others are picked with the `Prefer: code=<status>` header.
+pub fn mocks(spec: &serde_json::Value) -> Result<Vec<API>, String> {
+    let version = spec
+        .get("openapi")
+        .and_then(|v| v.as_str())
+        .ok_or("openapi version not found")?;
+    if !version.starts_with('3') {
+        return Err(format!("unsupported openapi version: {version}"));
+    }
+    let base = base_path(spec);
+    let mut apis = vec![];
+    let Some(paths) = spec.get("paths").and_then(|p| p.as_object()) else {
+        return Ok(apis);
+    };
+    for (path, item) in paths {
+        let item = crate::schema::resolve(spec, item);
+        for method in METHODS {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+            apis.extend(operation_mocks(
+                spec,
+                format!("{base}{path}").as_str(),
+                method,
+                operation,
+            ));
+        }
+    }
+    Ok(apis)
+}
+
+/// Path of the first server url, like `/v1` for `https://api.example.com/v1`
+fn base_path(spec: &serde_json::Value) -> String {
+    let url = spec
+        .pointer("/servers/0/url")
+        .and_then(|u| u.as_str())
+        .unwrap_or_default();
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or_default(),
+        None => url,
+    };
+    path.trim_end_matches('/').to_string()
+}
+
+fn operation_mocks(
+    spec: &serde_json::Value,
+    path: &str,
+    method: &str,
+    operation: &serde_json::Value,
+) -> Vec<API> {
+    let Some(responses) = operation.get("responses").and_then(|r| r.as_object()) else {
+        return vec![];
+    };
+    let mut statuses: Vec<(u16, &serde_json::Value)> = responses
+        .iter()
+        .filter_map(|(code, response)| {
+            let status = match code.as_str() {
+                "default" => 500,
+                code => code.replace(['X', 'x'], "0").parse().ok()?,
+            };
+            Some((status, crate::schema::resolve(spec, response)))
+        })
+        .collect();
+    statuses.sort_by_key(|(status, _)| *status);
+    statuses.dedup_by_key(|(status, _)| *status);
+    let primary = statuses
+        .iter()
+        .position(|(status, _)| (200..300).contains(status))
+        .unwrap_or(0);
+
+    let name = operation
+        .get("operationId")
+        .and_then(|o| o.as_str())
+        .map(|o| o.to_string())
+        .unwrap_or_else(|| {
+            format!("{method}{path}")
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+                .trim_end_matches('_')
+                .to_string()
+        });
+    let mut tags: Vec<String> = operation
+        .get("tags")
+        .and_then(|t| t.as_array())
+        .map(|t| {
+            t.iter()
+                .filter_map(|t| t.as_str().map(|t| t.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    tags.push("openapi".to_string());
+
+    let mut apis = vec![];
+    for (index, (status, response)) in statuses.iter().enumerate() {
+        let (body, mut headers) = response_body(spec, response);
+        if let Some(response_headers) = response.get("headers").and_then(|h| h.as_object()) {
+            for (header, definition) in response_headers {
+                let definition = crate::schema::resolve(spec, definition);
+                let value = match definition.get("example") {
+                    Some(example) => example.clone(),
+                    None => match definition.get("schema") {
+                        Some(schema) => crate::schema::example(spec, schema),
+                        None => continue,
+                    },
+                };
+                headers.insert(header.to_lowercase(), crate::template::to_text(&value));
+            }
+        }
+        let request = (index != primary).then(|| crate::matcher::RequestMatch {
+            headers: HashMap::from([(
+                "prefer".to_string(),
+                crate::matcher::ValueMatch::Rule(crate::matcher::MatchRule {
+                    contains: Some(format!("code={status}")),
+                    ..Default::default()
+                }),
+            )]),
+            ..Default::default()
+        });
+        apis.push(API {
+            id: Some(format!("openapi:{name}:{status}")),
+            tags: tags.clone(),
+            method: method.to_uppercase(),
+            path: path.to_string(),
+            body,
+            status: Some(*status),
+            headers,
+            request,
+            priority: SPEC_PRIORITY,
+            ..Default::default()
+        });
+    }
+    // the alternative statuses come first to be tried before the default one
+    if !apis.is_empty() {
+        let default = apis.remove(primary);
+        apis.push(default);
+    }
+    apis
+}
+
+/// Body of the response from its example, the first of its named examples or
+/// synthesized from its schema, json media types are preferred
+fn response_body(
+    spec: &serde_json::Value,
+    response: &serde_json::Value,
+) -> (Option<ResponseBody>, HashMap<String, String>) {
+    let mut headers = HashMap::new();
+    let Some(content) = response.get("content").and_then(|c| c.as_object()) else {
+        return (None, headers);
+    };
+    let Some((media_type, media)) = content
+        .iter()
+        .find(|(media_type, _)| media_type.contains("json"))
+        .or_else(|| content.iter().next())
+    else {
+        return (None, headers);
+    };
+    let example = media
+        .get("example")
+        .cloned()
+        .or_else(|| {
+            media
+                .get("examples")
+                .and_then(|e| e.as_object())
+                .and_then(|e| e.values().next())
+                .map(|e| crate::schema::resolve(spec, e))
+                .and_then(|e| e.get("value"))
+                .cloned()
+        })
+        .or_else(|| {
+            media
+                .get("schema")
+                .map(|schema| crate::schema::example(spec, schema))
+        });
+    let Some(example) = example else {
+        return (None, headers);
+    };
+    headers.insert("content-type".to_string(), media_type.to_string());
+    let body = if media_type.contains("json") {
+        ResponseBody::Json(example)
+    } else if media_type.contains("xml") {
+        ResponseBody::Xml(crate::template::to_text(&example))
+    } else {
+        ResponseBody::Text(crate::template::to_text(&example))
+    };
+    (Some(body), headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SPEC: &str = r##"
+openapi: 3.0.3
+servers:
+  - url: https://api.example.com/v1/
+paths:
+  /pets/{petId}:
+    parameters:
+      - name: petId
+        in: path
+        required: true
+        schema: {type: integer}
+    get:
+      operationId: getPet
+      tags: [pets]
+      responses:
+        "404":
+          description: missing
+        "200":
+          description: found
+          headers:
+            X-Rate-Limit:
+              schema: {type: integer, minimum: 10, maximum: 10}
+          content:
+            application/json:
+              schema: {$ref: "#/components/schemas/Pet"}
+    delete:
+      responses:
+        default:
+          description: error
+          content:
+            text/plain:
+              example: gone
+components:
+  schemas:
+    Pet:
+      type: object
+      required: [id, name]
+      properties:
+        id: {type: integer, example: 7}
+        name: {type: string, example: Rex}
+"##;
+
+    fn generated() -> Vec<API> {
+        parse("spec.yaml", SPEC).expect("valid spec")
+    }
+
+    fn generated_mock(id: &str) -> API {
+        generated()
+            .into_iter()
+            .find(|api| api.id.as_deref() == Some(id))
+            .expect("generated mock")
+    }
+
+    #[test]
+    fn every_status_of_every_operation_gets_a_mock() {
+        let ids: Vec<String> = generated().into_iter().filter_map(|api| api.id).collect();
+        assert_eq!(
+            ids,
+            [
+                "openapi:getPet:404",
+                "openapi:getPet:200",
+                "openapi:delete_v1_pets__petId:500"
+            ]
+        );
+    }
+
+    #[test]
+    fn mocks_respond_with_the_examples() {
+        let found = generated_mock("openapi:getPet:200");
+        assert_eq!(found.method, "GET");
+        assert_eq!(found.path, "/v1/pets/{petId}");
+        assert_eq!(found.priority, SPEC_PRIORITY);
+        assert_eq!(found.tags, ["pets", "openapi"]);
+        assert_eq!(found.headers["x-rate-limit"], "10");
+        assert_eq!(found.headers["content-type"], "application/json");
+        assert!(matches!(
+            found.body,
+            Some(ResponseBody::Json(body)) if body == json!({"id": 7, "name": "Rex"})
+        ));
+        let gone = generated_mock("openapi:delete_v1_pets__petId:500");
+        assert!(matches!(gone.body, Some(ResponseBody::Text(text)) if text == "gone"));
+    }
+
+    #[test]
+    fn other_statuses_are_picked_with_the_prefer_header() {
+        assert!(generated_mock("openapi:getPet:200").request.is_none());
+        let missing = generated_mock("openapi:getPet:404")
+            .request
+            .expect("matcher");
+        assert!(matches!(
+            &missing.headers["prefer"],
+            crate::matcher::ValueMatch::Rule(rule) if rule.contains.as_deref() == Some("code=404")
+        ));
+    }
+
+    #[test]
+    fn operations_are_found_by_id_or_route() {
+        let spec = parse_spec("spec.yaml", SPEC).expect("valid spec");
+        let (by_id, parameters) = operation(&spec, "getPet").expect("operation");
+        assert_eq!(parameters.len(), 1);
+        let (by_route, _) = operation(&spec, "GET /pets/{petId}").expect("operation");
+        assert_eq!(by_id, by_route);
+        assert!(operation(&spec, "POST /pets/{petId}").is_none());
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        assert!(matches!(
+            parse("spec.yaml", "swagger: '2.0'"),
+            Err(OpenAPIError::InvalidSpec { .. })
+        ));
+        assert!(matches!(
+            parse("spec.yaml", "openapi: [3"),
+            Err(OpenAPIError::Parse { .. })
+        ));
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PathError {
+    #[error("InvalidRegex: {path}: {source}")]
+    InvalidRegex { path: String, source: regex::Error },
+    #[error("InvalidWildcard: {0}, `*` is only allowed as the last segment")]
+    InvalidWildcard(String),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Regex(String, regex::Regex),
+    Wildcard,
+}
+
+impl Segment {
+    // higher is more specific, used for ordering the patterns
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Literal(_) => 3,
+            Segment::Regex(_, _) => 2,
+            Segment::Param(_) => 1,
+            Segment::Wildcard => 0,
+        }
+    }
+}
+
+/// Compiled `API.path`, supports the following in the path segments
+/// - `/v1/users/` literal segments, matched exactly
+/// - `/v1/users/{id}/` named parameter, matches any single segment
+/// - `/v1/users/{id:[0-9]+}/` named parameter, segment must match the regex
+/// - `/static/*` trailing wildcard, matches rest of the path, captured as `wildcard`
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    segments: Vec<Segment>,
+}
+
+impl PathPattern {
+    pub fn parse(path: &str) -> Result<Self, PathError> {
+        let parts: Vec<&str> = path.split('/').collect();
+        let mut segments = Vec::with_capacity(parts.len());
+        for (index, part) in parts.iter().enumerate() {
+            let segment = if *part == "*" {
+                if index != parts.len() - 1 {
+                    return Err(PathError::InvalidWildcard(path.to_string()));
+                }
+                Segment::Wildcard
+            } else if part.len() > 2 && part.starts_with('{') && part.ends_with('}') {
+                let inner = &part[1..part.len() - 1];
+                match inner.split_once(':') {
+                    Some((name, re)) => {
+                        let re = regex::Regex::new(format!("^(?:{re})$").as_str()).map_err(
+                            |source| PathError::InvalidRegex {
+                                path: path.to_string(),
+                                source,
+                            },
+                        )?;
+                        Segment::Regex(name.to_string(), re)
+                    }
+                    None => Segment::Param(inner.to_string()),
+                }
+            } else {
+                Segment::Literal(part.to_string())
+            };
+            segments.push(segment);
+        }
+        Ok(PathPattern { segments })
+    }
+
+    /// Returns the captured parameters if the path matches the pattern
+    pub fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let parts: Vec<&str> = path.split('/').collect();
+        let mut params = HashMap::new();
+        for (index, segment) in self.segments.iter().enumerate() {
+            if let Segment::Wildcard = segment {
+                params.insert("wildcard".to_string(), parts.get(index..)?.join("/"));
+                return Some(params);
+            }
+            let part = parts.get(index)?;
+            match segment {
+                Segment::Literal(literal) if literal.eq(part) => {}
+                Segment::Param(name) if !part.is_empty() => {
+                    params.insert(name.to_string(), part.to_string());
+                }
+                Segment::Regex(name, re) if re.is_match(part) => {
+                    params.insert(name.to_string(), part.to_string());
+                }
+                _ => return None,
+            }
+        }
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        Some(params)
+    }
+
+    /// Specificity of the pattern, compared segment by segment from the left, a
+    /// literal segment wins over a regex parameter, which wins over a plain
+    /// parameter, which wins over the wildcard.
+    pub fn specificity(&self) -> Vec<u8> {
+        self.segments.iter().map(|s| s.rank()).collect()
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    literals: HashMap<String, Node>,
+    // parameters of any kind, the regexes are checked by the pattern itself
+    params: Option<Box<Node>>,
+    wildcards: Vec<usize>,
+    ends: Vec<usize>,
+}
+
+impl Node {
+    fn collect(&self, parts: &[&str], out: &mut Vec<usize>) {
+        out.extend(&self.wildcards);
+        let Some((part, rest)) = parts.split_first() else {
+            out.extend(&self.ends);
+            return;
+        };
+        if let Some(node) = self.literals.get(*part) {
+            node.collect(rest, out);
+        }
+        if let Some(node) = &self.params {
+            node.collect(rest, out);
+        }
+    }
+}
+
+/// Trie of the path patterns by their segments, narrows down the patterns a
+/// path can match without trying every one of them. Values are the positions
+/// of the patterns, the candidates still have to be matched with the pattern.
+#[derive(Default)]
+pub struct PathIndex {
+    root: Node,
+}
+
+impl PathIndex {
+    pub fn insert(&mut self, pattern: &PathPattern, value: usize) {
+        let mut node = &mut self.root;
+        for segment in &pattern.segments {
+            node = match segment {
+                Segment::Literal(literal) => node.literals.entry(literal.to_string()).or_default(),
+                Segment::Param(_) | Segment::Regex(_, _) => {
+                    node.params.get_or_insert_with(Default::default)
+                }
+                Segment::Wildcard => {
+                    node.wildcards.push(value);
+                    return;
+                }
+            };
+        }
+        node.ends.push(value);
+    }
+
+    /// Positions of the patterns which can match the path, in ascending order
+    pub fn candidates(&self, path: &str) -> Vec<usize> {
+        let parts: Vec<&str> = path.split('/').collect();
+        let mut out = vec![];
+        self.root.collect(&parts, &mut out);
+        out.sort_unstable();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(path: &str) -> PathPattern {
+        PathPattern::parse(path).expect("valid pattern")
+    }
+
+    fn params(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+        let mut params: Vec<_> = parse(pattern).matches(path)?.into_iter().collect();
+        params.sort();
+        Some(params)
+    }
+
+    fn pair(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn specificity_orders_literal_regex_param_then_wildcard() {
+        let mut patterns = vec!["/users/*", "/users/{id}", "/users/me", "/users/{id:[0-9]+}"];
+        patterns.sort_by_key(|p| std::cmp::Reverse(parse(p).specificity()));
+        assert_eq!(
+            patterns,
+            vec!["/users/me", "/users/{id:[0-9]+}", "/users/{id}", "/users/*"]
+        );
+    }
+
+    #[test]
+    fn specificity_is_decided_from_the_left() {
+        assert!(parse("/users/{id}").specificity() > parse("/{kind}/me").specificity());
+        assert!(parse("/users/{id}/posts").specificity() > parse("/users/{id}").specificity());
+    }
+
+    #[test]
+    fn params_are_captured() {
+        assert_eq!(
+            params("/v1/users/{id}/posts/{post}", "/v1/users/42/posts/7"),
+            Some(vec![pair("id", "42"), pair("post", "7")])
+        );
+        assert_eq!(params("/v1/users/{id}", "/v1/users/42/posts"), None);
+        assert_eq!(params("/v1/users/{id}/posts", "/v1/users/42"), None);
+    }
+
+    #[test]
+    fn params_do_not_match_empty_segments() {
+        assert_eq!(params("/users/{id}", "/users/"), None);
+        assert_eq!(params("/users/{id}/posts", "/users//posts"), None);
+    }
+
+    #[test]
+    fn regex_params_match_the_whole_segment() {
+        assert_eq!(
+            params("/users/{id:[0-9]+}", "/users/42"),
+            Some(vec![pair("id", "42")])
+        );
+        assert_eq!(params("/users/{id:[0-9]+}", "/users/42a"), None);
+        assert_eq!(params("/users/{id:[0-9]+}", "/users/a42"), None);
+    }
+
+    #[test]
+    fn wildcard_captures_the_rest_of_the_path() {
+        assert_eq!(
+            params("/static/*", "/static/css/site.css"),
+            Some(vec![pair("wildcard", "css/site.css")])
+        );
+        assert_eq!(params("/static/*", "/assets/site.css"), None);
+    }
+
+    #[test]
+    fn wildcard_matches_an_empty_rest() {
+        assert_eq!(
+            params("/static/*", "/static/"),
+            Some(vec![pair("wildcard", "")])
+        );
+        assert_eq!(
+            params("/static/*", "/static"),
+            Some(vec![pair("wildcard", "")])
+        );
+        assert_eq!(
+            params("/static/*", "/static//a"),
+            Some(vec![pair("wildcard", "/a")])
+        );
+    }
+
+    #[test]
+    fn invalid_patterns_are_refused() {
+        assert!(matches!(
+            PathPattern::parse("/static/*/a"),
+            Err(PathError::InvalidWildcard(_))
+        ));
+        assert!(matches!(
+            PathPattern::parse("/users/{id:[0-9}"),
+            Err(PathError::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn index_narrows_down_the_candidates() {
+        let patterns = [
+            "/users/me",
+            "/users/{id}",
+            "/users/*",
+            "/posts/{id}",
+            "/users/{id}/posts",
+        ];
+        let mut index = PathIndex::default();
+        for (position, pattern) in patterns.iter().enumerate() {
+            index.insert(&parse(pattern), position);
+        }
+        assert_eq!(index.candidates("/users/me"), vec![0, 1, 2]);
+        assert_eq!(index.candidates("/users/42/posts"), vec![2, 4]);
+        assert_eq!(index.candidates("/users"), vec![2]);
+        assert_eq!(index.candidates("/comments/1"), Vec::<usize>::new());
+    }
+}
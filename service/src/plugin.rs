@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+/// Plugins are stopped once they consume this much fuel, roughly one unit per
+/// instruction, unless the mock sets its own `fuel`
+const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// Memory a plugin instance can grow to, every request gets its own instance
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PluginError {
+    #[error("IOError: {path}: {source}")]
+    IO {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("WasmError: {0}")]
+    Wasm(#[from] wasmi::Error),
+    #[error("MissingExport: {0}")]
+    MissingExport(&'static str),
+    #[error("NotFound: plugin {0} is not loaded")]
+    NotFound(String),
+    #[error("OutOfFuel: plugin consumed more than {0} fuel")]
+    OutOfFuel(u64),
+    #[error("OutOfMemory: plugin grew its memory beyond {0} bytes")]
+    OutOfMemory(usize),
+    #[error("InvalidResult: {0}")]
+    InvalidResult(String),
+    #[error("TaskError: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Exports every plugin has to provide
+const EXPORTS: [&str; 3] = ["memory", "alloc", "handle"];
+
+/// WebAssembly module computing the responses of the mocks which refer to it
+/// by name. Modules are loaded from `PLUGINS_PATH` at the startup, named after
+/// the files, or through the admin apis.
+///
+/// A module exports
+/// - `memory`
+/// - `alloc(len: i32) -> i32`, memory for the host to write `len` bytes into
+/// - `handle(ptr: i32, len: i32) -> i64`, receives the request as json, with
+///   the path `params`, and returns the response json in the same shape as the
+///   one of the scripts, `{"status": .., "headers": {..}, "body": ..}`
+///
+/// and can import from `env`
+/// - `log(level: i32, ptr: i32, len: i32)`, `0` error, `1` warn, `2` info and
+///   anything else debug
+/// - `state_get(key_ptr: i32, key_len: i32) -> i64`, json of the value in the
+///   memory allocated with `alloc`, `0` if there is no such key
+/// - `state_set(key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32)`,
+///   value as json
+/// - `state_remove(key_ptr: i32, key_len: i32)`
+///
+/// on the key value state shared with the scripts. Memory regions are passed
+/// as `i64` with the pointer in the high and the length in the low 32 bits.
+pub struct Plugin {
+    name: String,
+    module: wasmi::Module,
+    size: usize,
+}
+
+/// Plugin a mock responds with
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PluginHandler {
+    pub name: String,
+    /// Execution limit, `10000000` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PluginInfo {
+    pub name: String,
+    pub size: usize,
+}
+
+static ENGINE: LazyLock<wasmi::Engine> = LazyLock::new(|| {
+    let mut config = wasmi::Config::default();
+    config.consume_fuel(true);
+    wasmi::Engine::new(&config)
+});
+
+static PLUGINS: LazyLock<RwLock<HashMap<String, Arc<Plugin>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Compiles the module and registers it under the name, replacing the plugin
+/// with the same name
+pub fn load(name: &str, bytes: &[u8]) -> Result<PluginInfo, PluginError> {
+    let module = wasmi::Module::new(&ENGINE, bytes)?;
+    for export in EXPORTS {
+        if module.get_export(export).is_none() {
+            return Err(PluginError::MissingExport(export));
+        }
+    }
+    let plugin = Plugin {
+        name: name.to_string(),
+        module,
+        size: bytes.len(),
+    };
+    let info = plugin.info();
+    PLUGINS
+        .write()
+        .expect("plugins lock poisoned")
+        .insert(name.to_string(), Arc::new(plugin));
+    Ok(info)
+}
+
+pub fn remove(name: &str) -> Option<PluginInfo> {
+    PLUGINS
+        .write()
+        .expect("plugins lock poisoned")
+        .remove(name)
+        .map(|plugin| plugin.info())
+}
+
+/// Loaded plugins sorted by name
+pub fn list() -> Vec<PluginInfo> {
+    let mut plugins: Vec<PluginInfo> = PLUGINS
+        .read()
+        .expect("plugins lock poisoned")
+        .values()
+        .map(|plugin| plugin.info())
+        .collect();
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    plugins
+}
+
+fn get(name: &str) -> Result<Arc<Plugin>, PluginError> {
+    PLUGINS
+        .read()
+        .expect("plugins lock poisoned")
+        .get(name)
+        .cloned()
+        .ok_or_else(|| PluginError::NotFound(name.to_string()))
+}
+
+/// Loads every `*.wasm` file in `PLUGINS_PATH`, a plugin which fails to load is
+/// skipped so that the rest keep working
+pub fn init() {
+    let Some(path) = crate::utils::read_plugins_path() else {
+        return;
+    };
+    let entries = match std::fs::read_dir(path.as_str()) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!(target = "PluginLoadError", "Error: {}: {}", path, e);
+            return;
+        }
+    };
+    for file in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if file.extension().map(|e| e != "wasm").unwrap_or(true) {
+            continue;
+        }
+        let name = file
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let loaded = std::fs::read(&file)
+            .map_err(|source| PluginError::IO {
+                path: file.display().to_string(),
+                source,
+            })
+            .and_then(|bytes| load(name.as_str(), &bytes));
+        match loaded {
+            Ok(_) => tracing::info!("Loaded plugin {} from: {}", name, file.display()),
+            Err(e) => tracing::error!(target = "PluginLoadError", "Error: {}: {}", name, e),
+        }
+    }
+}
+
+fn unpack(region: i64) -> (usize, usize) {
+    (
+        (region as u64 >> 32) as usize,
+        (region as u64 & 0xffff_ffff) as usize,
+    )
+}
+
+fn memory(caller: &wasmi::Caller<'_, wasmi::StoreLimits>) -> Result<wasmi::Memory, wasmi::Error> {
+    caller
+        .get_export("memory")
+        .and_then(|e| e.into_memory())
+        .ok_or_else(|| wasmi::Error::new("plugin does not export memory"))
+}
+
+/// Bytes of the region, `None` if it is not within the memory, the region is
+/// read in place so a plugin can not make the host allocate more than it has
+fn region(memory: &[u8], ptr: usize, len: usize) -> Option<&[u8]> {
+    memory.get(ptr..ptr.checked_add(len)?)
+}
+
+fn read(
+    caller: &wasmi::Caller<'_, wasmi::StoreLimits>,
+    ptr: i32,
+    len: i32,
+) -> Result<String, wasmi::Error> {
+    let memory = memory(caller)?;
+    let bytes = region(
+        memory.data(caller),
+        ptr as u32 as usize,
+        len as u32 as usize,
+    )
+    .ok_or_else(|| wasmi::Error::new("region out of the plugin memory"))?;
+    Ok(String::from_utf8_lossy(bytes).to_string())
+}
+
+/// Writes the bytes into the memory allocated by the plugin, returns the packed region
+fn write(
+    caller: &mut wasmi::Caller<'_, wasmi::StoreLimits>,
+    bytes: &[u8],
+) -> Result<i64, wasmi::Error> {
+    let alloc = caller
+        .get_export("alloc")
+        .and_then(|e| e.into_func())
+        .ok_or_else(|| wasmi::Error::new("plugin does not export alloc"))?
+        .typed::<i32, i32>(&*caller)?;
+    let ptr = alloc.call(&mut *caller, bytes.len() as i32)?;
+    memory(caller)?
+        .write(&mut *caller, ptr as u32 as usize, bytes)
+        .map_err(|e| wasmi::Error::new(e.to_string()))?;
+    Ok(((ptr as u32 as i64) << 32) | bytes.len() as i64)
+}
+
+fn linker() -> Result<wasmi::Linker<wasmi::StoreLimits>, wasmi::Error> {
+    let mut linker = wasmi::Linker::<wasmi::StoreLimits>::new(&ENGINE);
+    linker.func_wrap(
+        "env",
+        "log",
+        |caller: wasmi::Caller<'_, wasmi::StoreLimits>, level: i32, ptr: i32, len: i32| {
+            let message = read(&caller, ptr, len)?;
+            match level {
+                0 => tracing::error!(target = "plugin", "{}", message),
+                1 => tracing::warn!(target = "plugin", "{}", message),
+                2 => tracing::info!(target = "plugin", "{}", message),
+                _ => tracing::debug!(target = "plugin", "{}", message),
+            }
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "state_get",
+        |mut caller: wasmi::Caller<'_, wasmi::StoreLimits>, ptr: i32, len: i32| {
+            let key = read(&caller, ptr, len)?;
+            match crate::script::state_get(key.as_str()) {
+                Some(value) => write(&mut caller, value.to_string().as_bytes()),
+                None => Ok(0),
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "state_set",
+        |caller: wasmi::Caller<'_, wasmi::StoreLimits>,
+         key_ptr: i32,
+         key_len: i32,
+         ptr: i32,
+         len: i32| {
+            let key = read(&caller, key_ptr, key_len)?;
+            let value = serde_json::from_str(read(&caller, ptr, len)?.as_str())
+                .map_err(|e| wasmi::Error::new(e.to_string()))?;
+            crate::script::state_set(key.as_str(), value);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "state_remove",
+        |caller: wasmi::Caller<'_, wasmi::StoreLimits>, ptr: i32, len: i32| {
+            crate::script::state_remove(read(&caller, ptr, len)?.as_str());
+            Ok(())
+        },
+    )?;
+    Ok(linker)
+}
+
+impl Plugin {
+    fn info(&self) -> PluginInfo {
+        PluginInfo {
+            name: self.name.to_string(),
+            size: self.size,
+        }
+    }
+
+    /// Runs `handle` on a fresh instance so that no memory is shared between
+    /// the requests, state is kept with `state_set` instead
+    fn run(&self, request: &[u8], fuel: u64) -> Result<Vec<u8>, PluginError> {
+        let limits = wasmi::StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY)
+            .trap_on_grow_failure(true)
+            .build();
+        let mut store = wasmi::Store::new(&ENGINE, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(fuel)?;
+        let out_of_fuel = |e: wasmi::Error| match e.as_trap_code() {
+            Some(wasmi::core::TrapCode::OutOfFuel) => PluginError::OutOfFuel(fuel),
+            Some(wasmi::core::TrapCode::GrowthOperationLimited) => {
+                PluginError::OutOfMemory(MAX_MEMORY)
+            }
+            _ => e.into(),
+        };
+        let instance = linker()?
+            .instantiate(&mut store, &self.module)
+            .map_err(out_of_fuel)?
+            .start(&mut store)
+            .map_err(out_of_fuel)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or(PluginError::MissingExport("memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+        let handle = instance.get_typed_func::<(i32, i32), i64>(&store, "handle")?;
+
+        let ptr = alloc
+            .call(&mut store, request.len() as i32)
+            .map_err(out_of_fuel)?;
+        memory
+            .write(&mut store, ptr as u32 as usize, request)
+            .map_err(|e| PluginError::InvalidResult(e.to_string()))?;
+        let region = handle
+            .call(&mut store, (ptr, request.len() as i32))
+            .map_err(out_of_fuel)?;
+        let (ptr, len) = unpack(region);
+        self::region(memory.data(&store), ptr, len)
+            .map(|response| response.to_vec())
+            .ok_or_else(|| {
+                PluginError::InvalidResult(format!(
+                    "response at {ptr} of {len} bytes is out of the plugin memory"
+                ))
+            })
+    }
+}
+
+/// Response of the plugin to the request, the plugin runs on the blocking pool
+/// so that a run burning through its fuel does not hold up the other requests
+pub async fn handle(
+    handler: &PluginHandler,
+    request: &crate::utils::MockRequest,
+    params: &HashMap<String, String>,
+) -> Result<crate::script::ScriptResponse, PluginError> {
+    let plugin = get(handler.name.as_str())?;
+    let context = request.template_context(params);
+    let input = serde_json::to_vec(&context["request"])
+        .map_err(|e| PluginError::InvalidResult(e.to_string()))?;
+    let fuel = handler.fuel.unwrap_or(DEFAULT_FUEL);
+    let output = tokio::task::spawn_blocking(move || plugin.run(&input, fuel)).await??;
+    serde_json::from_slice::<crate::script::ScriptResponse>(&output)
+        .map_err(|e| PluginError::InvalidResult(e.to_string()))?
+        .check()
+        .map_err(PluginError::InvalidResult)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(name: &str, body: &str) -> Arc<Plugin> {
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+                {body})"#
+        );
+        load(name, &wat::parse_str(wat).expect("valid wat")).expect("valid plugin");
+        get(name).expect("loaded plugin")
+    }
+
+    #[test]
+    fn plugins_return_the_response_region() {
+        // echoes the request back
+        let plugin = plugin(
+            "test-echo",
+            r#"(func (export "handle") (param i32 i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get 0)) (i64.const 32))
+                    (i64.extend_i32_u (local.get 1))))"#,
+        );
+        assert_eq!(plugin.run(b"{}", DEFAULT_FUEL).expect("response"), b"{}");
+    }
+
+    #[test]
+    fn plugins_running_out_of_fuel_are_stopped() {
+        let plugin = plugin(
+            "test-loop",
+            r#"(func (export "handle") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0))"#,
+        );
+        assert!(matches!(
+            plugin.run(b"{}", 10_000),
+            Err(PluginError::OutOfFuel(10_000))
+        ));
+    }
+
+    #[test]
+    fn plugins_growing_beyond_the_memory_limit_are_stopped() {
+        let pages = (MAX_MEMORY / 65536 + 1) as i32;
+        let plugin = plugin(
+            "test-grow",
+            format!(
+                r#"(func (export "handle") (param i32 i32) (result i64)
+                    (drop (memory.grow (i32.const {pages})))
+                    (i64.const 0))"#
+            )
+            .as_str(),
+        );
+        assert!(matches!(
+            plugin.run(b"{}", DEFAULT_FUEL),
+            Err(PluginError::OutOfMemory(MAX_MEMORY))
+        ));
+    }
+
+    #[test]
+    fn plugins_starting_beyond_the_memory_limit_are_refused() {
+        let pages = MAX_MEMORY / 65536 + 1;
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") {pages})
+                (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+                (func (export "handle") (param i32 i32) (result i64) (i64.const 0)))"#
+        );
+        load("test-big", &wat::parse_str(wat).expect("valid wat")).expect("valid plugin");
+        let plugin = get("test-big").expect("loaded plugin");
+        assert!(plugin.run(b"{}", DEFAULT_FUEL).is_err());
+    }
+
+    #[test]
+    fn responses_out_of_the_memory_are_refused() {
+        let plugin = plugin(
+            "test-outside",
+            r#"(func (export "handle") (param i32 i32) (result i64)
+                (i64.const 0xffffffff))"#,
+        );
+        assert!(matches!(
+            plugin.run(b"{}", DEFAULT_FUEL),
+            Err(PluginError::InvalidResult(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn invalid_statuses_and_headers_are_refused() {
+        // answers with the 15 bytes of `{"status":1000}` at the offset 0
+        plugin(
+            "test-status",
+            r#"(data (i32.const 0) "{\"status\":1000}")
+                (func (export "handle") (param i32 i32) (result i64)
+                (i64.const 15))"#,
+        );
+        let handler = PluginHandler {
+            name: "test-status".to_string(),
+            fuel: None,
+        };
+        let response = handle(
+            &handler,
+            &crate::utils::MockRequest::default(),
+            &HashMap::new(),
+        )
+        .await;
+        assert!(matches!(response, Err(PluginError::InvalidResult(_))));
+    }
+}
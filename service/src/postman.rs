@@ -0,0 +1,271 @@
+use crate::utils::API;
+
+/// Path of the Postman url, the host and `{{baseUrl}}` like variables in front
+/// of the path are dropped and `:id` segments become `{id}` parameters
+fn path(url: &serde_json::Value) -> String {
+    let segments: Vec<String> = match url {
+        serde_json::Value::Object(url) if url.get("path").is_some() => url
+            .get("path")
+            .and_then(|p| p.as_array())
+            .map(|p| {
+                // v2.0 collections may give a segment as `{"type": .., "value": ..}`
+                p.iter()
+                    .filter_map(|s| s.as_str().or_else(|| s.get("value")?.as_str()))
+                    .map(|s| s.to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        url => {
+            let raw = url
+                .get("raw")
+                .and_then(|r| r.as_str())
+                .or_else(|| url.as_str())
+                .unwrap_or_default();
+            let raw = raw.split(['?', '#']).next().unwrap_or_default();
+            let raw = match raw.split_once("://") {
+                Some((_, rest)) => rest,
+                None => raw,
+            };
+            // the first segment is the host or a variable standing for it
+            let mut segments: Vec<&str> = raw.split('/').collect();
+            if !raw.starts_with('/') {
+                segments.remove(0);
+            }
+            segments.iter().map(|s| s.to_string()).collect()
+        }
+    };
+    let path: Vec<String> = segments
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.strip_prefix(':') {
+            Some(param) => format!("{{{param}}}"),
+            None => match s.strip_prefix("{{").and_then(|s| s.strip_suffix("}}")) {
+                Some(variable) => format!("{{{variable}}}"),
+                None => s,
+            },
+        })
+        .collect();
+    format!("/{}", path.join("/"))
+}
+
+fn headers(headers: Option<&serde_json::Value>) -> Vec<(String, String)> {
+    headers
+        .and_then(|h| h.as_array())
+        .map(|h| {
+            h.iter()
+                .filter(|h| h.get("disabled").and_then(|d| d.as_bool()) != Some(true))
+                .filter_map(|h| {
+                    Some((
+                        h.get("key")?.as_str()?.to_string(),
+                        h.get("value")?.as_str()?.to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn raw_body(request: &serde_json::Value) -> Option<String> {
+    request
+        .get("body")
+        .filter(|b| b.get("mode").and_then(|m| m.as_str()) == Some("raw"))
+        .and_then(|b| b.get("raw"))
+        .and_then(|r| r.as_str())
+        .map(|r| r.to_string())
+}
+
+/// Walks the folders of the collection for the requests with examples
+fn requests<'a>(items: &'a serde_json::Value, out: &mut Vec<&'a serde_json::Value>) {
+    for item in items.as_array().into_iter().flatten() {
+        match item.get("item") {
+            Some(children) => requests(children, out),
+            None => out.push(item),
+        }
+    }
+}
+
+/// Mocks from the examples of the requests in a Postman collection, v2.0 or
+/// v2.1. Requests without examples are skipped. Among the examples with the
+/// same method and path, or the same body as well with `match_body`, a
+/// successful one is preferred, otherwise the last one wins.
+pub fn import(collection: &serde_json::Value, match_body: bool) -> Vec<API> {
+    let mut items = vec![];
+    requests(
+        collection.get("item").unwrap_or(&serde_json::Value::Null),
+        &mut items,
+    );
+    let mut apis = vec![];
+    for item in items {
+        let mut examples: Vec<&serde_json::Value> = item
+            .get("response")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().collect())
+            .unwrap_or_default();
+        // successful examples go last to win the de-duplication
+        examples.sort_by_key(|example| {
+            let code = example.get("code").and_then(|c| c.as_u64()).unwrap_or(200);
+            (200..300).contains(&code)
+        });
+        for example in examples {
+            let Some(request) = example
+                .get("originalRequest")
+                .or_else(|| item.get("request"))
+            else {
+                continue;
+            };
+            let url = request.get("url").cloned().unwrap_or_default();
+            let mut api = crate::recorder::Exchange {
+                method: request
+                    .get("method")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("GET")
+                    .to_string(),
+                url: path(&url),
+                request_body: raw_body(request),
+                status: example
+                    .get("code")
+                    .and_then(|c| c.as_u64())
+                    .and_then(|c| u16::try_from(c).ok())
+                    .unwrap_or(200),
+                headers: headers(example.get("header")),
+                body: example
+                    .get("body")
+                    .and_then(|b| b.as_str())
+                    .unwrap_or_default()
+                    .as_bytes()
+                    .to_vec(),
+            }
+            .into_api("postman", match_body);
+            // postman examples often leave out the content type of a json body
+            let preview = example
+                .get("_postman_previewlanguage")
+                .and_then(|l| l.as_str());
+            if let (Some(crate::utils::ResponseBody::Text(text)), Some("json")) =
+                (&api.body, preview)
+            {
+                if let Ok(json) = serde_json::from_str(text) {
+                    api.body = Some(crate::utils::ResponseBody::Json(json));
+                }
+            }
+            apis.push(api);
+        }
+    }
+    crate::recorder::dedupe(apis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn paths_drop_the_host_and_turn_variables_into_parameters() {
+        assert_eq!(
+            path(&json!({"raw": "{{baseUrl}}/users/:id?expand=true"})),
+            "/users/{id}"
+        );
+        assert_eq!(path(&json!("https://api.example.com/users")), "/users");
+        assert_eq!(
+            path(&json!({"path": ["users", "{{userId}}"]})),
+            "/users/{userId}"
+        );
+    }
+
+    #[test]
+    fn path_segments_may_be_objects() {
+        let url = json!({"path": [
+            {"type": "string", "value": "users"},
+            {"type": "string", "value": ":id"}
+        ]});
+        assert_eq!(path(&url), "/users/{id}");
+    }
+
+    fn example(name: &str, code: u16, body: &str) -> serde_json::Value {
+        json!({
+            "name": name,
+            "code": code,
+            "header": [
+                {"key": "X-Request-Id", "value": name},
+                {"key": "X-Debug", "value": "1", "disabled": true}
+            ],
+            "_postman_previewlanguage": "json",
+            "body": body
+        })
+    }
+
+    #[test]
+    fn examples_become_mocks() {
+        let collection = json!({"item": [{
+            "name": "users",
+            "item": [{
+                "name": "get user",
+                "request": {"method": "GET", "url": {"raw": "{{baseUrl}}/users/:id"}},
+                "response": [example("found", 200, "{\"id\": 1}")]
+            }, {
+                "name": "without examples",
+                "request": {"method": "GET", "url": "{{baseUrl}}/health"}
+            }]
+        }]});
+        let apis = import(&collection, false);
+        assert_eq!(apis.len(), 1);
+        let api = &apis[0];
+        assert_eq!(
+            (api.method.as_str(), api.path.as_str()),
+            ("GET", "/users/{id}")
+        );
+        assert_eq!(api.tags, ["postman"]);
+        assert_eq!(api.headers["x-request-id"], "found");
+        assert!(!api.headers.contains_key("x-debug"));
+        assert!(matches!(
+            &api.body,
+            Some(crate::utils::ResponseBody::Json(body)) if *body == json!({"id": 1})
+        ));
+    }
+
+    #[test]
+    fn successful_examples_are_preferred() {
+        let collection = json!({"item": [{
+            "request": {"method": "POST", "url": "{{baseUrl}}/orders"},
+            "response": [
+                example("created", 201, "{}"),
+                example("invalid", 400, "{}")
+            ]
+        }]});
+        let apis = import(&collection, false);
+        assert_eq!(apis.len(), 1);
+        assert_eq!(apis[0].status, Some(201));
+    }
+
+    #[test]
+    fn bodies_of_the_original_requests_are_matched() {
+        let request = |amount: u64| {
+            json!({
+                "method": "POST",
+                "url": "{{baseUrl}}/payments",
+                "body": {"mode": "raw", "raw": json!({"amount": amount}).to_string()}
+            })
+        };
+        let mut accepted = example("accepted", 200, "{}");
+        accepted["originalRequest"] = request(1);
+        let mut declined = example("declined", 402, "{}");
+        declined["originalRequest"] = request(2);
+        let collection = json!({"item": [{
+            "request": request(1),
+            "response": [accepted, declined]
+        }]});
+        assert_eq!(import(&collection, false).len(), 1);
+        let apis = import(&collection, true);
+        let bodies: Vec<_> = apis
+            .iter()
+            .map(|api| {
+                (
+                    api.status,
+                    api.request.as_ref().and_then(|r| r.body.clone()),
+                )
+            })
+            .collect();
+        assert_eq!(bodies.len(), 2);
+        assert!(bodies.contains(&(Some(402), Some(json!({"amount": 2})))));
+        assert!(bodies.contains(&(Some(200), Some(json!({"amount": 1})))));
+    }
+}
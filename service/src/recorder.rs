@@ -0,0 +1,408 @@
+use crate::utils::{ResponseBody, API};
+use std::sync::LazyLock;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RecordError {
+    #[error("UpstreamNotConfigured: PROXY_UPSTREAM env var not found")]
+    UpstreamNotConfigured,
+    #[error("InvalidUri: {0}")]
+    InvalidUri(#[from] hyper::http::uri::InvalidUri),
+    #[error("RequestBuildError: {0}")]
+    RequestBuild(#[from] hyper::http::Error),
+    #[error("UpstreamError: {0}")]
+    Upstream(#[from] hyper::Error),
+    #[error("RecordFileError: {0}")]
+    RecordFile(#[from] std::io::Error),
+    #[error("JsonError: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// `MOCK_MODE` of the service
+/// - `mock`, the default, serves the mocks and 404 for the unmatched requests
+/// - `record` forwards the unmatched requests to `PROXY_UPSTREAM` and records
+///   the responses as mocks in `RECORD_FILE`
+/// - `playback` serves only the recorded mocks from `RECORD_FILE`
+pub fn is_record() -> bool {
+    crate::utils::read_mock_mode().eq("record")
+}
+
+pub fn is_playback() -> bool {
+    crate::utils::read_mock_mode().eq("playback")
+}
+
+type Client = hyper::Client<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>;
+
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_http1()
+        .build();
+    hyper::Client::builder().build(connector)
+});
+
+/// Client shared by everything making outbound calls
+pub fn client() -> &'static Client {
+    &CLIENT
+}
+
+// Serializes the writes to the record file
+static RECORD_LOCK: LazyLock<tokio::sync::Mutex<()>> =
+    LazyLock::new(|| tokio::sync::Mutex::new(()));
+
+pub const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+fn csv(value: Option<String>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Redacts and removes the configured fields at any depth of the json
+fn scrub(value: &mut serde_json::Value, redact: &[String], ignore: &[String]) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|k, _| !ignore.contains(&k.to_lowercase()));
+            for (k, v) in map.iter_mut() {
+                if redact.contains(&k.to_lowercase()) {
+                    *v = serde_json::Value::String("REDACTED".to_string());
+                } else {
+                    scrub(v, redact, ignore);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|v| scrub(v, redact, ignore)),
+        _ => {}
+    }
+}
+
+/// Forwards the request to the upstream, records the response as a mock and
+/// returns the upstream response as is, along with its body for the journal
+pub async fn forward(
+    parts: &hyper::http::request::Parts,
+    body: hyper::body::Bytes,
+    request: &crate::utils::MockRequest,
+) -> Result<(hyper::Response<hyper::Body>, Vec<u8>), RecordError> {
+    let upstream = crate::utils::read_proxy_upstream().ok_or(RecordError::UpstreamNotConfigured)?;
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    let uri: hyper::Uri =
+        format!("{}{}", upstream.trim_end_matches('/'), path_and_query).parse()?;
+
+    let mut builder = hyper::Request::builder()
+        .method(parts.method.clone())
+        .uri(uri);
+    for (name, value) in parts.headers.iter() {
+        if name == hyper::header::HOST || HOP_BY_HOP.contains(&name.as_str()) {
+            continue;
+        }
+        builder = builder.header(name, value);
+    }
+    let upstream_response = client()
+        .request(builder.body(hyper::Body::from(body))?)
+        .await?;
+    let (upstream_parts, upstream_body) = upstream_response.into_parts();
+    let bytes = hyper::body::to_bytes(upstream_body).await?.to_vec();
+    tracing::info!(
+        target = "record",
+        method = parts.method.as_str(),
+        path = parts.uri.path(),
+        status = upstream_parts.status.as_u16()
+    );
+
+    save(capture(request, &upstream_parts, &bytes)).await?;
+
+    let mut response = hyper::Response::new(hyper::Body::from(bytes.clone()));
+    *response.status_mut() = upstream_parts.status;
+    for (name, value) in upstream_parts.headers.iter() {
+        if HOP_BY_HOP.contains(&name.as_str()) || name == hyper::header::CONTENT_LENGTH {
+            continue;
+        }
+        response.headers_mut().append(name, value.clone());
+    }
+    Ok((response, bytes))
+}
+
+/// Builds the mock from the upstream response, with the query and the json
+/// body of the request as the matchers
+fn capture(
+    request: &crate::utils::MockRequest,
+    parts: &hyper::http::response::Parts,
+    bytes: &[u8],
+) -> API {
+    let redact_headers = csv(crate::utils::read_record_redact_headers());
+    let ignore_headers = csv(crate::utils::read_record_ignore_headers());
+    let redact_fields = csv(crate::utils::read_record_redact_fields());
+    let ignore_fields = csv(crate::utils::read_record_ignore_fields());
+
+    let content_type = parts
+        .headers
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut headers = std::collections::HashMap::new();
+    let mut cookies = std::collections::HashMap::new();
+    for (name, value) in parts.headers.iter() {
+        let name = name.as_str();
+        if HOP_BY_HOP.contains(&name)
+            || ["content-length", "content-type", "date"].contains(&name)
+            || ignore_headers.contains(&name.to_string())
+        {
+            continue;
+        }
+        let redact = redact_headers.contains(&name.to_string());
+        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+        if name == "set-cookie" {
+            // the mock sets them back as `<name>=<value>`, attributes included
+            if let Some((cookie, rest)) = value.split_once('=') {
+                let rest = match (redact, rest.split_once(';')) {
+                    (true, Some((_, attributes))) => format!("REDACTED;{attributes}"),
+                    (true, None) => "REDACTED".to_string(),
+                    (false, _) => rest.to_string(),
+                };
+                cookies.insert(cookie.trim().to_string(), rest);
+            }
+            continue;
+        }
+        let value = if redact {
+            "REDACTED".to_string()
+        } else {
+            value
+        };
+        headers.insert(name.to_string(), value);
+    }
+
+    let body = match response_body(content_type.as_str(), bytes) {
+        Some(ResponseBody::Json(mut json)) => {
+            scrub(&mut json, &redact_fields, &ignore_fields);
+            Some(ResponseBody::Json(json))
+        }
+        body => body,
+    };
+    if let Some(content_type) = parts.headers.get(hyper::header::CONTENT_TYPE) {
+        headers.insert(
+            "content-type".to_string(),
+            String::from_utf8_lossy(content_type.as_bytes()).to_string(),
+        );
+    }
+
+    let mut request_body = request.body.clone();
+    scrub(&mut request_body, &[], &redact_fields);
+    scrub(&mut request_body, &[], &ignore_fields);
+    let request_match = crate::matcher::RequestMatch {
+        query: request
+            .query
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_string(),
+                    crate::matcher::ValueMatch::Equals(v.to_string()),
+                )
+            })
+            .collect(),
+        body: match request_body {
+            serde_json::Value::Null => None,
+            serde_json::Value::Object(map) if map.is_empty() => None,
+            body => Some(body),
+        },
+        ..Default::default()
+    };
+    let has_match = !request_match.query.is_empty() || request_match.body.is_some();
+
+    API {
+        id: Some(uuid::Uuid::new_v4().to_string()),
+        tags: vec!["recorded".to_string()],
+        method: request.method.to_string(),
+        path: request.path.to_string(),
+        body,
+        status: Some(parts.status.as_u16()),
+        headers,
+        cookies,
+        request: if has_match { Some(request_match) } else { None },
+        ..Default::default()
+    }
+}
+
+/// Response body by the content type, json, xml, text or base64 encoded binary
+pub fn response_body(content_type: &str, bytes: &[u8]) -> Option<ResponseBody> {
+    use base64::Engine;
+    match serde_json::from_slice::<serde_json::Value>(bytes) {
+        Ok(json) if content_type.contains("json") => Some(ResponseBody::Json(json)),
+        _ if bytes.is_empty() => None,
+        _ => match std::str::from_utf8(bytes) {
+            Ok(text) if content_type.contains("xml") => Some(ResponseBody::Xml(text.to_string())),
+            Ok(text) => Some(ResponseBody::Text(text.to_string())),
+            Err(_) => Some(ResponseBody::Base64(
+                base64::engine::general_purpose::STANDARD.encode(bytes),
+            )),
+        },
+    }
+}
+
+/// Mocks with the same key, method, path and matchers, answer the same requests
+pub fn key(api: &API) -> String {
+    // the matchers keep their rules in hash maps, which serialize in any order,
+    // a json value keeps the keys of its objects sorted
+    let request = serde_json::to_value(&api.request).unwrap_or_default();
+    format!("{} {} {}", api.method, api.path, request)
+}
+
+/// Request and response captured by another tool, like a HAR entry or an
+/// example of a Postman request
+pub struct Exchange {
+    pub method: String,
+    /// Absolute url or just the path, the query is left out of the mock
+    pub url: String,
+    pub request_body: Option<String>,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Exchange {
+    /// Mock responding to the request with the response, the json body of the
+    /// request becomes the body matcher if `match_body` is set
+    pub fn into_api(self, tag: &str, match_body: bool) -> API {
+        let path = match self.url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+            None => self.url.as_str(),
+        };
+        let path = path.split(['?', '#']).next().unwrap_or("/");
+        let content_type = self
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.to_lowercase())
+            .unwrap_or_default();
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.to_string()))
+            .filter(|(name, _)| {
+                !HOP_BY_HOP.contains(&name.as_str())
+                    && !["content-length", "content-encoding", "date", "set-cookie"]
+                        .contains(&name.as_str())
+            })
+            .collect();
+        let request_body = self
+            .request_body
+            .filter(|_| match_body)
+            .and_then(|body| serde_json::from_str::<serde_json::Value>(body.as_str()).ok());
+        API {
+            tags: vec![tag.to_string()],
+            method: self.method.to_uppercase(),
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+            body: response_body(content_type.as_str(), &self.body),
+            status: Some(self.status),
+            headers,
+            request: request_body.map(|body| crate::matcher::RequestMatch {
+                body: Some(body),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// Keeps the last of the mocks with the same key, in the place of the first
+pub fn dedupe(apis: Vec<API>) -> Vec<API> {
+    let mut deduped: Vec<API> = vec![];
+    for api in apis {
+        let api_key = key(&api);
+        match deduped.iter().position(|a| key(a) == api_key) {
+            Some(index) => deduped[index] = api,
+            None => deduped.push(api),
+        }
+    }
+    deduped
+}
+
+/// Appends the mock to the record file, replacing an earlier recording of the
+/// same method, path and matchers
+async fn save(api: API) -> Result<(), RecordError> {
+    let _lock = RECORD_LOCK.lock().await;
+    let path = crate::utils::read_record_file();
+    let mut apis: Vec<API> = match tokio::fs::read(path.as_str()).await {
+        Ok(content) => serde_json::from_slice(&content)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e.into()),
+    };
+    let recorded = key(&api);
+    match apis.iter().position(|a| key(a) == recorded) {
+        Some(index) => apis[index] = api,
+        None => apis.push(api),
+    }
+    tokio::fs::write(path.as_str(), serde_json::to_vec_pretty(&apis)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api(names: &[&str]) -> API {
+        let rules = names
+            .iter()
+            .map(|n| {
+                (
+                    n.to_string(),
+                    crate::matcher::ValueMatch::Equals(n.to_string()),
+                )
+            })
+            .collect();
+        API {
+            method: "GET".to_string(),
+            path: "/users".to_string(),
+            request: Some(crate::matcher::RequestMatch {
+                headers: rules,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn key_does_not_depend_on_the_order_of_the_rules() {
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let mut reversed = names;
+        reversed.reverse();
+        for _ in 0..16 {
+            assert_eq!(key(&api(&names)), key(&api(&reversed)));
+        }
+    }
+
+    #[test]
+    fn key_tells_the_matchers_apart() {
+        assert_ne!(key(&api(&["a"])), key(&api(&["b"])));
+    }
+
+    #[test]
+    fn set_cookies_are_kept_as_cookies() {
+        let (parts, _) = hyper::Response::builder()
+            .header("set-cookie", "session=abc; Path=/; HttpOnly")
+            .header("set-cookie", "theme=dark")
+            .body(())
+            .expect("valid response")
+            .into_parts();
+        let api = capture(&crate::utils::MockRequest::default(), &parts, b"");
+        assert_eq!(api.cookies["session"], "abc; Path=/; HttpOnly");
+        assert_eq!(api.cookies["theme"], "dark");
+        assert!(!api.headers.contains_key("set-cookie"));
+    }
+}
@@ -0,0 +1,420 @@
+use crate::utils::{APIs, API};
+use std::collections::HashSet;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, RwLock};
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoadError {
+    #[error("IOError: {path}: {source}")]
+    IO {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("ParseError: {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[error("CompileError: {0}")]
+    Compile(#[from] crate::utils::CompileError),
+    #[error("StoreError: {0}")]
+    Store(#[from] crate::store::StoreError),
+    #[error("OpenAPIError: {0}")]
+    OpenAPI(#[from] crate::openapi::OpenAPIError),
+}
+
+// Currently running set of mocks, readers take a cheap clone of the `Arc` so a
+// reload swapping the registry never affects a request which is in flight.
+static REGISTRY: LazyLock<RwLock<Arc<APIs>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(APIs::default())));
+
+pub fn current() -> Arc<APIs> {
+    REGISTRY.read().expect("registry lock poisoned").clone()
+}
+
+// Serializes the changes to the registry, the changes are compiled and stored
+// without holding the registry lock so that the requests keep being served
+static UPDATE: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+// Ids of the mocks read from the files on the last load, the rest of the
+// registry was added through the admin apis and is kept on a reload
+static LOADED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+pub fn swap(apis: APIs) {
+    *REGISTRY.write().expect("registry lock poisoned") = Arc::new(apis);
+}
+
+/// Swaps the registry with the mocks, the registry lock is only held to carry
+/// over the hit counters counted up to the swap
+fn swap_with_hits(apis: APIs) {
+    let mut registry = REGISTRY.write().expect("registry lock poisoned");
+    *registry = Arc::new(apis.with_hits(&registry));
+}
+
+/// Applies the change on the mock definitions and swaps the registry with the
+/// recompiled mocks, concurrent updates are applied one after the other so
+/// none of them is lost. Hit counters are carried over by the mock ids.
+/// Registry is left untouched if the change, the compilation or writing to the
+/// sqlite store fails.
+pub fn update<T, E>(f: impl FnOnce(&mut Vec<API>) -> Result<T, E>) -> Result<T, E>
+where
+    E: From<crate::utils::CompileError> + From<crate::store::StoreError>,
+{
+    let update = UPDATE.lock().expect("registry update lock poisoned");
+    apply(&update, f)
+}
+
+/// Body of `update`, for the callers already holding the update lock
+fn apply<T, E>(
+    _update: &MutexGuard<()>,
+    f: impl FnOnce(&mut Vec<API>) -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: From<crate::utils::CompileError> + From<crate::store::StoreError>,
+{
+    let old = current().apis();
+    let mut apis = old.clone();
+    let out = f(&mut apis)?;
+    let apis: Vec<API> = apis.into_iter().map(API::with_id).collect();
+    let compiled = APIs::new(apis.clone())?;
+    if crate::store::is_sqlite() {
+        crate::store::persist(&old, &apis)?;
+    }
+    swap_with_hits(compiled);
+    Ok(out)
+}
+
+/// Definition of the mock without its id
+fn definition(api: &API) -> serde_json::Value {
+    serde_json::to_value(API {
+        id: None,
+        ..api.clone()
+    })
+    .unwrap_or_default()
+}
+
+/// Swaps the registry with the mocks read from the files, keeping the mocks
+/// added through the admin apis and the hit counters. A mock without an id in
+/// the files keeps the id it got on the last load as long as it is unchanged.
+fn reload(files: Vec<API>) -> Result<usize, LoadError> {
+    let _update = UPDATE.lock().expect("registry update lock poisoned");
+    let previous = current().apis();
+    let mut loaded = LOADED.lock().expect("registry loaded lock poisoned");
+    let mut unchanged: Vec<(serde_json::Value, String)> = previous
+        .iter()
+        .filter_map(|api| {
+            let id = api.id.as_ref().filter(|id| loaded.contains(*id))?;
+            Some((definition(api), id.to_string()))
+        })
+        .collect();
+    let files: Vec<API> = files
+        .into_iter()
+        .map(|mut api| {
+            if api.id.is_none() {
+                let definition = definition(&api);
+                if let Some(index) = unchanged.iter().position(|(d, _)| *d == definition) {
+                    api.id = Some(unchanged.swap_remove(index).1);
+                }
+            }
+            api.with_id()
+        })
+        .collect();
+    let ids: HashSet<String> = files.iter().filter_map(|api| api.id.clone()).collect();
+    let count = files.len();
+    let mut apis = files;
+    apis.extend(previous.into_iter().filter(|api| {
+        api.id
+            .as_ref()
+            .map(|id| !loaded.contains(id) && !ids.contains(id))
+            .unwrap_or(true)
+    }));
+    swap_with_hits(APIs::new(apis)?);
+    *loaded = ids;
+    Ok(count)
+}
+
+/// Replaces the registry with the mocks from the files and resets the hit
+/// counters, with the sqlite store the stored mocks are replaced as well
+pub fn reset() -> Result<usize, LoadError> {
+    let apis = read_all()?;
+    let count = apis.len();
+    // the loaded ids are written under the update lock as well, so that a
+    // concurrent reload does not see them out of step with the registry
+    let update = UPDATE.lock().expect("registry update lock poisoned");
+    apply(&update, |current| {
+        *current = apis;
+        Ok::<_, LoadError>(())
+    })?;
+    let registry = current();
+    registry.reset_hits();
+    *LOADED.lock().expect("registry loaded lock poisoned") = registry
+        .apis()
+        .into_iter()
+        .filter_map(|api| api.id)
+        .collect();
+    Ok(count)
+}
+
+/// Mocks are read from `MOCKS_PATH`, or from `RECORD_FILE` in the playback mode
+pub fn source() -> String {
+    if crate::recorder::is_playback() {
+        crate::utils::read_record_file()
+    } else {
+        crate::utils::read_mocks_path()
+    }
+}
+
+/// Mock definition files, `MOCKS_PATH` can either point to a single json file
+/// or to a directory, in which case every `*.json` file in it is read in the
+/// sorted order of file names.
+fn files(path: &std::path::Path) -> Result<Vec<std::path::PathBuf>, LoadError> {
+    let io_err = |source| LoadError::IO {
+        path: path.display().to_string(),
+        source,
+    };
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = vec![];
+    for entry in std::fs::read_dir(path).map_err(io_err)? {
+        let entry = entry.map_err(io_err)?;
+        let file = entry.path();
+        if file.is_file() && file.extension().map(|e| e == "json").unwrap_or(false) {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn load() -> Result<APIs, LoadError> {
+    Ok(APIs::new(read_all()?)?)
+}
+
+/// Mocks from the files along with the ones generated from the OpenAPI specs,
+/// the specs are left out in the playback mode to serve only the recordings
+pub fn read_all() -> Result<Vec<API>, LoadError> {
+    let mut apis = read(source().as_str())?;
+    if !crate::recorder::is_playback() {
+        apis.extend(crate::openapi::read_all()?);
+    }
+    Ok(apis)
+}
+
+/// Reads the mock definitions from the files without compiling them
+pub fn read(path: &str) -> Result<Vec<API>, LoadError> {
+    let mut apis = vec![];
+    for file in files(std::path::Path::new(path))? {
+        let content = std::fs::read_to_string(&file).map_err(|source| LoadError::IO {
+            path: file.display().to_string(),
+            source,
+        })?;
+        let file_apis: Vec<API> =
+            serde_json::from_str(&content).map_err(|source| LoadError::Parse {
+                path: file.display().to_string(),
+                source,
+            })?;
+        apis.extend(file_apis);
+    }
+    Ok(apis)
+}
+
+/// Loads the mocks at the startup, service starts with an empty registry if
+/// the files can not be read so that these can be fixed and picked up by the
+/// watcher without restarting.
+pub fn init() {
+    let path = source();
+    if crate::store::is_sqlite() {
+        let seed = || {
+            read_all()
+                .map(|apis| apis.into_iter().map(API::with_id).collect())
+                .unwrap_or_else(|e| {
+                    tracing::error!(target = "RegistryLoadError", "Error: {}", e);
+                    vec![]
+                })
+        };
+        match crate::store::init(seed).map(APIs::new) {
+            Ok(Ok(apis)) => {
+                tracing::info!("Loaded {} mocks from the sqlite store", apis.len());
+                swap(apis);
+            }
+            Ok(Err(e)) => tracing::error!(target = "RegistryLoadError", "Error: {}", e),
+            Err(e) => panic!("cannot initialize the sqlite store: {e}"),
+        }
+        return;
+    }
+    match read_all().and_then(reload) {
+        Ok(count) => tracing::info!("Loaded {} mocks from: {}", count, path),
+        Err(e) => tracing::error!(target = "RegistryLoadError", "Error: {}", e),
+    }
+}
+
+type Fingerprint = Vec<(std::path::PathBuf, Option<std::time::SystemTime>, u64)>;
+
+fn fingerprint(path: &str) -> Fingerprint {
+    let mut files = files(std::path::Path::new(path)).unwrap_or_default();
+    files.extend(crate::openapi::paths().into_iter().map(Into::into));
+    files
+        .into_iter()
+        .map(|file| {
+            let meta = std::fs::metadata(&file).ok();
+            let modified = meta.as_ref().and_then(|m| m.modified().ok());
+            let len = meta.map(|m| m.len()).unwrap_or_default();
+            (file, modified, len)
+        })
+        .collect()
+}
+
+/// Polls the mock files and the OpenAPI specs for changes and reloads the mocks
+/// from the files on every change, the mocks added through the admin apis are
+/// kept. In case of an error last successfully loaded mocks keep serving. Files are
+/// not watched with the sqlite store, they are imported through the admin apis.
+pub async fn watch() {
+    if crate::store::is_sqlite() {
+        return;
+    }
+    let path = source();
+    let mut interval = tokio::time::interval(std::time::Duration::from_millis(
+        crate::utils::read_mocks_reload_interval(),
+    ));
+    let mut last = fingerprint(path.as_str());
+    loop {
+        interval.tick().await;
+        let current = fingerprint(path.as_str());
+        if current == last {
+            continue;
+        }
+        last = current;
+        match read_all().and_then(reload) {
+            Ok(count) => tracing::info!("Reloaded {} mocks from: {}", count, path),
+            Err(e) => tracing::error!(target = "RegistryReloadError", "Error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Serializes the tests changing the global registry, across the modules
+    static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    pub async fn serial() -> tokio::sync::MutexGuard<'static, ()> {
+        let guard = SERIAL.lock().await;
+        swap(APIs::default());
+        LOADED
+            .lock()
+            .expect("registry loaded lock poisoned")
+            .clear();
+        guard
+    }
+
+    fn api(definition: serde_json::Value) -> API {
+        serde_json::from_value(definition).expect("valid mock")
+    }
+
+    fn ids() -> Vec<(String, Option<String>)> {
+        current()
+            .apis()
+            .into_iter()
+            .map(|api| (api.path, api.id))
+            .collect()
+    }
+
+    fn id(path: &str) -> Option<String> {
+        ids()
+            .into_iter()
+            .find(|(p, _)| p == path)
+            .and_then(|(_, id)| id)
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_the_ids_of_the_unchanged_mocks() {
+        let _serial = serial().await;
+        reload(vec![api(json!({"method": "GET", "path": "/a"}))]).expect("reloaded");
+        let first = id("/a").expect("id given");
+        reload(vec![
+            api(json!({"method": "GET", "path": "/a"})),
+            api(json!({"method": "GET", "path": "/c"})),
+        ])
+        .expect("reloaded");
+        assert_eq!(id("/a"), Some(first.clone()));
+        // a changed mock is another mock
+        reload(vec![api(
+            json!({"method": "GET", "path": "/a", "status": 201}),
+        )])
+        .expect("reloaded");
+        assert_ne!(id("/a"), Some(first));
+        assert_eq!(id("/c"), None);
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_the_mocks_added_through_the_admin_apis() {
+        let _serial = serial().await;
+        reload(vec![api(
+            json!({"id": "file", "method": "GET", "path": "/a"}),
+        )])
+        .expect("reloaded");
+        update(|apis| {
+            apis.push(api(json!({"id": "admin", "method": "GET", "path": "/b"})));
+            Ok::<_, LoadError>(())
+        })
+        .expect("updated");
+        let count = reload(vec![api(json!({"method": "GET", "path": "/c"}))]).expect("reloaded");
+        assert_eq!(count, 1);
+        let paths: Vec<String> = ids().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, ["/c", "/b"]);
+    }
+
+    #[tokio::test]
+    async fn failed_updates_leave_the_registry_untouched() {
+        let _serial = serial().await;
+        update(|apis| {
+            apis.push(api(json!({"id": "a", "method": "GET", "path": "/a"})));
+            Ok::<_, LoadError>(())
+        })
+        .expect("updated");
+        let invalid = update(|apis| {
+            apis.push(api(json!({"method": "GET", "path": "/b", "status": 1000})));
+            Ok::<_, LoadError>(())
+        });
+        assert!(matches!(invalid, Err(LoadError::Compile(_))));
+        let refused = update(|apis| {
+            apis.clear();
+            Err::<(), _>(LoadError::IO {
+                path: "/a".to_string(),
+                source: std::io::Error::other("refused"),
+            })
+        });
+        assert!(refused.is_err());
+        assert_eq!(ids(), [("/a".to_string(), Some("a".to_string()))]);
+    }
+
+    #[tokio::test]
+    async fn updates_carry_the_hits_over() {
+        let _serial = serial().await;
+        update(|apis| {
+            apis.push(api(json!({"id": "a", "method": "GET", "path": "/a"})));
+            Ok::<_, LoadError>(())
+        })
+        .expect("updated");
+        let request = crate::utils::MockRequest {
+            method: "GET".to_string(),
+            path: "/a".to_string(),
+            ..Default::default()
+        };
+        current().response(&request).await.expect("response");
+        update(|apis| {
+            apis.push(api(json!({"id": "b", "method": "GET", "path": "/b"})));
+            Ok::<_, LoadError>(())
+        })
+        .expect("updated");
+        let hits: Vec<(Option<String>, u64)> = current()
+            .hits()
+            .into_iter()
+            .map(|h| (h.id, h.hits))
+            .collect();
+        assert!(hits.contains(&(Some("a".to_string()), 1)));
+        assert!(hits.contains(&(Some("b".to_string()), 0)));
+    }
+}
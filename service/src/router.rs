@@ -2,19 +2,6 @@ use futures::{SinkExt, StreamExt};
 use hyper::Body;
 use std::collections::HashMap;
 
-#[derive(thiserror::Error, Debug)]
-pub enum BodyError {
-    #[error("HyperBodyReadError: {}", _0)]
-    HyperBodyRead(#[from] hyper::Error),
-    #[error("SerdeDeserialize: {}", _0)]
-    SerdeDeserialize(#[from] serde_json::Error),
-}
-
-async fn from_body<T: serde::de::DeserializeOwned>(b: Body) -> Result<T, BodyError> {
-    let b = hyper::body::to_bytes(b).await?;
-    Ok(serde_json::from_slice(b.as_ref())?)
-}
-
 async fn send_file(p: &str) -> Result<hyper::Response<hyper::Body>, std::io::Error> {
     use tokio::io::AsyncReadExt;
     let mut f = tokio::fs::File::open(p).await?;
@@ -78,7 +65,7 @@ pub async fn handler(
             println!("{:#?}", body);
 
             let response_body = serde_json::to_vec(&body)?;
-            let mut response = hyper::Response::new(Body::from(response_body).into());
+            let mut response = hyper::Response::new(Body::from(response_body));
             *response.status_mut() = hyper::StatusCode::OK;
             response.headers_mut().append(
                 hyper::header::CONTENT_TYPE,
@@ -98,6 +85,7 @@ pub async fn handler(
             );
             Ok(response)
         }
+        (_, path) if crate::admin::is_admin(path) => crate::admin::handler(req).await,
         (&hyper::Method::GET, "/conver/settings/") => {
             let mut response = hyper::Response::new(hyper::Body::empty());
             *response.body_mut() = hyper::Body::from(conver_settings());
@@ -109,24 +97,159 @@ pub async fn handler(
             Ok(response)
         }
         _ => {
-            let apis = crate::utils::apis().expect("no api file found");
+            let apis = crate::registry::current();
             let (parts, body) = req.into_parts();
-            let req_body: serde_json::Value = from_body(body).await.unwrap();
+            let body_bytes = hyper::body::to_bytes(body).await?;
+            let req_body = crate::body::parse(
+                parts
+                    .headers
+                    .get(hyper::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok()),
+                &body_bytes,
+            );
             tracing::info!(body = serde_json::to_string(&req_body).unwrap());
-            match apis.response(parts.method.as_str(), parts.uri.path()) {
-                Some(r) => Ok(response(
-                    serde_json::to_string(&r).unwrap(),
-                    hyper::StatusCode::OK,
-                )),
-                None => {
-                    return Ok(response(
-                        "NOT-FOUND".to_string(),
-                        hyper::StatusCode::NOT_FOUND,
+            let request = crate::utils::MockRequest::new(&parts, req_body);
+            let started = std::time::Instant::now();
+            let mut entry = crate::journal::Entry::new(&request);
+            let result = match apis.response(&request).await {
+                Ok(Some(r)) => {
+                    tracing::info!(params = serde_json::to_string(&r.params).unwrap());
+                    entry.matched = r.id.clone();
+                    match mock_response(r, &mut entry).await {
+                        Err(http_service::errors::RouteError::ResponseBodyError(e)) => {
+                            tracing::error!(target = "ResponseBodyError", "Error: {}", e);
+                            failed(&e, &mut entry)
+                        }
+                        result => result,
+                    }
+                }
+                Ok(None) if crate::recorder::is_record() => {
+                    match crate::recorder::forward(&parts, body_bytes, &request).await {
+                        Ok((r, bytes)) => {
+                            entry.response = Some(crate::journal::LoggedResponse::new(&r, &bytes));
+                            Ok(r)
+                        }
+                        Err(e) => {
+                            tracing::error!(target = "RecordError", "Error: {}", e);
+                            Ok(response(
+                                serde_json::to_string(&serde_json::json!({
+                                    "success": false,
+                                    "message": e.to_string()
+                                }))?,
+                                hyper::StatusCode::BAD_GATEWAY,
+                            ))
+                        }
+                    }
+                }
+                Ok(None) => {
+                    let near_misses = apis.near_misses(&request, crate::nearmiss::LIMIT);
+                    tracing::warn!(
+                        target = "NearMiss",
+                        method = request.method.as_str(),
+                        path = request.path.as_str(),
+                        near_misses = serde_json::to_string(&near_misses)?
+                    );
+                    let not_found = serde_json::to_string(&serde_json::json!({
+                        "success": false,
+                        "message": "NOT-FOUND",
+                        "near_misses": near_misses,
+                    }))?;
+                    let r = response(not_found.clone(), hyper::StatusCode::NOT_FOUND);
+                    entry.response = Some(crate::journal::LoggedResponse::new(
+                        &r,
+                        not_found.as_bytes(),
                     ));
+                    Ok(r)
                 }
-            }
+                Err(e) => {
+                    tracing::error!(target = "TemplateError", "Error: {}", e);
+                    failed(&e, &mut entry)
+                }
+            };
+            entry.duration_ms = started.elapsed().as_millis() as u64;
+            crate::journal::record(entry);
+            result
+        }
+    }
+}
+
+/// Answers a matched request whose response could not be built with a `500`,
+/// recording it in the journal entry.
+fn failed(
+    error: &dyn std::fmt::Display,
+    entry: &mut crate::journal::Entry,
+) -> Result<hyper::Response<hyper::Body>, http_service::errors::RouteError> {
+    let error = serde_json::to_string(&serde_json::json!({
+        "success": false,
+        "message": error.to_string()
+    }))?;
+    let r = response(error.clone(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+    entry.response = Some(crate::journal::LoggedResponse::new(&r, error.as_bytes()));
+    Ok(r)
+}
+
+async fn mock_response(
+    matched: crate::utils::MatchedAPI,
+    entry: &mut crate::journal::Entry,
+) -> Result<hyper::Response<hyper::Body>, http_service::errors::RouteError> {
+    if let Some(delay) = matched.delay {
+        tokio::time::sleep(delay).await;
+    }
+    let mut response = hyper::Response::new(hyper::Body::empty());
+    *response.status_mut() = hyper::StatusCode::from_u16(matched.status)?;
+    let mut body_bytes = hyper::body::Bytes::new();
+    if let Some(body) = matched.body {
+        response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            hyper::http::HeaderValue::from_static(body.content_type()),
+        );
+        body_bytes = body.bytes().await?;
+    }
+    if let Some(stream) = &matched.stream {
+        if let Some(content_type) = stream.content_type() {
+            response.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                hyper::http::HeaderValue::from_static(content_type),
+            );
+            response.headers_mut().insert(
+                hyper::header::CACHE_CONTROL,
+                hyper::http::HeaderValue::from_static("no-cache"),
+            );
         }
     }
+    for (name, value) in matched.headers {
+        response.headers_mut().insert(
+            hyper::header::HeaderName::from_bytes(name.as_bytes())?,
+            hyper::http::HeaderValue::from_str(value.as_str())?,
+        );
+    }
+    for (name, value) in matched.cookies {
+        response.headers_mut().append(
+            hyper::header::SET_COOKIE,
+            hyper::http::HeaderValue::from_str(format!("{name}={value}").as_str())?,
+        );
+    }
+    let logged = match &matched.stream {
+        Some(stream) => stream.preview(&body_bytes),
+        None => body_bytes.to_vec(),
+    };
+    entry.response = Some(crate::journal::LoggedResponse::new(&response, &logged));
+    entry.fault = matched.fault.clone();
+    let response = match (matched.fault, matched.stream) {
+        (Some(fault), _) => crate::fault::apply(&fault, response, body_bytes.to_vec())?,
+        (None, Some(stream)) => {
+            *response.body_mut() = stream.body(body_bytes);
+            response
+        }
+        (None, None) => {
+            *response.body_mut() = hyper::Body::from(body_bytes);
+            response
+        }
+    };
+    // aborting faults return early above, the webhooks fire only once the
+    // response is actually handed to the client
+    crate::webhook::schedule(matched.id, matched.webhooks);
+    Ok(response)
 }
 
 pub fn conver_settings() -> String {
@@ -0,0 +1,101 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{LazyLock, Mutex, MutexGuard};
+
+/// State every scenario starts in and returns to on a reset
+pub const STARTED: &str = "Started";
+
+/// Named state machine shared by the mocks of a multi-step flow, a mock only
+/// matches while the scenario is in its `required_state` and moves the
+/// scenario to its `new_state` once it responds
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Scenario {
+    pub name: String,
+    /// Matches in any state if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_state: Option<String>,
+    /// State is left as is if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_state: Option<String>,
+}
+
+/// Current state of a scenario along with the states its mocks refer to
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ScenarioState {
+    pub name: String,
+    pub state: String,
+    pub possible_states: Vec<String>,
+}
+
+// States of the scenarios which have moved away from `STARTED`
+static STATES: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Scenario states locked while a mock checks and moves its scenario, so that
+/// concurrent requests can not both match a mock for the same state
+pub struct States(MutexGuard<'static, HashMap<String, String>>);
+
+impl States {
+    pub fn state(&self, name: &str) -> &str {
+        self.0.get(name).map(|s| s.as_str()).unwrap_or(STARTED)
+    }
+
+    pub fn matches(&self, scenario: &Scenario) -> bool {
+        match &scenario.required_state {
+            Some(required) => self.state(scenario.name.as_str()) == required,
+            None => true,
+        }
+    }
+
+    pub fn transition(&mut self, scenario: &Scenario) {
+        if let Some(state) = &scenario.new_state {
+            tracing::info!(
+                target = "ScenarioTransition",
+                scenario = scenario.name.as_str(),
+                from = self.state(scenario.name.as_str()),
+                to = state.as_str()
+            );
+            self.0.insert(scenario.name.to_string(), state.to_string());
+        }
+    }
+}
+
+pub fn lock() -> States {
+    States(STATES.lock().expect("scenario lock poisoned"))
+}
+
+pub fn set(name: &str, state: &str) {
+    lock().0.insert(name.to_string(), state.to_string());
+}
+
+/// Moves the scenario back to `STARTED`, or all of them if no name is given
+pub fn reset(name: Option<&str>) {
+    let mut states = lock();
+    match name {
+        Some(name) => {
+            states.0.remove(name);
+        }
+        None => states.0.clear(),
+    }
+}
+
+/// Scenarios of the mocks along with their current states, sorted by name
+pub fn list(apis: &[crate::utils::API]) -> Vec<ScenarioState> {
+    let mut scenarios: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for scenario in apis.iter().filter_map(|api| api.scenario.as_ref()) {
+        let states = scenarios.entry(scenario.name.as_str()).or_default();
+        states.insert(STARTED);
+        states.extend(scenario.required_state.as_deref());
+        states.extend(scenario.new_state.as_deref());
+    }
+    let current = lock();
+    let mut list: Vec<ScenarioState> = scenarios
+        .into_iter()
+        .map(|(name, states)| ScenarioState {
+            name: name.to_string(),
+            state: current.state(name).to_string(),
+            possible_states: states.into_iter().map(|s| s.to_string()).collect(),
+        })
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    list
+}
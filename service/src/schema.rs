@@ -0,0 +1,790 @@
+use std::collections::{HashMap, HashSet};
+
+// Chains of $ref are followed up to this depth
+const MAX_DEPTH: usize = 16;
+
+/// Resolves a local `$ref` like `#/components/schemas/User` against the root
+/// document, references to other documents are not supported
+pub fn resolve<'a>(
+    root: &'a serde_json::Value,
+    schema: &'a serde_json::Value,
+) -> &'a serde_json::Value {
+    let mut schema = schema;
+    for _ in 0..MAX_DEPTH {
+        let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) else {
+            return schema;
+        };
+        let Some(pointer) = reference.strip_prefix('#') else {
+            return schema;
+        };
+        match root.pointer(pointer) {
+            Some(resolved) => schema = resolved,
+            None => return schema,
+        }
+    }
+    schema
+}
+
+/// Builds an example value from the schema, `example`, `default` and `enum`
+/// are used when given, otherwise a placeholder of the right type
+pub fn example(root: &serde_json::Value, schema: &serde_json::Value) -> serde_json::Value {
+    example_at(root, schema, &mut vec![])
+}
+
+fn example_at(
+    root: &serde_json::Value,
+    schema: &serde_json::Value,
+    refs: &mut Vec<String>,
+) -> serde_json::Value {
+    // a schema referring back to itself is cut at the first repetition
+    if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+        if refs.len() > MAX_DEPTH || refs.iter().any(|r| r == reference) {
+            return serde_json::Value::Null;
+        }
+        refs.push(reference.to_string());
+        let example = example_at(root, resolve(root, schema), refs);
+        refs.pop();
+        return example;
+    }
+    if let Some(example) = schema.get("example").or_else(|| schema.get("default")) {
+        return example.clone();
+    }
+    if let Some(first) = schema
+        .get("examples")
+        .and_then(|e| e.as_array())
+        .and_then(|e| e.first())
+    {
+        return first.clone();
+    }
+    if let Some(first) = schema
+        .get("enum")
+        .and_then(|e| e.as_array())
+        .and_then(|e| e.first())
+    {
+        return first.clone();
+    }
+    if let Some(all_of) = schema.get("allOf").and_then(|a| a.as_array()) {
+        let mut merged = serde_json::Map::new();
+        for part in all_of {
+            if let serde_json::Value::Object(map) = example_at(root, part, refs) {
+                merged.extend(map);
+            }
+        }
+        return serde_json::Value::Object(merged);
+    }
+    if let Some(first) = schema
+        .get("oneOf")
+        .or_else(|| schema.get("anyOf"))
+        .and_then(|a| a.as_array())
+        .and_then(|a| a.first())
+    {
+        return example_at(root, first, refs);
+    }
+
+    match schema_type(schema).as_deref() {
+        Some("object") => {
+            let mut object = serde_json::Map::new();
+            if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+                for (name, property) in properties {
+                    object.insert(name.to_string(), example_at(root, property, refs));
+                }
+            }
+            serde_json::Value::Object(object)
+        }
+        Some("array") => match schema.get("items") {
+            Some(items) => serde_json::Value::Array(vec![example_at(root, items, refs)]),
+            None => serde_json::Value::Array(vec![]),
+        },
+        Some("integer") => {
+            serde_json::json!(schema.get("minimum").and_then(|m| m.as_i64()).unwrap_or(0))
+        }
+        Some("number") => serde_json::json!(schema
+            .get("minimum")
+            .and_then(|m| m.as_f64())
+            .unwrap_or(0.0)),
+        Some("boolean") => serde_json::Value::Bool(true),
+        Some("string") => serde_json::Value::String(
+            match schema.get("format").and_then(|f| f.as_str()) {
+                Some("date-time") => "2024-01-01T00:00:00Z",
+                Some("date") => "2024-01-01",
+                Some("email") => "user@example.com",
+                Some("uuid") => "00000000-0000-0000-0000-000000000000",
+                Some("uri") | Some("url") => "https://example.com",
+                _ => "string",
+            }
+            .to_string(),
+        ),
+        _ => serde_json::Value::Null,
+    }
+}
+
+/// Type of the schema, `type` can be a list in JSON Schema in which case the
+/// first non null type is taken, objects and arrays are inferred from their
+/// keywords if the type is not given
+pub fn schema_type(schema: &serde_json::Value) -> Option<String> {
+    match schema.get("type") {
+        Some(serde_json::Value::String(t)) => Some(t.to_string()),
+        Some(serde_json::Value::Array(types)) => types
+            .iter()
+            .filter_map(|t| t.as_str())
+            .find(|t| *t != "null")
+            .map(|t| t.to_string()),
+        _ if schema.get("properties").is_some() => Some("object".to_string()),
+        _ if schema.get("items").is_some() => Some("array".to_string()),
+        _ => None,
+    }
+}
+
+/// A value of the request not conforming to its schema
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Violation {
+    /// `body`, `query` or `headers`
+    pub location: String,
+    /// Path of the value, like `$.transactions[0].amount`
+    pub path: String,
+    pub message: String,
+}
+
+/// Validates the value against the schema and returns every violation, the
+/// commonly used keywords of JSON Schema and of the OpenAPI schemas are
+/// supported, unknown keywords and formats are ignored
+pub fn validate(
+    root: &serde_json::Value,
+    patterns: &Patterns,
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+    location: &str,
+) -> Vec<Violation> {
+    let mut errors = vec![];
+    check(
+        root,
+        patterns,
+        schema,
+        value,
+        "$".to_string(),
+        0,
+        &mut errors,
+    );
+    errors
+        .into_iter()
+        .map(|(path, message)| Violation {
+            location: location.to_string(),
+            path,
+            message,
+        })
+        .collect()
+}
+
+fn is_type(value: &serde_json::Value, name: &str) -> bool {
+    match name {
+        "integer" => {
+            value.is_i64()
+                || value.is_u64()
+                || value.as_f64().map(|n| n.fract() == 0.0).unwrap_or(false)
+        }
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+fn is_format(value: &str, format: &str) -> bool {
+    match format {
+        "email" => value
+            .split_once('@')
+            .map(|(user, domain)| !user.is_empty() && domain.contains('.'))
+            .unwrap_or(false),
+        "uuid" => uuid::Uuid::parse_str(value).is_ok(),
+        "date-time" => chrono::DateTime::parse_from_rfc3339(value).is_ok(),
+        "date" => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+        "ipv4" => value.parse::<std::net::Ipv4Addr>().is_ok(),
+        "ipv6" => value.parse::<std::net::Ipv6Addr>().is_ok(),
+        "uri" | "url" => value.contains("://"),
+        _ => true,
+    }
+}
+
+/// `pattern` regexes of a schema compiled up front, along the `$ref`s into the
+/// root document, so a bad pattern is refused with the mock
+#[derive(Default)]
+pub struct Patterns(HashMap<String, regex::Regex>);
+
+impl Patterns {
+    pub fn compile(
+        root: &serde_json::Value,
+        schema: &serde_json::Value,
+    ) -> Result<Self, regex::Error> {
+        let mut patterns = Patterns::default();
+        patterns.collect(root, schema, &mut HashSet::new())?;
+        Ok(patterns)
+    }
+
+    fn collect<'a>(
+        &mut self,
+        root: &'a serde_json::Value,
+        schema: &'a serde_json::Value,
+        refs: &mut HashSet<&'a str>,
+    ) -> Result<(), regex::Error> {
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            if !refs.insert(reference) {
+                return Ok(());
+            }
+        }
+        let schema = resolve(root, schema);
+        if let Some(pattern) = schema.get("pattern").and_then(|p| p.as_str()) {
+            if !self.0.contains_key(pattern) {
+                self.0
+                    .insert(pattern.to_string(), regex::Regex::new(pattern)?);
+            }
+        }
+        let properties = schema
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .into_iter()
+            .flat_map(|p| p.values());
+        let parts = ["allOf", "anyOf", "oneOf"]
+            .iter()
+            .filter_map(|keyword| schema.get(*keyword).and_then(|a| a.as_array()))
+            .flatten();
+        let single = ["items", "additionalProperties", "not"]
+            .iter()
+            .filter_map(|keyword| schema.get(*keyword).filter(|s| s.is_object()));
+        for nested in properties.chain(parts).chain(single) {
+            self.collect(root, nested, refs)?;
+        }
+        Ok(())
+    }
+}
+
+fn check(
+    root: &serde_json::Value,
+    patterns: &Patterns,
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+    path: String,
+    depth: usize,
+    errors: &mut Vec<(String, String)>,
+) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let schema = resolve(root, schema);
+    if let Some(allowed) = schema.as_bool() {
+        if !allowed {
+            errors.push((path, "no value is allowed here".to_string()));
+        }
+        return;
+    }
+    // `nullable` of the OpenAPI 3.0 schemas
+    if value.is_null() && schema.get("nullable").and_then(|n| n.as_bool()) == Some(true) {
+        return;
+    }
+    let types: Vec<&str> = match schema.get("type") {
+        Some(serde_json::Value::String(t)) => vec![t.as_str()],
+        Some(serde_json::Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).collect(),
+        _ => vec![],
+    };
+    if !types.is_empty() && !types.iter().any(|t| is_type(value, t)) {
+        errors.push((
+            path,
+            format!(
+                "expected {}, found {}",
+                types.join(" or "),
+                type_name(value)
+            ),
+        ));
+        return;
+    }
+    if let Some(values) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !values.contains(value) {
+            errors.push((
+                path.to_string(),
+                format!(
+                    "must be one of {}",
+                    serde_json::Value::Array(values.clone())
+                ),
+            ));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            errors.push((path.to_string(), format!("must be {constant}")));
+        }
+    }
+
+    let matches = |schema: &serde_json::Value| {
+        let mut errors = vec![];
+        check(
+            root,
+            patterns,
+            schema,
+            value,
+            path.to_string(),
+            depth + 1,
+            &mut errors,
+        );
+        errors.is_empty()
+    };
+    if let Some(all_of) = schema.get("allOf").and_then(|a| a.as_array()) {
+        for part in all_of {
+            check(
+                root,
+                patterns,
+                part,
+                value,
+                path.to_string(),
+                depth + 1,
+                errors,
+            );
+        }
+    }
+    if let Some(any_of) = schema.get("anyOf").and_then(|a| a.as_array()) {
+        if !any_of.iter().any(matches) {
+            errors.push((path.to_string(), "must match a schema in anyOf".to_string()));
+        }
+    }
+    if let Some(one_of) = schema.get("oneOf").and_then(|a| a.as_array()) {
+        let matched = one_of.iter().filter(|s| matches(s)).count();
+        if matched != 1 {
+            errors.push((
+                path.to_string(),
+                format!("must match exactly one schema in oneOf, matched {matched}"),
+            ));
+        }
+    }
+    if let Some(not) = schema.get("not") {
+        if matches(not) {
+            errors.push((
+                path.to_string(),
+                "must not match the schema in not".to_string(),
+            ));
+        }
+    }
+
+    let number = |keyword: &str| schema.get(keyword).and_then(|n| n.as_f64());
+    match value {
+        serde_json::Value::String(s) => {
+            let length = s.chars().count() as f64;
+            if let Some(min) = number("minLength").filter(|min| length < *min) {
+                errors.push((
+                    path.to_string(),
+                    format!("must be at least {min} characters"),
+                ));
+            }
+            if let Some(max) = number("maxLength").filter(|max| length > *max) {
+                errors.push((
+                    path.to_string(),
+                    format!("must be at most {max} characters"),
+                ));
+            }
+            if let Some(pattern) = schema.get("pattern").and_then(|p| p.as_str()) {
+                // every pattern is compiled with the schema, see `Patterns`
+                if let Some(re) = patterns.0.get(pattern) {
+                    if !re.is_match(s) {
+                        errors.push((path.to_string(), format!("must match {pattern}")));
+                    }
+                }
+            }
+            if let Some(format) = schema.get("format").and_then(|f| f.as_str()) {
+                if !is_format(s, format) {
+                    errors.push((path.to_string(), format!("must be a valid {format}")));
+                }
+            }
+        }
+        serde_json::Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            // OpenAPI 3.0 has the exclusive bounds as flags on the minimum and maximum
+            let exclusive = |keyword: &str, bound: Option<f64>| match schema.get(keyword) {
+                Some(serde_json::Value::Bool(true)) => bound,
+                Some(serde_json::Value::Number(bound)) => bound.as_f64(),
+                _ => None,
+            };
+            if let Some(min) = number("minimum").filter(|min| n < *min) {
+                errors.push((path.to_string(), format!("must be at least {min}")));
+            }
+            if let Some(max) = number("maximum").filter(|max| n > *max) {
+                errors.push((path.to_string(), format!("must be at most {max}")));
+            }
+            if let Some(min) =
+                exclusive("exclusiveMinimum", number("minimum")).filter(|min| n <= *min)
+            {
+                errors.push((path.to_string(), format!("must be greater than {min}")));
+            }
+            if let Some(max) =
+                exclusive("exclusiveMaximum", number("maximum")).filter(|max| n >= *max)
+            {
+                errors.push((path.to_string(), format!("must be less than {max}")));
+            }
+            if let Some(multiple) = number("multipleOf").filter(|m| *m > 0.0) {
+                if ((n / multiple) - (n / multiple).round()).abs() > 1e-9 {
+                    errors.push((
+                        path.to_string(),
+                        format!("must be a multiple of {multiple}"),
+                    ));
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            let length = items.len() as f64;
+            if let Some(min) = number("minItems").filter(|min| length < *min) {
+                errors.push((path.to_string(), format!("must have at least {min} items")));
+            }
+            if let Some(max) = number("maxItems").filter(|max| length > *max) {
+                errors.push((path.to_string(), format!("must have at most {max} items")));
+            }
+            if schema.get("uniqueItems").and_then(|u| u.as_bool()) == Some(true)
+                && items
+                    .iter()
+                    .enumerate()
+                    .any(|(i, item)| items[..i].contains(item))
+            {
+                errors.push((path.to_string(), "must have unique items".to_string()));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(
+                        root,
+                        patterns,
+                        item_schema,
+                        item,
+                        format!("{path}[{i}]"),
+                        depth + 1,
+                        errors,
+                    );
+                }
+            }
+        }
+        serde_json::Value::Object(map) => {
+            if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+                for name in required.iter().filter_map(|r| r.as_str()) {
+                    if !map.contains_key(name) {
+                        errors.push((format!("{path}.{name}"), "is required".to_string()));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for (name, property) in map {
+                let property_path = format!("{path}.{name}");
+                match properties.and_then(|p| p.get(name)) {
+                    Some(property_schema) => check(
+                        root,
+                        patterns,
+                        property_schema,
+                        property,
+                        property_path,
+                        depth + 1,
+                        errors,
+                    ),
+                    None => match schema.get("additionalProperties") {
+                        Some(serde_json::Value::Bool(false)) => {
+                            errors.push((property_path, "is not allowed".to_string()))
+                        }
+                        Some(additional) => check(
+                            root,
+                            patterns,
+                            additional,
+                            property,
+                            property_path,
+                            depth + 1,
+                            errors,
+                        ),
+                        None => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Converts the string values of the query or the headers to the types of
+/// the matching properties of the object schema, so that `?page=2` passes as
+/// an integer and `a,b` as an array
+pub fn coerce(
+    root: &serde_json::Value,
+    schema: &serde_json::Value,
+    values: &std::collections::HashMap<String, String>,
+) -> serde_json::Value {
+    let properties = resolve(root, schema)
+        .get("properties")
+        .and_then(|p| p.as_object());
+    let object = values
+        .iter()
+        .map(|(name, value)| {
+            let property = properties
+                .and_then(|p| p.get(name))
+                .map(|p| resolve(root, p));
+            (name.to_string(), coerce_value(root, property, value))
+        })
+        .collect();
+    serde_json::Value::Object(object)
+}
+
+fn coerce_value(
+    root: &serde_json::Value,
+    schema: Option<&serde_json::Value>,
+    value: &str,
+) -> serde_json::Value {
+    let string = || serde_json::Value::String(value.to_string());
+    let Some(schema) = schema else {
+        return string();
+    };
+    match schema_type(schema).as_deref() {
+        Some("integer") | Some("number") => value
+            .parse::<i64>()
+            .map(serde_json::Value::from)
+            .or_else(|_| value.parse::<f64>().map(serde_json::Value::from))
+            .unwrap_or_else(|_| string()),
+        Some("boolean") => match value {
+            "true" => serde_json::Value::Bool(true),
+            "false" => serde_json::Value::Bool(false),
+            _ => string(),
+        },
+        Some("array") => serde_json::Value::Array(
+            value
+                .split(',')
+                .map(|item| {
+                    let items = schema.get("items").map(|i| resolve(root, i));
+                    coerce_value(root, items, item)
+                })
+                .collect(),
+        ),
+        _ => string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn violations(schema: serde_json::Value, value: serde_json::Value) -> Vec<(String, String)> {
+        let patterns = Patterns::compile(&schema, &schema).expect("valid patterns");
+        validate(&schema, &patterns, &schema, &value, "body")
+            .into_iter()
+            .map(|v| (v.path, v.message))
+            .collect()
+    }
+
+    fn paths(schema: serde_json::Value, value: serde_json::Value) -> Vec<String> {
+        violations(schema, value).into_iter().map(|v| v.0).collect()
+    }
+
+    #[test]
+    fn types() {
+        assert!(paths(json!({"type": "integer"}), json!(1)).is_empty());
+        assert!(paths(json!({"type": "integer"}), json!(1.0)).is_empty());
+        assert_eq!(
+            violations(json!({"type": "integer"}), json!(1.5)),
+            vec![(
+                "$".to_string(),
+                "expected integer, found number".to_string()
+            )]
+        );
+        assert!(paths(json!({"type": ["string", "null"]}), json!(null)).is_empty());
+        assert!(!paths(json!({"type": "string"}), json!(null)).is_empty());
+        assert!(paths(json!({"type": "string", "nullable": true}), json!(null)).is_empty());
+        assert!(paths(json!(true), json!(1)).is_empty());
+        assert_eq!(paths(json!(false), json!(1)), vec!["$"]);
+    }
+
+    #[test]
+    fn enum_and_const() {
+        assert!(paths(json!({"enum": ["a", 1]}), json!(1)).is_empty());
+        assert_eq!(paths(json!({"enum": ["a", 1]}), json!("b")), vec!["$"]);
+        assert!(paths(json!({"const": {"a": 1}}), json!({"a": 1})).is_empty());
+        assert_eq!(paths(json!({"const": "a"}), json!("b")), vec!["$"]);
+    }
+
+    #[test]
+    fn string_keywords() {
+        let schema =
+            json!({"type": "string", "minLength": 2, "maxLength": 3, "pattern": "^[a-z]+$"});
+        assert!(paths(schema.clone(), json!("abc")).is_empty());
+        // lengths are counted in characters
+        assert!(paths(json!({"maxLength": 2}), json!("éé")).is_empty());
+        assert_eq!(paths(schema.clone(), json!("a")).len(), 1);
+        assert_eq!(paths(schema.clone(), json!("abcd")).len(), 1);
+        assert_eq!(paths(schema, json!("A1")).len(), 1);
+        assert!(Patterns::compile(&json!({}), &json!({"pattern": "("})).is_err());
+    }
+
+    #[test]
+    fn formats() {
+        for (format, valid, invalid) in [
+            ("email", "user@example.com", "user@example"),
+            ("uuid", "00000000-0000-0000-0000-000000000000", "0000"),
+            ("date-time", "2024-01-01T00:00:00Z", "2024-01-01"),
+            ("date", "2024-01-01", "2024-13-01"),
+            ("ipv4", "127.0.0.1", "::1"),
+            ("ipv6", "::1", "127.0.0.1"),
+            ("uri", "https://example.com", "example.com"),
+        ] {
+            let schema = json!({"type": "string", "format": format});
+            assert!(paths(schema.clone(), json!(valid)).is_empty(), "{format}");
+            assert_eq!(paths(schema, json!(invalid)).len(), 1, "{format}");
+        }
+        assert!(paths(json!({"format": "unknown"}), json!("anything")).is_empty());
+    }
+
+    #[test]
+    fn number_keywords() {
+        let schema = json!({"minimum": 1, "maximum": 10, "multipleOf": 0.5});
+        assert!(paths(schema.clone(), json!(1)).is_empty());
+        assert!(paths(schema.clone(), json!(9.5)).is_empty());
+        assert_eq!(paths(schema.clone(), json!(0)).len(), 1);
+        assert_eq!(paths(schema.clone(), json!(11)).len(), 1);
+        assert_eq!(paths(schema, json!(2.25)).len(), 1);
+        // JSON Schema bounds and the OpenAPI 3.0 flags
+        assert_eq!(paths(json!({"exclusiveMinimum": 1}), json!(1)).len(), 1);
+        assert!(paths(json!({"exclusiveMaximum": 10}), json!(9)).is_empty());
+        assert_eq!(
+            paths(json!({"minimum": 1, "exclusiveMinimum": true}), json!(1)).len(),
+            1
+        );
+        assert!(paths(json!({"minimum": 1, "exclusiveMinimum": false}), json!(1)).is_empty());
+    }
+
+    #[test]
+    fn array_keywords() {
+        let schema = json!({
+            "type": "array",
+            "minItems": 1,
+            "maxItems": 3,
+            "uniqueItems": true,
+            "items": {"type": "integer"},
+        });
+        assert!(paths(schema.clone(), json!([1, 2])).is_empty());
+        assert_eq!(paths(schema.clone(), json!([])), vec!["$"]);
+        assert_eq!(paths(schema.clone(), json!([1, 2, 3, 4])), vec!["$"]);
+        assert_eq!(paths(schema.clone(), json!([1, 1])), vec!["$"]);
+        assert_eq!(paths(schema, json!([1, "a", 2.5])), vec!["$[1]", "$[2]"]);
+    }
+
+    #[test]
+    fn object_keywords() {
+        let schema = json!({
+            "type": "object",
+            "required": ["id", "name"],
+            "properties": {"id": {"type": "integer"}, "name": {"type": "string"}},
+            "additionalProperties": false,
+        });
+        assert!(paths(schema.clone(), json!({"id": 1, "name": "a"})).is_empty());
+        assert_eq!(
+            paths(schema.clone(), json!({"id": "1", "extra": 1})),
+            vec!["$.name", "$.extra", "$.id"]
+        );
+        let schema = json!({"additionalProperties": {"type": "integer"}});
+        assert!(paths(schema.clone(), json!({"a": 1})).is_empty());
+        assert_eq!(paths(schema, json!({"a": "1"})), vec!["$.a"]);
+    }
+
+    #[test]
+    fn combinators() {
+        let all_of = json!({"allOf": [{"required": ["a"]}, {"required": ["b"]}]});
+        assert!(paths(all_of.clone(), json!({"a": 1, "b": 1})).is_empty());
+        assert_eq!(paths(all_of, json!({})), vec!["$.a", "$.b"]);
+        let any_of = json!({"anyOf": [{"type": "string"}, {"type": "integer"}]});
+        assert!(paths(any_of.clone(), json!(1)).is_empty());
+        assert_eq!(paths(any_of, json!(true)), vec!["$"]);
+        let one_of = json!({"oneOf": [{"type": "number"}, {"type": "integer"}]});
+        assert!(paths(one_of.clone(), json!(1.5)).is_empty());
+        assert_eq!(
+            violations(one_of, json!(1))[0].1,
+            "must match exactly one schema in oneOf, matched 2"
+        );
+        assert!(paths(json!({"not": {"type": "string"}}), json!(1)).is_empty());
+        assert_eq!(
+            paths(json!({"not": {"type": "string"}}), json!("a")),
+            vec!["$"]
+        );
+    }
+
+    #[test]
+    fn references_are_resolved() {
+        let root = json!({"components": {"schemas": {
+            "Id": {"type": "integer"},
+            "User": {"properties": {"id": {"$ref": "#/components/schemas/Id"}}},
+            "Loop": {"$ref": "#/components/schemas/Loop"},
+        }}});
+        let user = json!({"$ref": "#/components/schemas/User"});
+        let patterns = Patterns::default();
+        assert!(validate(&root, &patterns, &user, &json!({"id": 1}), "body").is_empty());
+        let found = validate(&root, &patterns, &user, &json!({"id": "1"}), "body");
+        assert_eq!(
+            (found[0].location.as_str(), found[0].path.as_str()),
+            ("body", "$.id")
+        );
+        // a reference to itself is given up on instead of looping
+        let looping = json!({"$ref": "#/components/schemas/Loop"});
+        assert!(validate(&root, &patterns, &looping, &json!(1), "body").is_empty());
+    }
+
+    #[test]
+    fn patterns_are_compiled_along_the_references() {
+        let root = json!({"components": {"schemas": {
+            "Code": {"type": "string", "pattern": "^[A-Z]{3}$"},
+            "Node": {"properties": {
+                "code": {"$ref": "#/components/schemas/Code"},
+                "children": {"items": {"$ref": "#/components/schemas/Node"}},
+                "tags": {"anyOf": [{"pattern": "^#"}, {"not": {"pattern": "x"}}]},
+            }},
+        }}});
+        let node = json!({"$ref": "#/components/schemas/Node"});
+        let patterns = Patterns::compile(&root, &node).expect("valid patterns");
+        assert_eq!(patterns.0.len(), 3);
+        let found = validate(
+            &root,
+            &patterns,
+            &node,
+            &json!({"children": [{"code": "ab"}]}),
+            "body",
+        );
+        assert_eq!(found[0].path, "$.children[0].code");
+        let broken = json!({"properties": {"a": {"items": {"pattern": "(["}}}});
+        assert!(Patterns::compile(&broken, &broken).is_err());
+    }
+
+    #[test]
+    fn query_values_are_coerced_to_the_schema_types() {
+        let schema = json!({"properties": {
+            "page": {"type": "integer"},
+            "ratio": {"type": "number"},
+            "active": {"type": "boolean"},
+            "ids": {"type": "array", "items": {"type": "integer"}},
+        }});
+        let values = [
+            ("page", "2"),
+            ("ratio", "0.5"),
+            ("active", "true"),
+            ("ids", "1,2"),
+            ("name", "a"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(
+            coerce(&json!({}), &schema, &values),
+            json!({"page": 2, "ratio": 0.5, "active": true, "ids": [1, 2], "name": "a"})
+        );
+        let values = [("page".to_string(), "two".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(coerce(&json!({}), &schema, &values), json!({"page": "two"}));
+    }
+}
@@ -0,0 +1,360 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Scripts are stopped after this long unless the mock sets its own `timeout`
+const DEFAULT_TIMEOUT_MS: u64 = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScriptError {
+    #[error("MissingScript: either `code` or `file` is required")]
+    Missing,
+    #[error("IOError: {path}: {source}")]
+    IO {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("ParseError: {0}")]
+    Parse(#[from] rhai::ParseError),
+    #[error("RuntimeError: {0}")]
+    Runtime(#[from] Box<rhai::EvalAltResult>),
+    #[error("Timeout: script ran longer than {0}ms")]
+    Timeout(u64),
+    #[error("InvalidResult: {0}")]
+    InvalidResult(String),
+    #[error("TaskError: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Rhai script which computes the response of a mock, either inline in `code`
+/// or read from `file` when the mocks are loaded. The script sees
+/// - `request`, the parsed request along with the path `params`
+/// - `state_get(key)`, `state_set(key, value)` and `state_remove(key)` on a
+///   key value state shared by all of the scripts and kept across requests
+/// - `scenario(name)` and `set_scenario(name, state)` on the scenario states
+///
+/// and returns a map with the optional `status`, `headers` and `body`, a map
+/// or an array body is sent as json and anything else as text.
+///
+/// ```text
+/// let balance = state_get("balance") ?? 100;
+/// if request.body.amount > balance {
+///     return #{ status: 422, body: #{ success: false, message: "INSUFFICIENT_FUNDS" } };
+/// }
+/// state_set("balance", balance - request.body.amount);
+/// #{ body: #{ success: true, balance: balance - request.body.amount } }
+/// ```
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Script {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Execution time limit in milliseconds, `100` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+/// Response computed by a script or a plugin
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct ScriptResponse {
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+}
+
+impl ScriptResponse {
+    pub fn body(&self) -> Option<crate::utils::ResponseBody> {
+        self.body.as_ref().map(|body| match body {
+            serde_json::Value::String(text) => crate::utils::ResponseBody::Text(text.to_string()),
+            body => crate::utils::ResponseBody::Json(body.clone()),
+        })
+    }
+
+    /// Refuses a status or headers the response can not be sent with
+    pub fn check(self) -> Result<Self, String> {
+        if let Some(status) = self.status {
+            hyper::StatusCode::from_u16(status).map_err(|e| format!("status {status}: {e}"))?;
+        }
+        for (name, value) in &self.headers {
+            hyper::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format!("header {name}: {e}"))?;
+            hyper::http::HeaderValue::from_str(value.as_str())
+                .map_err(|e| format!("header {name}: {e}"))?;
+        }
+        Ok(self)
+    }
+}
+
+// State shared by all of the scripts and the plugins, e.g. a balance kept
+// across transfers
+static STATE: LazyLock<Mutex<HashMap<String, serde_json::Value>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn state() -> HashMap<String, serde_json::Value> {
+    STATE.lock().expect("script state lock poisoned").clone()
+}
+
+pub fn state_get(key: &str) -> Option<serde_json::Value> {
+    STATE
+        .lock()
+        .expect("script state lock poisoned")
+        .get(key)
+        .cloned()
+}
+
+pub fn state_set(key: &str, value: serde_json::Value) {
+    STATE
+        .lock()
+        .expect("script state lock poisoned")
+        .insert(key.to_string(), value);
+}
+
+pub fn state_remove(key: &str) {
+    STATE
+        .lock()
+        .expect("script state lock poisoned")
+        .remove(key);
+}
+
+pub fn reset_state() {
+    STATE.lock().expect("script state lock poisoned").clear();
+}
+
+fn to_json(value: &rhai::Dynamic) -> serde_json::Value {
+    rhai::serde::from_dynamic(value).unwrap_or_default()
+}
+
+fn to_dynamic(value: &serde_json::Value) -> rhai::Dynamic {
+    rhai::serde::to_dynamic(value).unwrap_or_default()
+}
+
+thread_local! {
+    // deadline of the script running on the thread, checked by the engine as
+    // the script progresses
+    static DEADLINE: Cell<Option<std::time::Instant>> = const { Cell::new(None) };
+}
+
+/// Sandboxed engine shared by all of the scripts, scripts have no access to
+/// the file system or the network and are limited in the nesting and the size
+/// of the values they build, `print` and `debug` go to the logs
+static ENGINE: LazyLock<rhai::Engine> = LazyLock::new(|| {
+    let mut engine = rhai::Engine::new();
+    engine
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(1 << 20)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000);
+    engine
+        .on_progress(|_| {
+            DEADLINE
+                .get()
+                .filter(|deadline| std::time::Instant::now() > *deadline)
+                .map(|_| rhai::Dynamic::UNIT)
+        })
+        .on_print(|text| tracing::info!(target = "script", "{}", text))
+        .on_debug(|text, source, position| {
+            tracing::debug!(
+                target = "script",
+                source = source.unwrap_or_default(),
+                position = position.to_string(),
+                "{}",
+                text
+            )
+        });
+    engine
+        .register_fn("state_get", |key: &str| -> rhai::Dynamic {
+            state_get(key)
+                .map(|value| to_dynamic(&value))
+                .unwrap_or(rhai::Dynamic::UNIT)
+        })
+        .register_fn("state_set", |key: &str, value: rhai::Dynamic| {
+            state_set(key, to_json(&value))
+        })
+        .register_fn("state_remove", |key: &str| state_remove(key))
+        .register_fn("scenario", |name: &str| -> String {
+            crate::scenario::lock().state(name).to_string()
+        })
+        .register_fn("set_scenario", |name: &str, state: &str| {
+            crate::scenario::set(name, state);
+        });
+    engine
+});
+
+/// Script compiled when the mocks are loaded
+pub struct CompiledScript {
+    ast: rhai::AST,
+    timeout: u64,
+}
+
+impl CompiledScript {
+    pub fn new(script: &Script) -> Result<Self, ScriptError> {
+        let code = match (&script.code, &script.file) {
+            (Some(code), _) => code.to_string(),
+            (None, Some(path)) => {
+                std::fs::read_to_string(path).map_err(|source| ScriptError::IO {
+                    path: path.to_string(),
+                    source,
+                })?
+            }
+            (None, None) => return Err(ScriptError::Missing),
+        };
+        Ok(CompiledScript {
+            ast: ENGINE.compile(code)?,
+            timeout: script.timeout.unwrap_or(DEFAULT_TIMEOUT_MS),
+        })
+    }
+
+    /// Runs the script against the request, the script is terminated once it
+    /// runs past its time limit
+    pub fn run(
+        &self,
+        request: &crate::utils::MockRequest,
+        params: &HashMap<String, String>,
+    ) -> Result<ScriptResponse, ScriptError> {
+        let context = request.template_context(params);
+        let mut scope = rhai::Scope::new();
+        scope.push_dynamic("request", to_dynamic(&context["request"]));
+        DEADLINE.set(Some(
+            std::time::Instant::now() + std::time::Duration::from_millis(self.timeout),
+        ));
+        let result = ENGINE.eval_ast_with_scope::<rhai::Dynamic>(&mut scope, &self.ast);
+        DEADLINE.set(None);
+        let result = match result {
+            Ok(result) => result,
+            Err(e) if matches!(*e, rhai::EvalAltResult::ErrorTerminated(_, _)) => {
+                return Err(ScriptError::Timeout(self.timeout))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !result.is_map() {
+            return Err(ScriptError::InvalidResult(format!(
+                "expected a map with status, headers and body, got {}",
+                result.type_name()
+            )));
+        }
+        rhai::serde::from_dynamic::<ScriptResponse>(&result)
+            .map_err(|e| ScriptError::InvalidResult(e.to_string()))?
+            .check()
+            .map_err(ScriptError::InvalidResult)
+    }
+}
+
+/// Runs the script on the blocking pool, so that a script running up to its
+/// time limit does not hold up the other requests
+pub async fn run(
+    script: Arc<CompiledScript>,
+    request: crate::utils::MockRequest,
+    params: HashMap<String, String>,
+) -> Result<ScriptResponse, ScriptError> {
+    tokio::task::spawn_blocking(move || script.run(&request, &params)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::MockRequest;
+
+    fn compiled(code: &str, timeout: Option<u64>) -> CompiledScript {
+        CompiledScript::new(&Script {
+            code: Some(code.to_string()),
+            timeout,
+            ..Default::default()
+        })
+        .expect("valid script")
+    }
+
+    fn run(code: &str) -> Result<ScriptResponse, ScriptError> {
+        compiled(code, None).run(&MockRequest::default(), &HashMap::new())
+    }
+
+    #[test]
+    fn scripts_compute_the_response() {
+        let response = run(r#"#{ status: 201, headers: #{ "x-id": "1" }, body: "created" }"#)
+            .expect("response");
+        assert_eq!(response.status, Some(201));
+        assert_eq!(response.headers["x-id"], "1");
+        assert_eq!(response.body, Some(serde_json::json!("created")));
+    }
+
+    #[test]
+    fn invalid_statuses_and_headers_are_refused() {
+        for code in [
+            "#{ status: 1000 }",
+            r#"#{ headers: #{ "bad header": "1" } }"#,
+            r#"#{ headers: #{ "x-id": "a\nb" } }"#,
+        ] {
+            assert!(
+                matches!(run(code), Err(ScriptError::InvalidResult(_))),
+                "{code}"
+            );
+        }
+    }
+
+    #[test]
+    fn scripts_see_the_request_and_the_parameters() {
+        let request = MockRequest {
+            method: "POST".to_string(),
+            path: "/users/7".to_string(),
+            body: serde_json::json!({"amount": 5}),
+            ..Default::default()
+        };
+        let params = HashMap::from([("id".to_string(), "7".to_string())]);
+        let response = compiled(
+            "#{ body: #{ id: request.params.id, amount: request.body.amount * 2, method: request.method } }",
+            None,
+        )
+        .run(&request, &params)
+        .expect("response");
+        assert_eq!(
+            response.body,
+            Some(serde_json::json!({"id": "7", "amount": 10, "method": "POST"}))
+        );
+    }
+
+    #[test]
+    fn scripts_share_the_state_across_runs() {
+        run(r#"state_set("test-counter", 1); #{}"#).expect("response");
+        let response = run(
+            r#"let n = state_get("test-counter"); state_remove("test-counter"); #{ body: n + 1 }"#,
+        )
+        .expect("response");
+        assert_eq!(response.body, Some(serde_json::json!(2)));
+        assert_eq!(state_get("test-counter"), None);
+    }
+
+    #[test]
+    fn scripts_running_past_the_timeout_are_stopped() {
+        let started = std::time::Instant::now();
+        let result = compiled("loop {}", Some(50)).run(&MockRequest::default(), &HashMap::new());
+        assert!(matches!(result, Err(ScriptError::Timeout(50))));
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn results_other_than_a_map_are_refused() {
+        assert!(matches!(run("42"), Err(ScriptError::InvalidResult(_))));
+        assert!(matches!(
+            run("#{ status: \"ok\" }"),
+            Err(ScriptError::InvalidResult(_))
+        ));
+    }
+
+    #[test]
+    fn scripts_need_code_or_a_file() {
+        assert!(matches!(
+            CompiledScript::new(&Script::default()),
+            Err(ScriptError::Missing)
+        ));
+        assert!(matches!(
+            CompiledScript::new(&Script {
+                code: Some("let".to_string()),
+                ..Default::default()
+            }),
+            Err(ScriptError::Parse(_))
+        ));
+    }
+}
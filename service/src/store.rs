@@ -0,0 +1,90 @@
+use crate::utils::API;
+
+#[derive(thiserror::Error, Debug)]
+pub enum StoreError {
+    #[error("SqliteError: {0}")]
+    Sqlite(#[from] db::sqlite::Error),
+    #[error("InvalidDefinition: {id}: {source}")]
+    InvalidDefinition {
+        id: String,
+        source: serde_json::Error,
+    },
+    #[error("JsonSerializeError: {0}")]
+    JsonSerialize(#[from] serde_json::Error),
+}
+
+/// `MOCK_STORE=sqlite` keeps the mocks in the sqlite database at `SQLITE_PATH`
+/// so the mocks created at runtime survive the restarts, the mock files are
+/// then only used as the import format. Defaults to `file`.
+pub fn is_sqlite() -> bool {
+    crate::utils::read_mock_store().eq("sqlite")
+}
+
+/// Opens the database and loads the mocks, an empty database is seeded with
+/// the mocks from the files
+pub fn init(files: impl FnOnce() -> Vec<API>) -> Result<Vec<API>, StoreError> {
+    let path = crate::utils::read_sqlite_path();
+    db::sqlite::init_sqlite(path.as_str())?;
+    tracing::info!("Sqlite mock store: {}", path);
+    let apis = load()?;
+    if !apis.is_empty() {
+        return Ok(apis);
+    }
+    let apis = files();
+    persist(&[], &apis)?;
+    Ok(apis)
+}
+
+pub fn load() -> Result<Vec<API>, StoreError> {
+    db::sqlite::with_conn(|conn| db::sqlite::mocks(conn))?
+        .into_iter()
+        .map(|mock| {
+            serde_json::from_str(mock.definition.as_str()).map_err(|source| {
+                StoreError::InvalidDefinition {
+                    id: mock.id.to_string(),
+                    source,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Writes the difference between `old` and `new` mocks to the store in a
+/// single transaction, every mock is expected to have an id by now
+pub fn persist(old: &[API], new: &[API]) -> Result<(), StoreError> {
+    let definitions = new
+        .iter()
+        .map(|api| {
+            Ok((
+                api.id.clone().unwrap_or_default(),
+                serde_json::to_string(api)?,
+                api.tags.clone(),
+            ))
+        })
+        .collect::<Result<Vec<_>, StoreError>>()?;
+    let deleted: Vec<&str> = old
+        .iter()
+        .filter_map(|api| api.id.as_deref())
+        .filter(|id| !new.iter().any(|api| api.id.as_deref() == Some(*id)))
+        .collect();
+    db::sqlite::with_conn(|conn| {
+        let tx = conn.transaction()?;
+        for id in deleted {
+            db::sqlite::delete(&tx, id)?;
+        }
+        for (position, (id, definition, tags)) in definitions.iter().enumerate() {
+            db::sqlite::upsert(&tx, id, definition, tags, position)?;
+        }
+        tx.commit()
+    })?;
+    Ok(())
+}
+
+/// Stored mocks with their version and timestamps
+pub fn mocks() -> Result<Vec<db::sqlite::Mock>, StoreError> {
+    Ok(db::sqlite::with_conn(|conn| db::sqlite::mocks(conn))?)
+}
+
+pub fn history(id: &str) -> Result<Vec<db::sqlite::MockVersion>, StoreError> {
+    Ok(db::sqlite::with_conn(|conn| db::sqlite::history(conn, id))?)
+}
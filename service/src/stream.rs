@@ -0,0 +1,275 @@
+/// Response sent over time instead of all at once
+/// - `sse` sends the `events` as Server-Sent Events, each after its `delay`,
+///   and plays them again `repeat` more times, `0` repeats until the client
+///   disconnects
+/// - `chunked` sends the body of the mock in chunks of `chunk_size` bytes with
+///   `delay` milliseconds in between
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Stream {
+    Sse {
+        events: Vec<SseEvent>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        repeat: Option<u32>,
+        /// Reconnection time in milliseconds advised to the client
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry: Option<u64>,
+    },
+    Chunked {
+        #[serde(default = "default_chunk_size")]
+        chunk_size: usize,
+        #[serde(default)]
+        delay: u64,
+    },
+}
+
+fn default_chunk_size() -> usize {
+    64
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SseEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Sent as is if a string, as json otherwise
+    pub data: serde_json::Value,
+    /// Milliseconds to wait before the event is sent
+    #[serde(default)]
+    pub delay: u64,
+}
+
+impl SseEvent {
+    /// Event in the `text/event-stream` format, every line of the data is sent
+    /// as a `data:` field
+    fn encode(&self) -> String {
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str(format!("event: {}\n", single_line(event)).as_str());
+        }
+        if let Some(id) = &self.id {
+            out.push_str(format!("id: {}\n", single_line(id)).as_str());
+        }
+        let data = match &self.data {
+            serde_json::Value::String(text) => text.to_string(),
+            data => data.to_string(),
+        };
+        // clients break the lines on `\r` as well, an empty data is still sent
+        // as a `data:` field as an event without any is not dispatched
+        let data = data.replace("\r\n", "\n").replace('\r', "\n");
+        for line in data.split('\n') {
+            out.push_str(format!("data: {line}\n").as_str());
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// Value without the line breaks, which would end the field and start another
+fn single_line(value: &str) -> String {
+    value.replace(['\n', '\r'], "")
+}
+
+impl Stream {
+    pub fn render(
+        &self,
+        context: &serde_json::Value,
+    ) -> Result<Self, crate::template::TemplateError> {
+        Ok(match self {
+            Stream::Sse {
+                events,
+                repeat,
+                retry,
+            } => Stream::Sse {
+                events: events
+                    .iter()
+                    .map(|e| {
+                        let text = |value: &Option<String>| {
+                            value
+                                .as_deref()
+                                .map(|v| crate::template::render_text(v, context))
+                                .transpose()
+                        };
+                        Ok(SseEvent {
+                            event: text(&e.event)?,
+                            id: text(&e.id)?,
+                            data: crate::template::render(&e.data, context)?,
+                            delay: e.delay,
+                        })
+                    })
+                    .collect::<Result<_, crate::template::TemplateError>>()?,
+                repeat: *repeat,
+                retry: *retry,
+            },
+            stream => stream.clone(),
+        })
+    }
+
+    /// Content type of the stream, the chunked responses keep the one of the body
+    pub fn content_type(&self) -> Option<&'static str> {
+        match self {
+            Stream::Sse { .. } => Some("text/event-stream"),
+            Stream::Chunked { .. } => None,
+        }
+    }
+
+    /// What the journal records for the stream, a single pass of the events
+    /// or the whole body
+    pub fn preview(&self, body: &[u8]) -> Vec<u8> {
+        match self {
+            Stream::Sse { events, retry, .. } => {
+                let mut out = retry.map(|r| format!("retry: {r}\n\n")).unwrap_or_default();
+                events
+                    .iter()
+                    .for_each(|e| out.push_str(e.encode().as_str()));
+                out.into_bytes()
+            }
+            Stream::Chunked { .. } => body.to_vec(),
+        }
+    }
+
+    /// Body which is written in the background, writing stops once the client
+    /// goes away
+    pub fn body(self, body: hyper::body::Bytes) -> hyper::Body {
+        let (mut sender, streamed) = hyper::Body::channel();
+        let sleep = |millis: u64| tokio::time::sleep(std::time::Duration::from_millis(millis));
+        tokio::spawn(async move {
+            match self {
+                Stream::Sse {
+                    events,
+                    repeat,
+                    retry,
+                } => {
+                    if let Some(retry) = retry {
+                        let retry = format!("retry: {retry}\n\n");
+                        if sender.send_data(retry.into()).await.is_err() {
+                            return;
+                        }
+                    }
+                    let mut pass = 0;
+                    while !events.is_empty() {
+                        for event in &events {
+                            sleep(event.delay).await;
+                            if sender.send_data(event.encode().into()).await.is_err() {
+                                return;
+                            }
+                        }
+                        pass += 1;
+                        match repeat {
+                            Some(0) => {}
+                            Some(repeat) if pass <= repeat => {}
+                            _ => return,
+                        }
+                        // events without any delay would otherwise hold the
+                        // worker for as long as the client keeps reading
+                        tokio::task::yield_now().await;
+                    }
+                }
+                Stream::Chunked { chunk_size, delay } => {
+                    for (index, chunk) in body.chunks(chunk_size.max(1)).enumerate() {
+                        if index > 0 {
+                            sleep(delay).await;
+                        }
+                        if sender.send_data(body.slice_ref(chunk)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        streamed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stream(definition: serde_json::Value) -> Stream {
+        serde_json::from_value(definition).expect("valid stream")
+    }
+
+    fn event(definition: serde_json::Value) -> SseEvent {
+        serde_json::from_value(definition).expect("valid event")
+    }
+
+    async fn read(body: hyper::Body) -> String {
+        let bytes = hyper::body::to_bytes(body).await.expect("body");
+        String::from_utf8(bytes.to_vec()).expect("utf8")
+    }
+
+    #[test]
+    fn events_are_encoded_line_by_line() {
+        let encoded = event(json!({"event": "update", "id": "1", "data": "a\r\nb\rc"})).encode();
+        assert_eq!(
+            encoded,
+            "event: update\nid: 1\ndata: a\ndata: b\ndata: c\n\n"
+        );
+        assert_eq!(
+            event(json!({"data": {"a": 1}})).encode(),
+            "data: {\"a\":1}\n\n"
+        );
+        assert_eq!(event(json!({"data": ""})).encode(), "data: \n\n");
+    }
+
+    #[test]
+    fn event_names_and_ids_stay_on_a_single_line() {
+        let encoded = event(json!({"event": "a\nb", "id": "1\r2", "data": "x"})).encode();
+        assert_eq!(encoded, "event: ab\nid: 12\ndata: x\n\n");
+    }
+
+    #[test]
+    fn events_are_rendered_with_the_request() {
+        let context = json!({"request": {"path": "/feed", "params": {"id": "7"}}});
+        let rendered = stream(json!({"type": "sse", "events": [{
+            "event": "{{request.path}}",
+            "id": "{{request.params.id}}",
+            "data": {"id": "{{request.params.id}}"}
+        }]}))
+        .render(&context)
+        .expect("rendered");
+        assert_eq!(
+            String::from_utf8(rendered.preview(b"")).expect("utf8"),
+            "event: /feed\nid: 7\ndata: {\"id\":\"7\"}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn events_are_repeated_after_the_retry() {
+        let sse = stream(json!({
+            "type": "sse",
+            "retry": 500,
+            "repeat": 1,
+            "events": [{"data": "a"}, {"data": "b"}]
+        }));
+        assert_eq!(sse.content_type(), Some("text/event-stream"));
+        let body = read(sse.body(hyper::body::Bytes::new())).await;
+        assert_eq!(
+            body,
+            "retry: 500\n\ndata: a\n\ndata: b\n\ndata: a\n\ndata: b\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn chunked_bodies_keep_the_whole_body() {
+        let chunked = stream(json!({"type": "chunked", "chunk_size": 3}));
+        assert_eq!(chunked.content_type(), None);
+        let body = hyper::body::Bytes::from_static(b"abcdefgh");
+        assert_eq!(chunked.preview(&body), b"abcdefgh");
+        assert_eq!(read(chunked.body(body)).await, "abcdefgh");
+    }
+
+    #[tokio::test]
+    async fn chunks_are_sent_apart() {
+        use hyper::body::HttpBody;
+        let chunked = stream(json!({"type": "chunked", "chunk_size": 4, "delay": 1}));
+        let mut body = chunked.body(hyper::body::Bytes::from_static(b"abcdefghij"));
+        let mut chunks = vec![];
+        while let Some(chunk) = body.data().await {
+            chunks.push(chunk.expect("chunk").to_vec());
+        }
+        assert_eq!(chunks, [b"abcd".to_vec(), b"efgh".to_vec(), b"ij".to_vec()]);
+    }
+}
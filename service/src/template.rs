@@ -0,0 +1,332 @@
+use base64::Engine;
+use rand::Rng;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TemplateError {
+    #[error("UnknownHelper: {0}")]
+    UnknownHelper(String),
+    #[error("InvalidArgument: {helper}: {message}")]
+    InvalidArgument { helper: String, message: String },
+    #[error("UnclosedString: {0}")]
+    UnclosedString(String),
+}
+
+/// Renders the mock response, every string in the json is looked for `{{ }}`
+/// expressions. If the whole string is a single expression, the resolved json
+/// value replaces the string as is, so `"{{request.body.amount}}"` stays a
+/// number, otherwise the resolved values are interpolated into the string.
+///
+/// Expressions are either a path into the request or a helper call:
+/// - `request.method`, `request.path`, `request.params.<name>`,
+///   `request.query.<name>`, `request.headers.<name>`, `request.body.<a.b.0>`
+/// - `now`, `now "<strftime format>"`, `now_seconds`, `now_millis`
+/// - `uuid`
+/// - `random_int <min> <max>`, `random_float`
+/// - `base64_encode <arg>`, `base64_decode <arg>`
+///
+/// Arguments can be request paths, "quoted strings" or numbers.
+pub fn render(
+    value: &serde_json::Value,
+    context: &serde_json::Value,
+) -> Result<serde_json::Value, TemplateError> {
+    Ok(match value {
+        serde_json::Value::String(s) => render_str(s, context)?,
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .iter()
+                .map(|item| render(item, context))
+                .collect::<Result<_, _>>()?,
+        ),
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| Ok((k.to_string(), render(v, context)?)))
+                .collect::<Result<_, TemplateError>>()?,
+        ),
+        v => v.clone(),
+    })
+}
+
+fn render_str(s: &str, context: &serde_json::Value) -> Result<serde_json::Value, TemplateError> {
+    let trimmed = s.trim();
+    if trimmed.starts_with("{{") && trimmed.ends_with("}}") && trimmed.matches("{{").count() == 1 {
+        return eval(&trimmed[2..trimmed.len() - 2], context);
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let value = eval(&rest[start + 2..start + end], context)?;
+        out.push_str(to_text(&value).as_str());
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    Ok(serde_json::Value::String(out))
+}
+
+/// Renders the expressions in a text, resolved values are always interpolated
+pub fn render_text(s: &str, context: &serde_json::Value) -> Result<String, TemplateError> {
+    Ok(to_text(&render_str(s, context)?))
+}
+
+pub fn to_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.to_string(),
+        serde_json::Value::Null => "".to_string(),
+        v => v.to_string(),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<String>, TemplateError> {
+    let mut tokens = vec![];
+    let mut chars = expr.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut token = String::new();
+        if c == '"' {
+            token.push(c);
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err(TemplateError::UnclosedString(expr.to_string())),
+                }
+            }
+            token.push('"');
+        } else {
+            token.push(c);
+            while let Some(c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(*c);
+                chars.next();
+            }
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Looks up a dotted path like `request.body.items.0.id` in the json
+pub fn lookup<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        serde_json::Value::Object(map) => map.get(key),
+        serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+fn argument(token: &str, context: &serde_json::Value) -> serde_json::Value {
+    if token.len() >= 2 && token.starts_with('"') && token.ends_with('"') {
+        return serde_json::Value::String(token[1..token.len() - 1].to_string());
+    }
+    if let Ok(number) = serde_json::from_str::<serde_json::Number>(token) {
+        return serde_json::Value::Number(number);
+    }
+    lookup(context, token)
+        .cloned()
+        .unwrap_or(serde_json::Value::Null)
+}
+
+fn eval(expr: &str, context: &serde_json::Value) -> Result<serde_json::Value, TemplateError> {
+    let tokens = tokenize(expr)?;
+    let Some((helper, args)) = tokens.split_first() else {
+        return Ok(serde_json::Value::Null);
+    };
+    let args: Vec<serde_json::Value> = args.iter().map(|a| argument(a, context)).collect();
+    let invalid = |message: &str| TemplateError::InvalidArgument {
+        helper: helper.to_string(),
+        message: message.to_string(),
+    };
+    let now = chrono::Utc::now();
+    Ok(match helper.as_str() {
+        "now" => match args.first() {
+            Some(serde_json::Value::String(format)) => {
+                use std::fmt::Write;
+                // an invalid format fails on writing, `to_string` would panic
+                let mut formatted = String::new();
+                write!(formatted, "{}", now.format(format))
+                    .map_err(|_| invalid(format!("invalid format {format}").as_str()))?;
+                serde_json::Value::String(formatted)
+            }
+            _ => serde_json::Value::String(now.to_rfc3339()),
+        },
+        "now_seconds" => serde_json::json!(now.timestamp()),
+        "now_millis" => serde_json::json!(now.timestamp_millis()),
+        "uuid" => serde_json::Value::String(uuid::Uuid::new_v4().to_string()),
+        "random_int" => {
+            let min = args.first().and_then(|v| v.as_i64()).unwrap_or(0);
+            let max = args.get(1).and_then(|v| v.as_i64()).unwrap_or(i64::MAX);
+            if min > max {
+                return Err(invalid("min is greater than max"));
+            }
+            serde_json::json!(rand::thread_rng().gen_range(min..=max))
+        }
+        "random_float" => serde_json::json!(rand::thread_rng().gen::<f64>()),
+        "base64_encode" => {
+            let arg = args.first().ok_or_else(|| invalid("missing argument"))?;
+            serde_json::Value::String(
+                base64::engine::general_purpose::STANDARD.encode(to_text(arg).as_bytes()),
+            )
+        }
+        "base64_decode" => {
+            let arg = args.first().ok_or_else(|| invalid("missing argument"))?;
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(to_text(arg).as_bytes())
+                .map_err(|e| invalid(e.to_string().as_str()))?;
+            serde_json::Value::String(String::from_utf8_lossy(&decoded).to_string())
+        }
+        path if path.starts_with("request.") || path == "request" => {
+            if !args.is_empty() {
+                return Err(invalid("request paths do not take arguments"));
+            }
+            lookup(context, path)
+                .cloned()
+                .unwrap_or(serde_json::Value::Null)
+        }
+        helper => return Err(TemplateError::UnknownHelper(helper.to_string())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> serde_json::Value {
+        json!({
+            "request": {
+                "method": "POST",
+                "params": {"id": "42"},
+                "body": {"amount": 10, "items": [{"id": "a"}], "encoded": "aGk="},
+            }
+        })
+    }
+
+    fn eval(template: &str) -> Result<serde_json::Value, TemplateError> {
+        render(&json!(template), &context())
+    }
+
+    #[test]
+    fn single_expressions_keep_the_json_type() {
+        assert_eq!(eval("{{request.body.amount}}").unwrap(), json!(10));
+        assert_eq!(
+            eval(" {{ request.body.items }} ").unwrap(),
+            json!([{"id": "a"}])
+        );
+        assert_eq!(eval("{{request.body.items.0.id}}").unwrap(), json!("a"));
+        assert_eq!(eval("{{request.body.missing}}").unwrap(), json!(null));
+        assert_eq!(eval("{{}}").unwrap(), json!(null));
+    }
+
+    #[test]
+    fn expressions_are_interpolated_into_text() {
+        assert_eq!(
+            eval("{{request.method}} /users/{{request.params.id}} x{{request.body.amount}}")
+                .unwrap(),
+            json!("POST /users/42 x10")
+        );
+        assert_eq!(
+            eval("missing: {{request.nope}}.").unwrap(),
+            json!("missing: .")
+        );
+        assert_eq!(eval("open {{ only").unwrap(), json!("open {{ only"));
+    }
+
+    #[test]
+    fn nested_values_are_rendered() {
+        let rendered = render(
+            &json!({"id": "{{request.params.id}}", "list": ["{{request.method}}", 1]}),
+            &context(),
+        )
+        .unwrap();
+        assert_eq!(rendered, json!({"id": "42", "list": ["POST", 1]}));
+    }
+
+    #[test]
+    fn time_helpers() {
+        let now = eval("{{now}}").unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(now.as_str().unwrap()).is_ok());
+        let year = eval("{{now \"%Y\"}}").unwrap();
+        assert_eq!(year.as_str().unwrap().len(), 4);
+        assert!(eval("{{now_seconds}}").unwrap().is_i64());
+        let millis = eval("{{now_millis}}").unwrap().as_i64().unwrap();
+        let seconds = eval("{{now_seconds}}").unwrap().as_i64().unwrap();
+        assert!((millis / 1000 - seconds).abs() <= 1);
+    }
+
+    #[test]
+    fn invalid_now_format_is_an_error() {
+        assert!(matches!(
+            eval("{{now \"%Q\"}}"),
+            Err(TemplateError::InvalidArgument { helper, .. }) if helper == "now"
+        ));
+    }
+
+    #[test]
+    fn random_helpers() {
+        let uuid = eval("{{uuid}}").unwrap();
+        assert!(uuid::Uuid::parse_str(uuid.as_str().unwrap()).is_ok());
+        for _ in 0..32 {
+            let value = eval("{{random_int 1 3}}").unwrap().as_i64().unwrap();
+            assert!((1..=3).contains(&value));
+            let value = eval("{{random_float}}").unwrap().as_f64().unwrap();
+            assert!((0.0..1.0).contains(&value));
+        }
+        assert_eq!(eval("{{random_int 5 5}}").unwrap(), json!(5));
+        assert!(matches!(
+            eval("{{random_int 5 1}}"),
+            Err(TemplateError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn base64_helpers() {
+        assert_eq!(eval("{{base64_encode \"hi\"}}").unwrap(), json!("aGk="));
+        assert_eq!(
+            eval("{{base64_decode request.body.encoded}}").unwrap(),
+            json!("hi")
+        );
+        assert!(matches!(
+            eval("{{base64_encode}}"),
+            Err(TemplateError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            eval("{{base64_decode \"!!\"}}"),
+            Err(TemplateError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_expressions_are_errors() {
+        assert!(matches!(
+            eval("{{nope}}"),
+            Err(TemplateError::UnknownHelper(helper)) if helper == "nope"
+        ));
+        assert!(matches!(
+            eval("{{base64_encode \"hi}}"),
+            Err(TemplateError::UnclosedString(_))
+        ));
+        assert!(matches!(
+            eval("{{request.method 1}}"),
+            Err(TemplateError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn lookup_follows_objects_and_arrays() {
+        let context = context();
+        assert_eq!(
+            lookup(&context, "request.body.items.0.id"),
+            Some(&json!("a"))
+        );
+        assert_eq!(lookup(&context, "request.body.items.x"), None);
+        assert_eq!(lookup(&context, "request.method.x"), None);
+    }
+}
@@ -12,15 +12,109 @@ pub fn read_port_env() -> u16 {
     };
 
     port.parse()
-        .expect(format!("cannot parse port: {port}").as_str())
+        .unwrap_or_else(|_| panic!("cannot parse port: {port}"))
 }
 
 pub fn is_traced() -> bool {
     std::env::var("TRACING").is_ok() || std::env::args().any(|e| e == "--trace")
 }
 
-// these apis will be read from the db going forward, like SQLite
-const APIS: &str = include_str!("../apis.json");
+/// File or directory from where the mocks are loaded, defaults to `service/apis.json`
+pub fn read_mocks_path() -> String {
+    std::env::var("MOCKS_PATH").unwrap_or_else(|_| "service/apis.json".to_string())
+}
+
+/// Interval in milliseconds at which the mock files are checked for changes
+pub fn read_mocks_reload_interval() -> u64 {
+    match std::env::var("MOCKS_RELOAD_INTERVAL") {
+        Ok(interval) => interval
+            .parse()
+            .unwrap_or_else(|_| panic!("cannot parse mocks reload interval: {interval}")),
+        Err(_) => 1000,
+    }
+}
+
+/// Storage of the mocks, `file` or `sqlite`, see `crate::store`
+pub fn read_mock_store() -> String {
+    std::env::var("MOCK_STORE")
+        .map(|store| store.to_lowercase())
+        .unwrap_or_else(|_| "file".to_string())
+}
+
+pub fn read_sqlite_path() -> String {
+    std::env::var("SQLITE_PATH").unwrap_or_else(|_| "webgenix.sqlite3".to_string())
+}
+
+/// Maximum number of requests kept in the journal, `0` or not set keeps all
+pub fn read_journal_limit() -> usize {
+    match std::env::var("JOURNAL_LIMIT") {
+        Ok(limit) => limit
+            .parse()
+            .unwrap_or_else(|_| panic!("cannot parse journal limit: {limit}")),
+        Err(_) => 0,
+    }
+}
+
+/// File the journal entries are appended to as json lines, if set
+pub fn read_journal_file() -> Option<String> {
+    std::env::var("JOURNAL_FILE").ok()
+}
+
+/// `mock`, `record` or `playback`, see `crate::recorder`
+pub fn read_mock_mode() -> String {
+    std::env::var("MOCK_MODE")
+        .map(|mode| mode.to_lowercase())
+        .unwrap_or_else(|_| "mock".to_string())
+}
+
+/// Base url the unmatched requests are forwarded to in the `record` mode
+pub fn read_proxy_upstream() -> Option<String> {
+    std::env::var("PROXY_UPSTREAM").ok()
+}
+
+pub fn read_record_file() -> String {
+    std::env::var("RECORD_FILE").unwrap_or_else(|_| "recordings.json".to_string())
+}
+
+/// Comma separated response headers recorded with the value `REDACTED`
+pub fn read_record_redact_headers() -> Option<String> {
+    std::env::var("RECORD_REDACT_HEADERS").ok()
+}
+
+/// Comma separated response headers left out of the recordings
+pub fn read_record_ignore_headers() -> Option<String> {
+    std::env::var("RECORD_IGNORE_HEADERS").ok()
+}
+
+/// Comma separated json fields, at any depth, recorded with the value `REDACTED`
+pub fn read_record_redact_fields() -> Option<String> {
+    std::env::var("RECORD_REDACT_FIELDS").ok()
+}
+
+/// Comma separated json fields, at any depth, left out of the recordings
+pub fn read_record_ignore_fields() -> Option<String> {
+    std::env::var("RECORD_IGNORE_FIELDS").ok()
+}
+
+/// Comma separated OpenAPI 3 specs, yaml or json, the mocks are generated from
+pub fn read_openapi_path() -> Option<String> {
+    std::env::var("OPENAPI_PATH").ok()
+}
+
+/// Directory the `*.wasm` plugins are loaded from at the startup, if set
+pub fn read_plugins_path() -> Option<String> {
+    std::env::var("PLUGINS_PATH").ok()
+}
+
+/// Scales every mock delay, `2` doubles and `0` disables all of the delays
+pub fn read_latency_multiplier() -> f64 {
+    match std::env::var("LATENCY_MULTIPLIER") {
+        Ok(multiplier) => multiplier
+            .parse()
+            .unwrap_or_else(|_| panic!("cannot parse latency multiplier: {multiplier}")),
+        Err(_) => 1.0,
+    }
+}
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct APIResponse {
@@ -28,35 +122,895 @@ pub struct APIResponse {
     pub data: serde_json::Value,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+/// Raw response body of a mock, used in place of the `APIResponse` shape
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseBody {
+    Json(serde_json::Value),
+    Text(String),
+    Xml(String),
+    /// Base64 encoded binary content
+    Base64(String),
+    /// Path of the file to be served as the body
+    File(String),
+    /// Json generated from a schema, see `crate::fake`
+    Fake(crate::fake::FakeBody),
+}
+
+impl ResponseBody {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ResponseBody::Json(_) | ResponseBody::Fake(_) => "application/json",
+            ResponseBody::Text(_) => "text/plain",
+            ResponseBody::Xml(_) => "application/xml",
+            ResponseBody::Base64(_) => "application/octet-stream",
+            ResponseBody::File(path) => {
+                match std::path::Path::new(path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default()
+                {
+                    "json" => "application/json",
+                    "xml" => "application/xml",
+                    "html" => "text/html",
+                    "txt" => "text/plain",
+                    "csv" => "text/csv",
+                    "png" => "image/png",
+                    "jpg" | "jpeg" => "image/jpeg",
+                    "pdf" => "application/pdf",
+                    _ => "application/octet-stream",
+                }
+            }
+        }
+    }
+
+    pub fn render(
+        &self,
+        context: &serde_json::Value,
+    ) -> Result<Self, crate::template::TemplateError> {
+        Ok(match self {
+            ResponseBody::Json(value) => {
+                ResponseBody::Json(crate::template::render(value, context)?)
+            }
+            ResponseBody::Text(text) => {
+                ResponseBody::Text(crate::template::render_text(text, context)?)
+            }
+            ResponseBody::Xml(xml) => {
+                ResponseBody::Xml(crate::template::render_text(xml, context)?)
+            }
+            body => body.clone(),
+        })
+    }
+
+    pub async fn bytes(self) -> Result<Vec<u8>, ResponseBodyError> {
+        use base64::Engine;
+        Ok(match self {
+            ResponseBody::Json(value) => serde_json::to_vec(&value)?,
+            ResponseBody::Text(text) | ResponseBody::Xml(text) => text.into_bytes(),
+            ResponseBody::Base64(encoded) => {
+                base64::engine::general_purpose::STANDARD.decode(encoded.as_bytes())?
+            }
+            ResponseBody::File(path) => tokio::fs::read(path).await?,
+            ResponseBody::Fake(fake) => serde_json::to_vec(&fake.generate())?,
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResponseBodyError {
+    #[error("JsonSerializeError: {0}")]
+    JsonSerialize(#[from] serde_json::Error),
+    #[error("Base64DecodeError: {0}")]
+    Base64Decode(#[from] base64::DecodeError),
+    #[error("FileReadError: {0}")]
+    FileRead(#[from] std::io::Error),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct API {
+    /// Identifier used by the admin apis, generated if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Disabled mocks are kept in the registry but never matched
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub method: String,
     pub path: String,
-    pub response: APIResponse,
+    /// Json response in the `{"success": .., "data": ..}` shape, ignored if `body` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<APIResponse>,
+    /// Response body which can be json, text, xml, base64 encoded binary, a file
+    /// or json generated from a schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<ResponseBody>,
+    /// HTTP status code of the response, defaults to 200
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Response headers, these override the content type derived from the body
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub headers: std::collections::HashMap<String, String>,
+    /// Cookies sent as `Set-Cookie: <name>=<value>`, value can carry attributes
+    /// like `abc; Path=/; HttpOnly`
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub cookies: std::collections::HashMap<String, String>,
+    /// Fixed delay in milliseconds, same as a `fixed` latency
     pub wait: Option<u64>,
+    /// Delay sampled from a distribution for every request, takes precedence over `wait`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<crate::latency::Latency>,
+    /// Transport or protocol failure to respond with instead of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<crate::fault::Fault>,
+    /// Fails a random fraction of the requests with the given status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub random_error: Option<crate::fault::RandomError>,
+    /// Conditions on query, headers, cookies and body besides method and path
+    #[serde(default, rename = "match", skip_serializing_if = "Option::is_none")]
+    pub request: Option<crate::matcher::RequestMatch>,
+    /// Schemas the matched requests are validated against, see `crate::contract`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<crate::contract::Contract>,
+    /// GraphQL operation the mock responds to, see `crate::graphql`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graphql: Option<crate::graphql::GraphQL>,
+    /// Scenario the mock takes part in, see `crate::scenario`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<crate::scenario::Scenario>,
+    /// Script computing the response from the request, see `crate::script`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<crate::script::Script>,
+    /// WebAssembly plugin computing the response, see `crate::plugin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<crate::plugin::PluginHandler>,
+    /// Streams the response over time, as Server-Sent Events or in chunks, see
+    /// `crate::stream`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<crate::stream::Stream>,
+    /// Outbound calls made after the mock responds, see `crate::webhook`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<crate::webhook::Webhook>,
+    /// Responds only this many times, then falls through to the next matching mock
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<u64>,
+    /// Matches only from this time on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Matches only until this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Mocks with a higher priority are evaluated first, mocks with the same
+    /// priority are evaluated in the order of their path specificity
+    #[serde(default)]
+    pub priority: i64,
+    /// Render `{{ }}` expressions in the response from the request, see `crate::template`
+    #[serde(default)]
+    pub template: bool,
+}
+
+fn default_enabled() -> bool {
+    true
 }
 
-pub struct APIs(Vec<API>);
+impl Default for API {
+    fn default() -> Self {
+        API {
+            id: None,
+            enabled: true,
+            tags: vec![],
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            response: None,
+            body: None,
+            status: None,
+            headers: Default::default(),
+            cookies: Default::default(),
+            wait: None,
+            latency: None,
+            fault: None,
+            random_error: None,
+            request: None,
+            contract: None,
+            graphql: None,
+            scenario: None,
+            script: None,
+            plugin: None,
+            stream: None,
+            webhooks: vec![],
+            times: None,
+            active_from: None,
+            expires_at: None,
+            priority: 0,
+            template: false,
+        }
+    }
+}
 
-impl APIs {
-    pub fn response(&self, method: &str, path: &str) -> Option<APIResponse> {
-        match self
-            .0
-            .iter()
-            .find(|api| api.method.eq(method) && api.path.eq(path))
-        {
-            Some(api) => {
-                if let Some(wait) = api.wait {
-                    std::thread::sleep(std::time::Duration::from_millis(wait));
+impl API {
+    /// Assigns a generated id if the mock does not have one
+    pub fn with_id(mut self) -> Self {
+        if self.id.is_none() {
+            self.id = Some(uuid::Uuid::new_v4().to_string());
+        }
+        self
+    }
+
+    /// Whether the time is within the `active_from` and `expires_at` window
+    pub fn is_active(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.active_from.map(|from| now >= from).unwrap_or(true)
+            && self.expires_at.map(|until| now < until).unwrap_or(true)
+    }
+}
+
+/// Number of times a mock has responded and how many are left
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Hits {
+    pub id: Option<String>,
+    pub hits: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<u64>,
+}
+
+/// Incoming request as seen by the mocks, header names are lower cased
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub query: std::collections::HashMap<String, String>,
+    pub headers: std::collections::HashMap<String, String>,
+    pub body: serde_json::Value,
+}
+
+impl MockRequest {
+    pub fn new(parts: &hyper::http::request::Parts, body: serde_json::Value) -> Self {
+        let query = form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+        // repeated headers are joined into one value, the cookies with `; `
+        let mut headers = std::collections::HashMap::<String, String>::new();
+        for (name, value) in parts.headers.iter() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|joined| {
+                    joined.push_str(match name == hyper::header::COOKIE {
+                        true => "; ",
+                        false => ", ",
+                    });
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.to_string());
+        }
+        MockRequest {
+            method: parts.method.as_str().to_string(),
+            path: parts.uri.path().to_string(),
+            query,
+            headers,
+            body,
+        }
+    }
+
+    /// Json context the response templates are rendered against
+    pub fn template_context(
+        &self,
+        params: &std::collections::HashMap<String, String>,
+    ) -> serde_json::Value {
+        let mut request = serde_json::to_value(self).unwrap_or_default();
+        if let Some(request) = request.as_object_mut() {
+            request.insert("params".to_string(), serde_json::json!(params));
+        }
+        serde_json::json!({ "request": request })
+    }
+}
+
+/// Body of a matched mock, the bodies which do not depend on the request are
+/// serialized once when the mocks are compiled
+#[derive(Clone, Debug)]
+pub enum MatchedBody {
+    Prepared {
+        content_type: &'static str,
+        bytes: hyper::body::Bytes,
+    },
+    Body(ResponseBody),
+}
+
+impl MatchedBody {
+    /// Serialized body if it is the same for every request, `None` for the
+    /// templates, generated bodies and files, and for the invalid base64
+    /// bodies which fail on the request instead
+    fn prepare(body: &ResponseBody, template: bool) -> Option<Self> {
+        use base64::Engine;
+        if template {
+            return None;
+        }
+        let bytes = match body {
+            ResponseBody::Json(value) => serde_json::to_vec(value).ok()?,
+            ResponseBody::Text(text) | ResponseBody::Xml(text) => text.as_bytes().to_vec(),
+            ResponseBody::Base64(encoded) => base64::engine::general_purpose::STANDARD
+                .decode(encoded.as_bytes())
+                .ok()?,
+            ResponseBody::File(_) | ResponseBody::Fake(_) => return None,
+        };
+        Some(MatchedBody::Prepared {
+            content_type: body.content_type(),
+            bytes: bytes.into(),
+        })
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            MatchedBody::Prepared { content_type, .. } => content_type,
+            MatchedBody::Body(body) => body.content_type(),
+        }
+    }
+
+    pub async fn bytes(self) -> Result<hyper::body::Bytes, ResponseBodyError> {
+        Ok(match self {
+            MatchedBody::Prepared { bytes, .. } => bytes,
+            MatchedBody::Body(body) => body.bytes().await?.into(),
+        })
+    }
+}
+
+/// Response of the matched mock along with the parameters captured from the path
+pub struct MatchedAPI {
+    pub id: Option<String>,
+    pub status: u16,
+    pub headers: std::collections::HashMap<String, String>,
+    pub cookies: std::collections::HashMap<String, String>,
+    pub body: Option<MatchedBody>,
+    pub params: std::collections::HashMap<String, String>,
+    pub delay: Option<std::time::Duration>,
+    pub fault: Option<crate::fault::Fault>,
+    pub stream: Option<crate::stream::Stream>,
+    /// Webhooks rendered with the request, fired once the response is sent
+    pub webhooks: Vec<crate::webhook::Webhook>,
+}
+
+impl MatchedAPI {
+    /// `400` for a request which matched the mock but is not valid for it
+    fn rejected(
+        api: &API,
+        params: std::collections::HashMap<String, String>,
+        body: serde_json::Value,
+    ) -> Self {
+        MatchedAPI {
+            id: api.id.clone(),
+            status: 400,
+            headers: Default::default(),
+            cookies: Default::default(),
+            body: Some(MatchedBody::Body(ResponseBody::Json(body))),
+            params,
+            delay: None,
+            fault: None,
+            stream: None,
+            webhooks: vec![],
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CompileError {
+    #[error("PathError: {0}")]
+    Path(#[from] crate::path::PathError),
+    #[error("MatcherError: {path}: {source}")]
+    Matcher {
+        path: String,
+        source: crate::matcher::MatcherError,
+    },
+    #[error("LatencyError: {path}: {source}")]
+    Latency {
+        path: String,
+        source: crate::latency::LatencyError,
+    },
+    #[error("ContractError: {path}: {source}")]
+    Contract {
+        path: String,
+        source: crate::contract::ContractError,
+    },
+    #[error("ScriptError: {path}: {source}")]
+    Script {
+        path: String,
+        source: crate::script::ScriptError,
+    },
+    #[error("GraphQLError: {path}: {source}")]
+    GraphQL {
+        path: String,
+        source: crate::graphql::GraphQLError,
+    },
+    #[error("StatusError: {path}: {source}")]
+    Status {
+        path: String,
+        source: hyper::http::status::InvalidStatusCode,
+    },
+    #[error("HeaderNameError: {path}: {source}")]
+    HeaderName {
+        path: String,
+        source: hyper::header::InvalidHeaderName,
+    },
+    #[error("HeaderValueError: {path}: {source}")]
+    HeaderValue {
+        path: String,
+        source: hyper::header::InvalidHeaderValue,
+    },
+    #[error("WebhookError: {path}: {source}")]
+    Webhook {
+        path: String,
+        source: crate::webhook::WebhookError,
+    },
+}
+
+/// Checks the status codes, headers and cookies of the response up front so a
+/// bad mock is refused when loaded instead of failing every request it matches
+fn check_response(api: &API) -> Result<(), CompileError> {
+    let statuses = api
+        .status
+        .iter()
+        .chain(api.random_error.as_ref().map(|e| &e.status));
+    for status in statuses {
+        hyper::StatusCode::from_u16(*status).map_err(|source| CompileError::Status {
+            path: api.path.to_string(),
+            source,
+        })?;
+    }
+    for (name, value) in &api.headers {
+        hyper::header::HeaderName::from_bytes(name.as_bytes()).map_err(|source| {
+            CompileError::HeaderName {
+                path: api.path.to_string(),
+                source,
+            }
+        })?;
+        hyper::http::HeaderValue::from_str(value.as_str()).map_err(|source| {
+            CompileError::HeaderValue {
+                path: api.path.to_string(),
+                source,
+            }
+        })?;
+    }
+    for (name, value) in &api.cookies {
+        hyper::http::HeaderValue::from_str(format!("{name}={value}").as_str()).map_err(
+            |source| CompileError::HeaderValue {
+                path: api.path.to_string(),
+                source,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+struct Mock {
+    pattern: crate::path::PathPattern,
+    matcher: crate::matcher::RequestMatcher,
+    contract: Option<crate::contract::ContractValidator>,
+    graphql: Option<crate::graphql::GraphQLMatcher>,
+    script: Option<std::sync::Arc<crate::script::CompiledScript>>,
+    hits: std::sync::atomic::AtomicU64,
+    body: Option<MatchedBody>,
+    api: API,
+}
+
+impl Mock {
+    fn new(api: API) -> Result<Self, CompileError> {
+        let api = api.with_id();
+        let matcher = match &api.request {
+            Some(m) => {
+                crate::matcher::RequestMatcher::new(m).map_err(|source| CompileError::Matcher {
+                    path: api.path.to_string(),
+                    source,
+                })?
+            }
+            None => Default::default(),
+        };
+        check_response(&api)?;
+        for webhook in &api.webhooks {
+            webhook.validate().map_err(|source| CompileError::Webhook {
+                path: api.path.to_string(),
+                source,
+            })?;
+        }
+        if let Some(latency) = &api.latency {
+            latency.validate().map_err(|source| CompileError::Latency {
+                path: api.path.to_string(),
+                source,
+            })?;
+        }
+        let contract = api
+            .contract
+            .as_ref()
+            .map(crate::contract::ContractValidator::new)
+            .transpose()
+            .map_err(|source| CompileError::Contract {
+                path: api.path.to_string(),
+                source,
+            })?;
+        let graphql = api
+            .graphql
+            .as_ref()
+            .map(crate::graphql::GraphQLMatcher::new)
+            .transpose()
+            .map_err(|source| CompileError::GraphQL {
+                path: api.path.to_string(),
+                source,
+            })?;
+        let script = api
+            .script
+            .as_ref()
+            .map(crate::script::CompiledScript::new)
+            .transpose()
+            .map(|script| script.map(std::sync::Arc::new))
+            .map_err(|source| CompileError::Script {
+                path: api.path.to_string(),
+                source,
+            })?;
+        let body = match (&api.body, &api.response) {
+            (Some(body), _) => Some(body.clone()),
+            (None, Some(response)) => Some(ResponseBody::Json(
+                serde_json::to_value(response).unwrap_or_default(),
+            )),
+            (None, None) => api
+                .graphql
+                .as_ref()
+                .and_then(|g| g.envelope())
+                .map(ResponseBody::Json),
+        }
+        .map(|body| MatchedBody::prepare(&body, api.template).unwrap_or(MatchedBody::Body(body)));
+        Ok(Mock {
+            pattern: crate::path::PathPattern::parse(api.path.as_str())?,
+            matcher,
+            contract,
+            graphql,
+            script,
+            hits: Default::default(),
+            body,
+            api,
+        })
+    }
+
+    /// Counts a hit, fails without counting once the mock is used up so that
+    /// concurrent requests can not go over `times`
+    fn hit(&self) -> bool {
+        use std::sync::atomic::Ordering;
+        self.hits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |hits| {
+                match self.api.times {
+                    Some(times) if hits >= times => None,
+                    _ => Some(hits + 1),
                 }
-                Some(api.response.clone())
+            })
+            .is_ok()
+    }
+
+    fn used_up(&self) -> bool {
+        let hits = self.hits.load(std::sync::atomic::Ordering::SeqCst);
+        self.api.times.map(|times| hits >= times).unwrap_or(false)
+    }
+
+    /// Whether the scenario of the mock is in the required state, without
+    /// taking the lock for the mocks outside of a scenario
+    fn in_state(&self) -> bool {
+        self.api
+            .scenario
+            .as_ref()
+            .map(|scenario| crate::scenario::lock().matches(scenario))
+            .unwrap_or(true)
+    }
+
+    /// `400` for a request which matches the mock but violates its contract or
+    /// its GraphQL schema
+    fn reject(
+        &self,
+        request: &MockRequest,
+        operation: Option<&crate::graphql::Operation>,
+        params: &std::collections::HashMap<String, String>,
+    ) -> Option<MatchedAPI> {
+        if let Some(contract) = &self.contract {
+            let violations = contract.validate(request);
+            if !violations.is_empty() {
+                tracing::warn!(
+                    target = "ContractViolation",
+                    id = self.api.id.as_deref().unwrap_or_default(),
+                    method = request.method.as_str(),
+                    path = request.path.as_str(),
+                    violations = serde_json::to_string(&violations).unwrap_or_default()
+                );
+                return Some(MatchedAPI::rejected(
+                    &self.api,
+                    params.clone(),
+                    serde_json::json!({
+                        "success": false,
+                        "message": "CONTRACT_VIOLATION",
+                        "violations": violations,
+                    }),
+                ));
+            }
+        }
+        if let Some(graphql) = &self.graphql {
+            let errors = graphql.validate(operation);
+            if !errors.is_empty() {
+                tracing::warn!(
+                    target = "GraphQLValidationError",
+                    id = self.api.id.as_deref().unwrap_or_default(),
+                    errors = serde_json::to_string(&errors).unwrap_or_default()
+                );
+                return Some(MatchedAPI::rejected(
+                    &self.api,
+                    params.clone(),
+                    serde_json::json!({ "errors": errors }),
+                ));
             }
-            None => None,
+        }
+        None
+    }
+
+    /// Counts a hit for the request, a mock in a scenario also needs to be in
+    /// the required state and moves the scenario on, under one lock so that
+    /// concurrent requests can not both match the same state
+    fn claim(&self) -> bool {
+        let Some(scenario) = &self.api.scenario else {
+            return self.hit();
+        };
+        let mut states = crate::scenario::lock();
+        if !states.matches(scenario) || !self.hit() {
+            return false;
+        }
+        states.transition(scenario);
+        true
+    }
+
+    fn hits(&self) -> Hits {
+        let hits = self.hits.load(std::sync::atomic::Ordering::SeqCst);
+        Hits {
+            id: self.api.id.clone(),
+            hits,
+            remaining: self.api.times.map(|times| times.saturating_sub(hits)),
         }
     }
 }
 
-pub fn apis() -> Result<APIs, Box<dyn std::error::Error>> {
-    let apis: Vec<API> = serde_json::from_str(APIS)?;
-    Ok(APIs(apis))
+/// Outcome of matching a request, the claiming mock with the path parameters
+/// or a response rejecting the request
+enum Found<'a> {
+    Mock(&'a Mock, std::collections::HashMap<String, String>),
+    Rejected(MatchedAPI),
+}
+
+/// Mocks along with their compiled paths, matchers, bodies and hit counters,
+/// ordered by the priority and then by the specificity of the paths so the
+/// first match wins. Mocks are indexed by the method and the path so that only
+/// the ones which can match a request are evaluated.
+#[derive(Default)]
+pub struct APIs {
+    mocks: Vec<Mock>,
+    index: std::collections::HashMap<String, crate::path::PathIndex>,
+}
+
+impl APIs {
+    pub fn new(apis: Vec<API>) -> Result<Self, CompileError> {
+        let mut apis = apis
+            .into_iter()
+            .map(Mock::new)
+            .collect::<Result<Vec<_>, CompileError>>()?;
+        apis.sort_by_key(|mock| {
+            (
+                std::cmp::Reverse(mock.api.priority),
+                std::cmp::Reverse(mock.pattern.specificity()),
+            )
+        });
+        let mut index: std::collections::HashMap<String, crate::path::PathIndex> =
+            Default::default();
+        for (position, mock) in apis.iter().enumerate() {
+            index
+                .entry(mock.api.method.to_string())
+                .or_default()
+                .insert(&mock.pattern, position);
+        }
+        Ok(APIs { mocks: apis, index })
+    }
+
+    pub fn len(&self) -> usize {
+        self.mocks.len()
+    }
+
+    /// Mock definitions in the order they are evaluated
+    pub fn apis(&self) -> Vec<API> {
+        self.mocks.iter().map(|mock| mock.api.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mocks.is_empty()
+    }
+
+    /// Hit counters of the mocks in the order they are evaluated
+    pub fn hits(&self) -> Vec<Hits> {
+        self.mocks.iter().map(Mock::hits).collect()
+    }
+
+    /// Carries the hit counters over from the mocks with the same id, so that
+    /// editing one mock does not give the used up ones a fresh start
+    pub fn with_hits(self, previous: &APIs) -> Self {
+        use std::sync::atomic::Ordering;
+        let hits: std::collections::HashMap<&str, u64> = previous
+            .mocks
+            .iter()
+            .filter_map(|old| Some((old.api.id.as_deref()?, old.hits.load(Ordering::SeqCst))))
+            .collect();
+        for mock in &self.mocks {
+            if let Some(hits) = mock.api.id.as_deref().and_then(|id| hits.get(id)) {
+                mock.hits.store(*hits, Ordering::SeqCst);
+            }
+        }
+        self
+    }
+
+    pub fn reset_hits(&self) {
+        for mock in &self.mocks {
+            mock.hits.store(0, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// Mocks closest to matching the request, best first, for the requests
+    /// which did not match any of the mocks
+    pub fn near_misses(
+        &self,
+        request: &MockRequest,
+        limit: usize,
+    ) -> Vec<crate::nearmiss::NearMiss> {
+        let states = crate::scenario::lock();
+        let mut near_misses: Vec<crate::nearmiss::NearMiss> = self
+            .mocks
+            .iter()
+            .map(|mock| {
+                crate::nearmiss::compare(
+                    &mock.api,
+                    &mock.pattern,
+                    &mock.matcher,
+                    mock.graphql.as_ref(),
+                    &states,
+                    &mock.hits(),
+                    request,
+                )
+            })
+            .filter(|near_miss| near_miss.score > 0.0)
+            .collect();
+        near_misses.sort_by(|a, b| b.score.total_cmp(&a.score));
+        near_misses.truncate(limit);
+        near_misses
+    }
+
+    /// First mock claiming the request, or the `400` of a mock the request
+    /// matches but is not valid for
+    fn find(&self, request: &MockRequest) -> Option<Found<'_>> {
+        let now = chrono::Utc::now();
+        let candidates = self
+            .index
+            .get(request.method.as_str())
+            .map(|index| index.candidates(request.path.as_str()))
+            .unwrap_or_default();
+        // parsed once for all of the GraphQL mocks
+        let operation = std::cell::OnceCell::new();
+        candidates.into_iter().find_map(|position| {
+            let mock = &self.mocks[position];
+            if !mock.api.enabled || !mock.api.is_active(now) {
+                return None;
+            }
+            let params = mock.pattern.matches(request.path.as_str())?;
+            if !mock.matcher.matches(request) {
+                return None;
+            }
+            if let Some(graphql) = &mock.graphql {
+                let operation = operation.get_or_init(|| crate::graphql::Operation::new(request));
+                if !graphql.matches(operation.as_ref()) {
+                    return None;
+                }
+            }
+            if mock.used_up() || !mock.in_state() {
+                return None;
+            }
+            // rejected requests neither count as a hit nor move the scenario on
+            let operation = operation.get().and_then(|o| o.as_ref());
+            if let Some(rejected) = mock.reject(request, operation, &params) {
+                return Some(Found::Rejected(rejected));
+            }
+            if !mock.claim() {
+                return None;
+            }
+            Some(Found::Mock(mock, params))
+        })
+    }
+
+    pub async fn response(
+        &self,
+        request: &MockRequest,
+    ) -> Result<Option<MatchedAPI>, crate::template::TemplateError> {
+        let (mock, params) = match self.find(request) {
+            Some(Found::Mock(mock, params)) => (mock, params),
+            Some(Found::Rejected(rejected)) => return Ok(Some(rejected)),
+            None => return Ok(None),
+        };
+        let api = &mock.api;
+        let delay = match (&api.latency, api.wait) {
+            (Some(latency), _) => Some(latency.sample()),
+            (None, Some(wait)) => Some(crate::latency::Latency::Fixed { millis: wait }.sample()),
+            (None, None) => None,
+        };
+        let mut body = mock.body.clone();
+        let mut status = api.status.unwrap_or(200);
+        let mut headers = api.headers.clone();
+        let computed = match (&mock.script, &api.plugin) {
+            (Some(script), _) => Some(
+                crate::script::run(script.clone(), request.clone(), params.clone())
+                    .await
+                    .map_err(|e| e.to_string()),
+            ),
+            (None, Some(plugin)) => Some(
+                crate::plugin::handle(plugin, request, &params)
+                    .await
+                    .map_err(|e| e.to_string()),
+            ),
+            (None, None) => None,
+        };
+        match computed {
+            Some(Ok(response)) => {
+                status = response.status.unwrap_or(status);
+                body = response.body().map(MatchedBody::Body).or(body);
+                headers.extend(response.headers);
+            }
+            Some(Err(e)) => {
+                let (target, message) = if mock.script.is_some() {
+                    ("ScriptError", "SCRIPT_ERROR")
+                } else {
+                    ("PluginError", "PLUGIN_ERROR")
+                };
+                tracing::warn!(
+                    target = target,
+                    id = api.id.as_deref().unwrap_or_default(),
+                    "Error: {}",
+                    e
+                );
+                status = 500;
+                body = Some(MatchedBody::Body(ResponseBody::Json(serde_json::json!({
+                    "success": false,
+                    "message": message,
+                    "error": e,
+                }))));
+            }
+            None => {}
+        }
+        if let Some(error) = api.random_error.as_ref().filter(|e| e.triggered()) {
+            status = error.status;
+            body = Some(MatchedBody::Body(error.body()));
+        }
+        let webhooks = match api.webhooks.is_empty() {
+            true => vec![],
+            false => {
+                let context = request.template_context(&params);
+                api.webhooks
+                    .iter()
+                    .map(|webhook| webhook.render(&context))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        let mut stream = api.stream.clone();
+        if api.template {
+            let context = request.template_context(&params);
+            body = match body {
+                Some(MatchedBody::Body(b)) => Some(MatchedBody::Body(b.render(&context)?)),
+                body => body,
+            };
+            stream = stream.map(|s| s.render(&context)).transpose()?;
+        }
+        Ok(Some(MatchedAPI {
+            id: api.id.clone(),
+            status,
+            headers,
+            cookies: api.cookies.clone(),
+            body,
+            params,
+            delay,
+            fault: api.fault.clone(),
+            stream,
+            webhooks,
+        }))
+    }
 }
@@ -0,0 +1,365 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+
+// Every attempt is given up after this long
+const ATTEMPT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebhookError {
+    #[error("InvalidUri: {0}")]
+    InvalidUri(#[from] hyper::http::uri::InvalidUri),
+    #[error("InvalidMethod: {0}")]
+    InvalidMethod(#[from] hyper::http::method::InvalidMethod),
+    #[error("InvalidHeaderName: {0}")]
+    InvalidHeaderName(#[from] hyper::header::InvalidHeaderName),
+    #[error("InvalidHeaderValue: {0}")]
+    InvalidHeaderValue(#[from] hyper::header::InvalidHeaderValue),
+    #[error("RequestBuildError: {0}")]
+    RequestBuild(#[from] hyper::http::Error),
+    #[error("RequestError: {0}")]
+    Request(#[from] hyper::Error),
+    #[error("Timeout: no response in {0:?}")]
+    Timeout(std::time::Duration),
+}
+
+/// Outbound call made after a mock responds, like the callback of an api which
+/// answers `202 Accepted` and reports the result later. `url`, the header
+/// values and the body are templates rendered with the request which
+/// triggered the call, see `crate::template`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<crate::utils::ResponseBody>,
+    /// Milliseconds to wait after the response before the first attempt
+    #[serde(default)]
+    pub delay: u64,
+    /// Attempts made after the first one fails, an attempt fails on an error
+    /// or on a status other than `2xx`
+    #[serde(default)]
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubled for every next retry
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_retry_delay() -> u64 {
+    1000
+}
+
+impl Webhook {
+    /// Checks the method and the headers up front, the header values are
+    /// checked before they are rendered
+    pub fn validate(&self) -> Result<(), WebhookError> {
+        hyper::Method::from_bytes(self.method.as_bytes())?;
+        for (name, value) in &self.headers {
+            hyper::header::HeaderName::from_bytes(name.as_bytes())?;
+            hyper::http::HeaderValue::from_str(value.as_str())?;
+        }
+        Ok(())
+    }
+
+    pub fn render(
+        &self,
+        context: &serde_json::Value,
+    ) -> Result<Self, crate::template::TemplateError> {
+        Ok(Webhook {
+            url: crate::template::render_text(self.url.as_str(), context)?,
+            headers: self
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    Ok((
+                        name.to_string(),
+                        crate::template::render_text(value.as_str(), context)?,
+                    ))
+                })
+                .collect::<Result<_, crate::template::TemplateError>>()?,
+            body: self.body.as_ref().map(|b| b.render(context)).transpose()?,
+            ..self.clone()
+        })
+    }
+}
+
+/// Attempt to deliver a webhook
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Delivery {
+    pub id: String,
+    /// Id of the mock which triggered the webhook
+    pub mock: Option<String>,
+    pub attempt: u32,
+    pub sent_at: String,
+    pub method: String,
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<crate::journal::LoggedResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+static DELIVERIES: LazyLock<Mutex<VecDeque<Delivery>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+/// Records the attempt, oldest attempts are dropped beyond the `JOURNAL_LIMIT`
+fn record(delivery: Delivery) {
+    let limit = crate::journal::limit();
+    let mut deliveries = DELIVERIES.lock().expect("webhook lock poisoned");
+    deliveries.push_back(delivery);
+    while limit > 0 && deliveries.len() > limit {
+        deliveries.pop_front();
+    }
+}
+
+pub fn deliveries() -> Vec<Delivery> {
+    DELIVERIES
+        .lock()
+        .expect("webhook lock poisoned")
+        .iter()
+        .cloned()
+        .collect()
+}
+
+pub fn clear() {
+    DELIVERIES.lock().expect("webhook lock poisoned").clear();
+}
+
+async fn send(
+    webhook: &Webhook,
+    body: &[u8],
+) -> Result<(hyper::Response<hyper::Body>, Vec<u8>), WebhookError> {
+    let mut builder = hyper::Request::builder()
+        .method(webhook.method.as_str())
+        .uri(webhook.url.parse::<hyper::Uri>()?);
+    if let Some(b) = &webhook.body {
+        builder = builder.header(hyper::header::CONTENT_TYPE, b.content_type());
+    }
+    for (name, value) in &webhook.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    let request = builder.body(hyper::Body::from(body.to_vec()))?;
+    let response =
+        tokio::time::timeout(ATTEMPT_TIMEOUT, crate::recorder::client().request(request))
+            .await
+            .map_err(|_| WebhookError::Timeout(ATTEMPT_TIMEOUT))??;
+    let (parts, response_body) = response.into_parts();
+    let bytes = hyper::body::to_bytes(response_body).await?.to_vec();
+    Ok((
+        hyper::Response::from_parts(parts, hyper::Body::empty()),
+        bytes,
+    ))
+}
+
+/// Delivers the webhook, retrying the failed attempts, every attempt is recorded
+async fn deliver(mock: Option<String>, webhook: Webhook) {
+    tokio::time::sleep(std::time::Duration::from_millis(webhook.delay)).await;
+    let body = match webhook.body.clone() {
+        Some(b) => match b.bytes().await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::error!(
+                    target = "WebhookError",
+                    url = webhook.url.as_str(),
+                    "Error: {}",
+                    e
+                );
+                return;
+            }
+        },
+        None => vec![],
+    };
+    let mut retry_delay = webhook.retry_delay;
+    for attempt in 0..=webhook.retries {
+        if attempt > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(retry_delay)).await;
+            retry_delay = retry_delay.saturating_mul(2);
+        }
+        let started = std::time::Instant::now();
+        let mut delivery = Delivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            mock: mock.clone(),
+            attempt: attempt + 1,
+            sent_at: chrono::Utc::now().to_rfc3339(),
+            method: webhook.method.to_string(),
+            url: webhook.url.to_string(),
+            headers: webhook.headers.clone(),
+            body: (!body.is_empty()).then(|| String::from_utf8_lossy(&body).to_string()),
+            ..Default::default()
+        };
+        let delivered = match send(&webhook, &body).await {
+            Ok((response, bytes)) => {
+                let status = response.status();
+                delivery.response = Some(crate::journal::LoggedResponse::new(&response, &bytes));
+                status.is_success()
+            }
+            Err(e) => {
+                delivery.error = Some(e.to_string());
+                false
+            }
+        };
+        delivery.duration_ms = started.elapsed().as_millis() as u64;
+        tracing::info!(
+            target = "webhook",
+            method = webhook.method.as_str(),
+            url = webhook.url.as_str(),
+            attempt = attempt + 1,
+            status = delivery
+                .response
+                .as_ref()
+                .map(|r| r.status)
+                .unwrap_or_default(),
+            error = delivery.error.as_deref().unwrap_or_default()
+        );
+        record(delivery);
+        if delivered {
+            return;
+        }
+    }
+    tracing::warn!(
+        target = "WebhookError",
+        url = webhook.url.as_str(),
+        "Error: not delivered after {} attempts",
+        webhook.retries + 1
+    );
+}
+
+/// Fires the webhooks in the background, the response of the mock does not
+/// wait on them
+pub fn schedule(mock: Option<String>, webhooks: Vec<Webhook>) {
+    for webhook in webhooks {
+        tokio::spawn(deliver(mock.clone(), webhook));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn webhook(definition: serde_json::Value) -> Webhook {
+        serde_json::from_value(definition).expect("valid webhook")
+    }
+
+    /// Server failing the first `failures` calls with a `500`, along with the
+    /// number of calls it got
+    fn upstream(failures: usize) -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let make = hyper::service::make_service_fn(move |_| {
+            let counted = counted.clone();
+            async move {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(move |_| {
+                    let call = counted.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        let mut response = hyper::Response::new(hyper::Body::empty());
+                        if call < failures {
+                            *response.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+                        }
+                        Ok::<_, hyper::Error>(response)
+                    }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        (url, calls)
+    }
+
+    fn attempts(mock: &str) -> Vec<Delivery> {
+        deliveries()
+            .into_iter()
+            .filter(|d| d.mock.as_deref() == Some(mock))
+            .collect()
+    }
+
+    #[test]
+    fn webhooks_are_rendered_with_the_request() {
+        let context = json!({"request": {"path": "/orders/7", "body": {"id": 7}}});
+        let rendered = webhook(json!({
+            "url": "http://callback{{request.path}}",
+            "headers": {"x-order": "{{request.body.id}}"},
+            "body": {"json": {"order": "{{request.body.id}}"}}
+        }))
+        .render(&context)
+        .expect("rendered");
+        assert_eq!(rendered.url, "http://callback/orders/7");
+        assert_eq!(rendered.headers["x-order"], "7");
+        assert!(matches!(
+            rendered.body,
+            Some(crate::utils::ResponseBody::Json(body)) if body == json!({"order": 7})
+        ));
+    }
+
+    #[test]
+    fn invalid_methods_and_headers_are_refused() {
+        let valid = webhook(json!({"url": "http://a", "headers": {"x-a": "{{request.path}}"}}));
+        assert!(valid.validate().is_ok());
+        assert!(matches!(
+            webhook(json!({"url": "http://a", "method": "NOT VALID"})).validate(),
+            Err(WebhookError::InvalidMethod(_))
+        ));
+        assert!(matches!(
+            webhook(json!({"url": "http://a", "headers": {"bad name": "1"}})).validate(),
+            Err(WebhookError::InvalidHeaderName(_))
+        ));
+        assert!(matches!(
+            webhook(json!({"url": "http://a", "headers": {"x-a": "a\nb"}})).validate(),
+            Err(WebhookError::InvalidHeaderValue(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn failed_attempts_are_retried() {
+        let (url, calls) = upstream(2);
+        let hook = webhook(json!({"url": url, "retries": 3, "retry_delay": 1}));
+        deliver(Some("test-retried".to_string()), hook).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        let statuses: Vec<u16> = attempts("test-retried")
+            .iter()
+            .filter_map(|d| d.response.as_ref().map(|r| r.status))
+            .collect();
+        assert_eq!(statuses, [500, 500, 200]);
+    }
+
+    #[tokio::test]
+    async fn delivery_is_given_up_after_the_retries() {
+        let (url, calls) = upstream(usize::MAX);
+        let hook = webhook(json!({"url": url, "retries": 1, "retry_delay": 1}));
+        deliver(Some("test-given-up".to_string()), hook).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let attempts: Vec<u32> = attempts("test-given-up")
+            .iter()
+            .map(|d| d.attempt)
+            .collect();
+        assert_eq!(attempts, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn scheduled_webhooks_do_not_hold_up_the_caller() {
+        let (url, calls) = upstream(0);
+        let hook = webhook(json!({"url": url, "delay": 50}));
+        schedule(Some("test-scheduled".to_string()), vec![hook]);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        for _ in 0..100 {
+            if !attempts("test-scheduled").is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}